@@ -0,0 +1,297 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Deserializer, de::Error as _};
+use thiserror::Error;
+
+use crate::resources::{loader::{FileError, LoadErrors, load_json_dir, parse_json}, manager::ResourceManager};
+
+use super::{block::BlockState, registry::BlockRegistry};
+
+/// Directory inside of a resource pack holding one definition per biome
+pub const BIOMES_DIR: &str = "biomes";
+
+/// Index of a biome in the `BiomeRegistry`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct BiomeId(pub u16);
+
+/// Which of the biome colors a tinted face is multiplied with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TintType {
+    Grass,
+    Foliage,
+}
+
+/// An sRGB color written as `#rrggbb`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Color(pub [u8; 3]);
+
+impl Color {
+    pub const WHITE: Color = Color([255; 3]);
+
+    /// Channels in linear space, to multiply with sRGB textures in the shader
+    pub fn to_linear(self) -> [f32; 3] {
+        self.0.map(|c| {
+            let c = c as f32 / 255.0;
+            if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let invalid = || D::Error::custom(format!("invalid color `{}`, expected `#rrggbb`", text));
+        let hex = text.strip_prefix('#').filter(|hex| hex.len() == 6 && hex.is_ascii()).ok_or_else(invalid)?;
+        let channel = |i: usize| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid());
+        Ok(Color([channel(0)?, channel(1)?, channel(2)?]))
+    }
+}
+
+/// A problem with one field of a biome definition file
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{}: `{field}`: {reason}", file.display())]
+pub struct BiomeError {
+    pub file: PathBuf,
+    pub field: String,
+    pub reason: String,
+}
+
+impl FileError for BiomeError {
+    fn new(file: PathBuf, field: &str, reason: String) -> Self {
+        Self { file, field: field.to_string(), reason }
+    }
+
+    fn set_file(&mut self, file: PathBuf) {
+        self.file = file;
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HeightJson {
+    #[serde(default)]
+    offset: f64,
+    #[serde(default = "default_scale")]
+    scale: f64,
+}
+
+impl Default for HeightJson {
+    fn default() -> Self {
+        Self { offset: 0.0, scale: 1.0 }
+    }
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_filler_depth() -> u32 {
+    3
+}
+
+/// `biomes/<name>.json`, blocks are given as state strings
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BiomeJson {
+    display_name: Option<String>,
+    /// Climate the biome is chosen for, both in `-1.0..=1.0`
+    temperature: f64,
+    humidity: f64,
+    surface: String,
+    filler: String,
+    /// Surface below the sea level, `filler` if missing
+    underwater: Option<String>,
+    #[serde(default = "default_filler_depth")]
+    filler_depth: u32,
+    #[serde(default)]
+    height: HeightJson,
+    #[serde(default)]
+    tree_density: f64,
+    grass_color: Color,
+    foliage_color: Color,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Biome {
+    pub name: String,
+    pub display_name: String,
+    pub temperature: f64,
+    pub humidity: f64,
+    /// Top block above the sea level
+    pub surface: BlockState,
+    /// Top block below the sea level
+    pub underwater: BlockState,
+    /// Blocks between the surface and the stone
+    pub filler: BlockState,
+    pub filler_depth: u32,
+    /// Blocks added to the height of the terrain
+    pub height_offset: f64,
+    /// Factor of the height of the hills
+    pub height_scale: f64,
+    /// Chance of a tree on every surface block
+    pub tree_density: f64,
+    pub grass_color: Color,
+    pub foliage_color: Color,
+}
+
+impl Biome {
+    /// Key of the biome's name in the language files
+    pub fn translation_key(&self) -> String {
+        format!("biome.{}", self.name)
+    }
+
+    pub fn tint(&self, tint: TintType) -> Color {
+        match tint {
+            TintType::Grass => self.grass_color,
+            TintType::Foliage => self.foliage_color,
+        }
+    }
+}
+
+/// Parses and validates a biome definition, `file` is left empty
+pub fn parse_biome(name: &str, json: &str, blocks: &BlockRegistry) -> Result<Biome, BiomeError> {
+    let error = |field: &str, reason: String| BiomeError {
+        file: PathBuf::new(),
+        field: field.to_string(),
+        reason,
+    };
+
+    let raw: BiomeJson = parse_json(json).map_err(|(field, reason)| error(&field, reason))?;
+
+    for (field, value) in [("temperature", raw.temperature), ("humidity", raw.humidity)] {
+        if !(-1.0..=1.0).contains(&value) {
+            return Err(error(field, format!("{} is out of range -1.0-1.0", value)));
+        }
+    }
+    if !(0.0..=1.0).contains(&raw.tree_density) {
+        return Err(error("tree_density", format!("{} is out of range 0.0-1.0", raw.tree_density)));
+    }
+    if !raw.height.offset.is_finite() || !raw.height.scale.is_finite() || raw.height.scale < 0.0 {
+        return Err(error("height", "expected a finite offset and a non negative scale".to_string()));
+    }
+
+    let state = |field: &str, input: &str| blocks.parse_state(input).map_err(|e| error(field, e.to_string()));
+    let filler = state("filler", &raw.filler)?;
+
+    Ok(Biome {
+        name: name.to_string(),
+        display_name: raw.display_name.unwrap_or_else(|| name.to_string()),
+        temperature: raw.temperature,
+        humidity: raw.humidity,
+        surface: state("surface", &raw.surface)?,
+        underwater: raw.underwater.map_or(Ok(filler), |underwater| state("underwater", &underwater))?,
+        filler,
+        filler_depth: raw.filler_depth,
+        height_offset: raw.height.offset,
+        height_scale: raw.height.scale,
+        tree_density: raw.tree_density,
+        grass_color: raw.grass_color,
+        foliage_color: raw.foliage_color,
+    })
+}
+
+/// Every biome, numbered in name order
+#[derive(Debug, Clone)]
+pub struct BiomeRegistry {
+    biomes: Vec<Biome>,
+}
+
+impl BiomeRegistry {
+    /// Biomes in ID order, there has to be at least one
+    pub fn new(biomes: Vec<Biome>) -> Self {
+        assert!(!biomes.is_empty(), "a world needs at least one biome");
+        assert!(biomes.len() <= u16::MAX as usize, "too many biomes");
+        Self { biomes }
+    }
+
+    /// Loads every `.json` file in `biomes` of every resource pack
+    pub fn load(resources: &ResourceManager, blocks: &BlockRegistry) -> Result<Self, LoadErrors<BiomeError>> {
+        let (biomes, mut errors) = load_json_dir(resources, BIOMES_DIR, |name, json| parse_biome(name, json, blocks));
+        if biomes.is_empty() && errors.is_empty() {
+            errors.push(BiomeError::new(PathBuf::from(BIOMES_DIR), "", "no biomes are defined".to_string()));
+        }
+        let biomes = LoadErrors::check(errors, biomes)?;
+        Ok(Self::new(biomes.into_iter().map(|(_, biome)| biome).collect()))
+    }
+
+    pub fn get(&self, id: BiomeId) -> Option<&Biome> {
+        self.biomes.get(id.0 as usize)
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<(BiomeId, &Biome)> {
+        self.iter().find(|(_, biome)| biome.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BiomeId, &Biome)> {
+        self.biomes.iter().enumerate().map(|(i, biome)| (BiomeId(i as u16), biome))
+    }
+
+    pub fn len(&self) -> usize {
+        self.biomes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.biomes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::registry::BlockProperties;
+
+    use super::*;
+
+    fn blocks() -> BlockRegistry {
+        let mut registry = BlockRegistry::new();
+        for name in ["stone", "dirt", "grass_block", "sand"] {
+            registry.register(name, name, BlockProperties::default());
+        }
+        registry
+    }
+
+    #[test]
+    fn full_biome() {
+        let blocks = blocks();
+        let biome = parse_biome("forest", r##"{
+            "temperature": 0.2,
+            "humidity": 0.6,
+            "surface": "grass_block",
+            "filler": "dirt",
+            "underwater": "sand",
+            "height": { "offset": 2.5 },
+            "tree_density": 0.05,
+            "grass_color": "#79C05A",
+            "foliage_color": "#59ae30"
+        }"##, &blocks).unwrap();
+
+        assert_eq!(biome.display_name, "forest");
+        assert_eq!(biome.surface, blocks.parse_state("grass_block").unwrap());
+        assert_eq!(biome.underwater, blocks.parse_state("sand").unwrap());
+        assert_eq!(biome.filler_depth, 3);
+        assert_eq!((biome.height_offset, biome.height_scale), (2.5, 1.0));
+        assert_eq!(biome.tint(TintType::Grass), Color([0x79, 0xc0, 0x5a]));
+        assert_eq!(biome.tint(TintType::Foliage), Color([0x59, 0xae, 0x30]));
+    }
+
+    #[test]
+    fn invalid_biomes() {
+        let blocks = blocks();
+        let field = |json: &str| parse_biome("test", json, &blocks).unwrap_err().field;
+        let base = r##""surface": "grass_block", "filler": "dirt", "grass_color": "#000000", "foliage_color": "#000000""##;
+
+        assert_eq!(field(&format!(r#"{{ "temperature": 2.0, "humidity": 0.0, {} }}"#, base)), "temperature");
+        assert_eq!(field(&format!(r#"{{ "temperature": 0.0, "humidity": 0.0, "tree_density": -1.0, {} }}"#, base)), "tree_density");
+        assert_eq!(field(&format!(r#"{{ "temperature": 0.0, "humidity": 0.0, "underwater": "lava", {} }}"#, base)), "underwater");
+        assert_eq!(field(r##"{ "temperature": 0.0, "humidity": 0.0, "surface": "dirt", "filler": "dirt", "grass_color": "#00000", "foliage_color": "#000000" }"##), "grass_color");
+    }
+
+    #[test]
+    fn shipped_biomes() {
+        let resources = ResourceManager::open(&["assets"]).unwrap();
+        let blocks = BlockRegistry::from(crate::game::static_data::StaticBlockData::load(&resources).unwrap());
+        let biomes = BiomeRegistry::load(&resources, &blocks).unwrap();
+        assert!(biomes.len() > 1);
+        assert!(biomes.get_by_name("plains").is_some());
+    }
+}
@@ -1,17 +1,15 @@
-pub struct Block {
-    pub id: &'static str,
-    pub data: Option<BlockData>,
-}
+use serde::{Deserialize, Serialize};
 
-pub struct BlockData {
-    
-}
+/// Dense ID of a block together with the values of all of its properties.
+/// Every state of a block type is numbered consecutively by the registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize, Deserialize)]
+pub struct BlockState(pub u32);
 
-impl Block {
-    pub fn new(id: &'static str) -> Self {
-        Self {
-            id,
-            data: None,
-        }
+impl BlockState {
+    /// Air has a single state and is always registered first
+    pub const AIR: BlockState = BlockState(0);
+
+    pub fn is_air(&self) -> bool {
+        *self == Self::AIR
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,515 @@
+use std::path::PathBuf;
+
+use image::DynamicImage;
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{render::util::cube_model::CubeModel, resources::{loader::{FileError, LoadErrors, load_json_dir, parse_json}, manager::ResourceManager}};
+
+use super::{biome::TintType, block_state::Property, pos::Direction, registry::AIR_NAME};
+
+/// Directory inside of a resource pack holding one definition per block
+pub const BLOCKS_DIR: &str = "blocks";
+
+/// A problem with one field of a block definition file
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{}: `{field}`: {reason}", file.display())]
+pub struct BlockDefinitionError {
+    pub file: PathBuf,
+    /// Path of the offending field, e.g. `textures.top` or `drops[1].count`
+    pub field: String,
+    pub reason: String,
+}
+
+impl FileError for BlockDefinitionError {
+    fn new(file: PathBuf, field: &str, reason: String) -> Self {
+        Self { file, field: field.to_string(), reason }
+    }
+
+    fn set_file(&mut self, file: PathBuf) {
+        self.file = file;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CollisionShape {
+    Empty,
+    Full,
+    /// Boxes in block space, each component in `0.0..=1.0`
+    Boxes(Vec<[f32; 6]>),
+}
+
+impl CollisionShape {
+    pub fn is_empty(&self) -> bool {
+        match self {
+            CollisionShape::Empty => true,
+            CollisionShape::Full => false,
+            CollisionShape::Boxes(boxes) => boxes.is_empty(),
+        }
+    }
+}
+
+/// Sound event names played when interacting with the block
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlockSounds {
+    #[serde(rename = "break")]
+    pub break_sound: Option<String>,
+    pub place: Option<String>,
+    pub step: Option<String>,
+    pub hit: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlockDrop {
+    /// Name of the dropped block
+    pub item: String,
+    #[serde(default = "default_count")]
+    pub count: u32,
+    #[serde(default = "default_chance")]
+    pub chance: f32,
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+fn default_chance() -> f32 {
+    1.0
+}
+
+/// A validated block definition, ready to be registered
+pub struct BlockDefinition {
+    pub name: String,
+    pub display_name: String,
+    /// `None` for blocks without geometry
+    pub model: Option<CubeModel>,
+    pub collision: CollisionShape,
+    pub opaque: bool,
+    /// Emitted light level, 0-15
+    pub light_emission: u8,
+    pub hardness: f32,
+    pub sounds: BlockSounds,
+    pub drops: Vec<BlockDrop>,
+    pub properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ModelKind {
+    Cube,
+    None,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum CollisionJson {
+    Named(String),
+    Boxes(Vec<[f32; 6]>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TintJson {
+    #[serde(rename = "type")]
+    kind: TintType,
+    /// Face keys like in `textures`
+    #[serde(default = "default_tint_faces")]
+    faces: Vec<String>,
+}
+
+fn default_tint_faces() -> Vec<String> {
+    vec!["all".to_string()]
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PropertyJson {
+    name: String,
+    values: Vec<String>,
+}
+
+/// `blocks/<name>.json`, every field but the textures of a cube is optional
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockJson {
+    display_name: Option<String>,
+    #[serde(default = "default_model")]
+    model: ModelKind,
+    /// Face name (`all`, `side`, `top`, `bottom`, `north`, ...) to texture path
+    #[serde(default)]
+    textures: FxHashMap<String, String>,
+    /// Faces colored by the biome
+    tint: Option<TintJson>,
+    collision: Option<CollisionJson>,
+    opaque: Option<bool>,
+    #[serde(default)]
+    light_emission: u8,
+    #[serde(default = "default_hardness")]
+    hardness: f32,
+    #[serde(default)]
+    sounds: BlockSounds,
+    /// Defaults to dropping the block itself
+    drops: Option<Vec<BlockDrop>>,
+    #[serde(default)]
+    properties: Vec<PropertyJson>,
+}
+
+fn default_model() -> ModelKind {
+    ModelKind::Cube
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+/// Texture keys from least to most specific, with the faces they apply to
+const FACE_KEYS: [(&str, &[Direction]); 8] = [
+    ("all", &Direction::ALL),
+    ("side", &[Direction::East, Direction::West, Direction::South, Direction::North]),
+    ("top", &[Direction::Up]),
+    ("bottom", &[Direction::Down]),
+    ("east", &[Direction::East]),
+    ("west", &[Direction::West]),
+    ("south", &[Direction::South]),
+    ("north", &[Direction::North]),
+];
+
+/// Loads and validates every `.json` file in `blocks` of every resource
+/// pack, sorted by name. Drops are checked against the other definitions.
+pub fn load_block_definitions(resources: &ResourceManager) -> Result<Vec<BlockDefinition>, LoadErrors<BlockDefinitionError>> {
+    let (definitions, mut errors) = load_json_dir(resources, BLOCKS_DIR, |name, json| parse_block_definition(name, json, resources));
+
+    for (file, definition) in &definitions {
+        for (i, drop) in definition.drops.iter().enumerate() {
+            if drop.item != definition.name && !definitions.iter().any(|(_, d)| d.name == drop.item) {
+                errors.push(BlockDefinitionError::new(file.clone(), &format!("drops[{}].item", i), format!("unknown block `{}`", drop.item)));
+            }
+        }
+    }
+
+    LoadErrors::check(errors, definitions.into_iter().map(|(_, definition)| definition).collect())
+}
+
+/// Parses and validates the definition of block `name`. Textures are read
+/// from `resources`. Errors don't have the file set.
+pub fn parse_block_definition(name: &str, json: &str, resources: &ResourceManager) -> Result<BlockDefinition, BlockDefinitionError> {
+    let error = |field: &str, reason: String| BlockDefinitionError {
+        file: PathBuf::new(),
+        field: field.to_string(),
+        reason,
+    };
+
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+        return Err(error("", format!("invalid block name `{}`, only lowercase letters, digits and `_` are allowed", name)));
+    }
+    if name == AIR_NAME {
+        return Err(error("", format!("`{}` is built in and can't be redefined", AIR_NAME)));
+    }
+
+    let raw: BlockJson = parse_json(json).map_err(|(field, reason)| error(&field, reason))?;
+
+    if raw.light_emission > 15 {
+        return Err(error("light_emission", format!("{} is out of range 0-15", raw.light_emission)));
+    }
+    if !raw.hardness.is_finite() || raw.hardness < 0.0 {
+        return Err(error("hardness", format!("{} is not a finite, non negative number", raw.hardness)));
+    }
+
+    let collision = match raw.collision {
+        None => match raw.model {
+            ModelKind::Cube => CollisionShape::Full,
+            ModelKind::None => CollisionShape::Empty,
+        },
+        Some(CollisionJson::Named(name)) => match name.as_str() {
+            "full" => CollisionShape::Full,
+            "none" => CollisionShape::Empty,
+            _ => return Err(error("collision", format!("unknown shape `{}`, expected `full`, `none` or a list of boxes", name))),
+        },
+        Some(CollisionJson::Boxes(boxes)) => {
+            for (i, b) in boxes.iter().enumerate() {
+                if b.iter().any(|v| !(0.0..=1.0).contains(v)) {
+                    return Err(error(&format!("collision[{}]", i), "coordinates must be within 0.0-1.0".to_string()));
+                }
+                if (0..3).any(|axis| b[axis] >= b[axis + 3]) {
+                    return Err(error(&format!("collision[{}]", i), "expected `[min_x, min_y, min_z, max_x, max_y, max_z]` with min < max".to_string()));
+                }
+            }
+            CollisionShape::Boxes(boxes)
+        },
+    };
+
+    let model = match raw.model {
+        ModelKind::Cube => {
+            let mut model = cube_model(&raw.textures, resources).map_err(|(field, reason)| error(&field, reason))?;
+            if let Some(tint) = &raw.tint {
+                model.tints = face_tints(tint).map_err(|(field, reason)| error(&field, reason))?;
+            }
+            Some(model)
+        },
+        ModelKind::None => {
+            if !raw.textures.is_empty() {
+                return Err(error("textures", "blocks without a model can't have textures".to_string()));
+            }
+            if raw.tint.is_some() {
+                return Err(error("tint", "blocks without a model can't be tinted".to_string()));
+            }
+            None
+        },
+    };
+
+    for (i, drop) in raw.drops.iter().flatten().enumerate() {
+        if drop.count == 0 {
+            return Err(error(&format!("drops[{}].count", i), "must be at least 1".to_string()));
+        }
+        if !(drop.chance > 0.0 && drop.chance <= 1.0) {
+            return Err(error(&format!("drops[{}].chance", i), format!("{} is out of range (0.0, 1.0]", drop.chance)));
+        }
+    }
+    let drops = raw.drops.unwrap_or_else(|| vec![BlockDrop {
+        item: name.to_string(),
+        count: 1,
+        chance: 1.0,
+    }]);
+
+    let mut properties: Vec<Property> = Vec::new();
+    for (i, property) in raw.properties.iter().enumerate() {
+        let field = format!("properties[{}]", i);
+        if properties.iter().any(|p| p.name == property.name) {
+            return Err(error(&format!("{}.name", field), format!("`{}` is declared twice", property.name)));
+        }
+        if property.values.is_empty() {
+            return Err(error(&format!("{}.values", field), "needs at least one value".to_string()));
+        }
+        for (j, value) in property.values.iter().enumerate() {
+            if property.values[..j].contains(value) {
+                return Err(error(&format!("{}.values[{}]", field, j), format!("`{}` is listed twice", value)));
+            }
+        }
+        let values: Vec<_> = property.values.iter().map(String::as_str).collect();
+        properties.push(Property::new(&property.name, &values));
+    }
+
+    Ok(BlockDefinition {
+        name: name.to_string(),
+        display_name: raw.display_name.unwrap_or_else(|| name.to_string()),
+        model,
+        collision,
+        opaque: raw.opaque.unwrap_or(raw.model == ModelKind::Cube),
+        light_emission: raw.light_emission,
+        hardness: raw.hardness,
+        sounds: raw.sounds,
+        drops,
+        properties,
+    })
+}
+
+/// Builds the cube model from the texture map, returning the field and reason on error
+fn cube_model(textures: &FxHashMap<String, String>, resources: &ResourceManager) -> Result<CubeModel, (String, String)> {
+    for key in textures.keys() {
+        if !FACE_KEYS.iter().any(|(k, _)| k == key) {
+            let expected: Vec<_> = FACE_KEYS.iter().map(|(k, _)| format!("`{}`", k)).collect();
+            return Err((format!("textures.{}", key), format!("unknown face, expected one of {}", expected.join(", "))));
+        }
+    }
+
+    let load = |key: &str| -> Result<DynamicImage, (String, String)> {
+        resources.load_image(&textures[key]).map_err(|reason| (format!("textures.{}", key), reason))
+    };
+
+    // A single texture may stack the top, side and bottom textures
+    if textures.len() == 1 && textures.contains_key("all") {
+        return Ok(CubeModel::from_image(load("all")?));
+    }
+
+    let mut face_keys: [Option<&str>; 6] = [None; 6];
+    for (key, faces) in FACE_KEYS {
+        if textures.contains_key(key) {
+            for face in faces {
+                face_keys[face.index()] = Some(key);
+            }
+        }
+    }
+
+    // Faces sharing a texture file share the image too
+    let mut images = Vec::new();
+    let mut loaded: Vec<&str> = Vec::new();
+    let mut face_textures = [0; 6];
+    for dir in Direction::ALL {
+        let key = face_keys[dir.index()].ok_or_else(|| {
+            ("textures".to_string(), format!("no texture for the {:?} face", dir).to_lowercase())
+        })?;
+        let path = textures[key].as_str();
+        face_textures[dir.index()] = match loaded.iter().position(|p| *p == path) {
+            Some(i) => i,
+            None => {
+                images.push(load(key)?);
+                loaded.push(path);
+                images.len() - 1
+            },
+        };
+    }
+
+    Ok(CubeModel {
+        textures: images,
+        face_textures,
+        tints: [None; 6],
+    })
+}
+
+/// Tint of every face, returning the field and reason on error
+fn face_tints(tint: &TintJson) -> Result<[Option<TintType>; 6], (String, String)> {
+    let mut ret = [None; 6];
+    for (i, key) in tint.faces.iter().enumerate() {
+        let Some((_, faces)) = FACE_KEYS.iter().find(|(k, _)| k == key) else {
+            let expected: Vec<_> = FACE_KEYS.iter().map(|(k, _)| format!("`{}`", k)).collect();
+            return Err((format!("tint.faces[{}]", i), format!("unknown face, expected one of {}", expected.join(", "))));
+        };
+        for face in *faces {
+            ret[face.index()] = Some(tint.kind);
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use image::{RgbaImage, Rgba};
+
+    use crate::{resources::pack::{MANIFEST, PACK_FORMAT}, util::test_dir::TestDir};
+
+    use super::*;
+
+    /// Resource pack with a few textures, unique per test
+    fn test_assets(test: &str) -> (TestDir, ResourceManager) {
+        let dir = TestDir::new(&format!("block-def-{}", test));
+        fs::create_dir_all(dir.join(BLOCKS_DIR)).unwrap();
+        fs::write(dir.join(MANIFEST), format!(r#"{{ "name": "test", "format": {} }}"#, PACK_FORMAT)).unwrap();
+        for (name, color) in [("a.png", [255, 0, 0, 255]), ("b.png", [0, 255, 0, 255])] {
+            RgbaImage::from_pixel(2, 2, Rgba(color)).save(dir.join(name)).unwrap();
+        }
+        fs::write(dir.join("broken.png"), b"not a png").unwrap();
+        let resources = ResourceManager::open(&[&dir]).unwrap();
+        (dir, resources)
+    }
+
+    fn parse_error(json: &str) -> BlockDefinitionError {
+        let (_dir, resources) = test_assets("errors");
+        match parse_block_definition("test", json, &resources) {
+            Ok(_) => panic!("expected an error for {}", json),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn full_definition() {
+        let (_dir, resources) = test_assets("full");
+        let def = parse_block_definition("log", r#"{
+            "display_name": "Log",
+            "textures": { "side": "a.png", "top": "b.png", "bottom": "b.png" },
+            "tint": { "type": "foliage", "faces": ["side", "bottom"] },
+            "collision": [[0.0, 0.0, 0.0, 1.0, 0.5, 1.0]],
+            "opaque": false,
+            "light_emission": 7,
+            "hardness": 2.5,
+            "sounds": { "break": "wood_break", "step": "wood_step" },
+            "drops": [{ "item": "planks", "count": 4, "chance": 0.5 }],
+            "properties": [{ "name": "axis", "values": ["x", "y", "z"] }]
+        }"#, &resources).unwrap();
+
+        assert_eq!(def.display_name, "Log");
+        let model = def.model.unwrap();
+        assert_eq!(model.textures.len(), 2);
+        assert_eq!(model.face_textures[Direction::Up.index()], model.face_textures[Direction::Down.index()]);
+        assert_ne!(model.face_textures[Direction::Up.index()], model.face_textures[Direction::North.index()]);
+        assert_eq!(model.tints, [Some(TintType::Foliage), Some(TintType::Foliage), None, Some(TintType::Foliage), Some(TintType::Foliage), Some(TintType::Foliage)]);
+        assert_eq!(def.collision, CollisionShape::Boxes(vec![[0.0, 0.0, 0.0, 1.0, 0.5, 1.0]]));
+        assert!(!def.opaque);
+        assert_eq!(def.light_emission, 7);
+        assert_eq!(def.sounds.break_sound.as_deref(), Some("wood_break"));
+        assert_eq!(def.sounds.place, None);
+        assert_eq!(def.drops, vec![BlockDrop { item: "planks".to_string(), count: 4, chance: 0.5 }]);
+        assert_eq!(def.properties, vec![Property::axis()]);
+    }
+
+    #[test]
+    fn defaults() {
+        let (_dir, resources) = test_assets("defaults");
+        let def = parse_block_definition("stone", r#"{ "textures": { "all": "a.png" } }"#, &resources).unwrap();
+        assert_eq!(def.display_name, "stone");
+        assert_eq!(def.collision, CollisionShape::Full);
+        assert!(def.opaque);
+        assert_eq!(def.hardness, 1.0);
+        assert_eq!(def.drops[0].item, "stone");
+        assert_eq!(def.model.unwrap().tints, [None; 6]);
+
+        let def = parse_block_definition("leaves", r#"{ "textures": { "all": "a.png" }, "tint": { "type": "grass" } }"#, &resources).unwrap();
+        assert_eq!(def.model.unwrap().tints, [Some(TintType::Grass); 6]);
+
+        let def = parse_block_definition("barrier", r#"{ "model": "none" }"#, &resources).unwrap();
+        assert!(def.model.is_none());
+        assert!(def.collision.is_empty());
+        assert!(!def.opaque);
+    }
+
+    #[test]
+    fn field_errors() {
+        let cases = [
+            (r#"{ "textures": { "all": "a.png" }, "hardnes": 1.0 }"#, "hardnes"),
+            (r#"{ "textures": { "all": "a.png" }, "light_emission": "bright" }"#, "light_emission"),
+            (r#"{ "textures": { "all": "a.png" }, "light_emission": 16 }"#, "light_emission"),
+            (r#"{ "textures": { "all": "a.png" }, "hardness": -1 }"#, "hardness"),
+            (r#"{ "textures": { "all": "a.png" }, "model": "sphere" }"#, "model"),
+            (r#"{ "textures": { "all": "missing.png" } }"#, "textures.all"),
+            (r#"{ "textures": { "top": "broken.png", "side": "a.png", "bottom": "a.png" } }"#, "textures.top"),
+            (r#"{ "textures": { "tops": "a.png" } }"#, "textures.tops"),
+            (r#"{ "textures": { "side": "a.png", "top": "a.png" } }"#, "textures"),
+            (r#"{ "textures": { "all": "a.png" }, "collision": "half" }"#, "collision"),
+            (r#"{ "textures": { "all": "a.png" }, "collision": [[0, 0, 0, 1, 1, 1], [0, 0, 0, 1, 2, 1]] }"#, "collision[1]"),
+            (r#"{ "textures": { "all": "a.png" }, "sounds": { "break": "x", "fall": "y" } }"#, "sounds.fall"),
+            (r#"{ "textures": { "all": "a.png" }, "drops": [{ "item": "x" }, { "item": "y", "count": 0 }] }"#, "drops[1].count"),
+            (r#"{ "textures": { "all": "a.png" }, "drops": [{ "item": "x", "chance": 2 }] }"#, "drops[0].chance"),
+            (r#"{ "textures": { "all": "a.png" }, "properties": [{ "name": "a", "values": ["x", "x"] }] }"#, "properties[0].values[1]"),
+            (r#"{ "model": "none", "textures": { "all": "a.png" } }"#, "textures"),
+            (r#"{ "textures": { "all": "a.png" }, "tint": { "type": "water" } }"#, "tint.type"),
+            (r#"{ "textures": { "all": "a.png" }, "tint": { "type": "grass", "faces": ["top", "front"] } }"#, "tint.faces[1]"),
+            (r#"{ "model": "none", "tint": { "type": "grass" } }"#, "tint"),
+        ];
+
+        for (json, field) in cases {
+            let e = parse_error(json);
+            assert_eq!(e.field, field, "{}: {}", json, e);
+            assert!(!e.reason.is_empty());
+        }
+
+        let e = parse_error(r#"{ "textures": { "all": "a.png" }, "light_emission": 16 }"#);
+        assert_eq!(e.reason, "16 is out of range 0-15");
+        assert!(parse_error(r#"{ "hardnes": 1.0 }"#).reason.contains("unknown field `hardnes`"));
+    }
+
+    #[test]
+    fn load_directory() {
+        let (assets, resources) = test_assets("load");
+        let blocks = assets.join(BLOCKS_DIR);
+        fs::write(blocks.join("stone.json"), r#"{ "textures": { "all": "a.png" }, "drops": [{ "item": "gravel" }] }"#).unwrap();
+        fs::write(blocks.join("gravel.json"), r#"{ "textures": { "all": "b.png" } }"#).unwrap();
+        fs::write(blocks.join("notes.txt"), "ignored").unwrap();
+
+        let defs = load_block_definitions(&resources).unwrap();
+        let names: Vec<_> = defs.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, vec!["gravel", "stone"]);
+
+        fs::write(blocks.join("dirt.json"), r#"{ "textures": { "all": "a.png" }, "drops": [{ "item": "clay" }] }"#).unwrap();
+        fs::write(blocks.join("sand.json"), r#"{ "textures": { "all": "a.png" }, "hardness": "#).unwrap();
+        let Err(LoadErrors(errors)) = load_block_definitions(&resources) else { panic!("expected errors") };
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].file, blocks.join("sand.json"));
+        assert_eq!(errors[1].file, blocks.join("dirt.json"));
+        assert_eq!(errors[1].field, "drops[0].item");
+        assert_eq!(errors[1].to_string(), format!("{}: `drops[0].item`: unknown block `clay`", blocks.join("dirt.json").display()));
+    }
+}
@@ -0,0 +1,350 @@
+use std::fmt;
+
+use thiserror::Error;
+
+/// A value type that can be used as a block property
+pub trait PropertyValue: Sized + Copy + 'static {
+    /// Every possible value, in the order used for state IDs
+    const ALL: &'static [Self];
+
+    fn name(&self) -> &'static str;
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|v| v.name() == name)
+    }
+}
+
+impl PropertyValue for bool {
+    const ALL: &'static [Self] = &[false, true];
+
+    fn name(&self) -> &'static str {
+        if *self { "true" } else { "false" }
+    }
+}
+
+/// Horizontal facing of stairs, doors, furnaces...
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Facing {
+    North,
+    South,
+    West,
+    East,
+}
+
+impl PropertyValue for Facing {
+    const ALL: &'static [Self] = &[Facing::North, Facing::South, Facing::West, Facing::East];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Facing::North => "north",
+            Facing::South => "south",
+            Facing::West => "west",
+            Facing::East => "east",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl PropertyValue for Axis {
+    const ALL: &'static [Self] = &[Axis::X, Axis::Y, Axis::Z];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Axis::X => "x",
+            Axis::Y => "y",
+            Axis::Z => "z",
+        }
+    }
+}
+
+/// Which half of the block space slabs and stairs occupy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Half {
+    Bottom,
+    Top,
+}
+
+impl PropertyValue for Half {
+    const ALL: &'static [Self] = &[Half::Bottom, Half::Top];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Half::Bottom => "bottom",
+            Half::Top => "top",
+        }
+    }
+}
+
+/// A named block property with a finite set of values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+impl Property {
+    pub fn new(name: &str, values: &[&str]) -> Self {
+        assert!(!values.is_empty(), "property `{}` has no values", name);
+        Self {
+            name: name.to_string(),
+            values: values.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+
+    pub fn of<T: PropertyValue>(name: &str) -> Self {
+        Self::new(name, &T::ALL.iter().map(|v| v.name()).collect::<Vec<_>>())
+    }
+
+    /// Integer property covering `min..=max`, e.g. crop age
+    pub fn int(name: &str, min: u32, max: u32) -> Self {
+        let values: Vec<_> = (min..=max).map(|v| v.to_string()).collect();
+        Self::new(name, &values.iter().map(String::as_str).collect::<Vec<_>>())
+    }
+
+    pub fn facing() -> Self {
+        Self::of::<Facing>("facing")
+    }
+
+    pub fn axis() -> Self {
+        Self::of::<Axis>("axis")
+    }
+
+    pub fn half() -> Self {
+        Self::of::<Half>("half")
+    }
+
+    pub fn waterlogged() -> Self {
+        Self::of::<bool>("waterlogged")
+    }
+
+    pub fn value_index(&self, value: &str) -> Option<usize> {
+        self.values.iter().position(|v| v == value)
+    }
+}
+
+/// The properties of one block type. Every combination of values maps to an
+/// offset in `0..state_count()`, with the last property varying fastest.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDefinition {
+    properties: Vec<Property>,
+}
+
+impl StateDefinition {
+    pub fn new(properties: Vec<Property>) -> Self {
+        for (i, property) in properties.iter().enumerate() {
+            assert!(
+                properties[..i].iter().all(|p| p.name != property.name),
+                "property `{}` declared twice", property.name
+            );
+        }
+        Self { properties }
+    }
+
+    pub fn properties(&self) -> &[Property] {
+        &self.properties
+    }
+
+    pub fn property(&self, name: &str) -> Option<(usize, &Property)> {
+        self.properties.iter().enumerate().find(|(_, p)| p.name == name)
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.properties.iter().map(|p| p.values.len()).product()
+    }
+
+    /// Splits a state offset into the value index of every property
+    pub fn value_indices(&self, offset: usize) -> Vec<usize> {
+        let mut ret = vec![0; self.properties.len()];
+        let mut rest = offset;
+        for (i, property) in self.properties.iter().enumerate().rev() {
+            ret[i] = rest % property.values.len();
+            rest /= property.values.len();
+        }
+        ret
+    }
+
+    pub fn offset(&self, value_indices: &[usize]) -> usize {
+        self.properties.iter().zip(value_indices)
+            .fold(0, |acc, (property, i)| acc * property.values.len() + i)
+    }
+
+    pub fn value(&self, offset: usize, property: &str) -> Option<&str> {
+        let (i, p) = self.property(property)?;
+        Some(&p.values[self.value_indices(offset)[i]])
+    }
+
+    /// The offset of the state that matches `offset` except for one property
+    pub fn with_value(&self, offset: usize, property: &str, value: &str) -> Option<usize> {
+        let (i, p) = self.property(property)?;
+        let mut indices = self.value_indices(offset);
+        indices[i] = p.value_index(value)?;
+        Some(self.offset(&indices))
+    }
+}
+
+/// A state string split into its parts, `stone_stairs[facing=north,half=top]`
+/// becomes the block name and a list of property assignments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateString<'a> {
+    pub block: &'a str,
+    pub properties: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> StateString<'a> {
+    pub fn parse(input: &'a str) -> Result<Self, StateParseError> {
+        let input = input.trim();
+        let malformed = |reason: &str| StateParseError::Malformed {
+            input: input.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let (block, properties) = match input.find('[') {
+            Some(open) => {
+                let rest = input[open + 1..].strip_suffix(']')
+                    .ok_or_else(|| malformed("missing closing `]`"))?;
+                (&input[..open], Some(rest))
+            },
+            None => (input, None),
+        };
+
+        let block = block.trim();
+        if block.is_empty() {
+            return Err(malformed("missing block name"));
+        }
+        if block.contains(|c: char| c == ']' || c == '=' || c == ',' || c.is_whitespace()) {
+            return Err(malformed("invalid character in block name"));
+        }
+
+        let mut ret = Self { block, properties: Vec::new() };
+        let Some(properties) = properties else { return Ok(ret) };
+        if properties.trim().is_empty() {
+            return Ok(ret);
+        }
+
+        for pair in properties.split(',') {
+            let (key, value) = pair.split_once('=')
+                .ok_or_else(|| malformed("expected `property=value`"))?;
+            let (key, value) = (key.trim(), value.trim());
+            if key.is_empty() || value.is_empty() {
+                return Err(malformed("expected `property=value`"));
+            }
+            if ret.properties.iter().any(|(k, _)| *k == key) {
+                return Err(StateParseError::DuplicateProperty {
+                    block: block.to_string(),
+                    property: key.to_string(),
+                });
+            }
+            ret.properties.push((key, value));
+        }
+
+        Ok(ret)
+    }
+}
+
+impl fmt::Display for StateString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.block)?;
+        if !self.properties.is_empty() {
+            write!(f, "[")?;
+            for (i, (key, value)) in self.properties.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ",")?;
+                }
+                write!(f, "{}={}", key, value)?;
+            }
+            write!(f, "]")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum StateParseError {
+    #[error("malformed block state `{input}`: {reason}")]
+    Malformed { input: String, reason: String },
+    #[error("unknown block `{0}`")]
+    UnknownBlock(String),
+    #[error("block `{block}` has no property `{property}`")]
+    UnknownProperty { block: String, property: String },
+    #[error("`{value}` is not a valid value for property `{property}` of block `{block}`")]
+    InvalidValue { block: String, property: String, value: String },
+    #[error("property `{property}` of block `{block}` is set more than once")]
+    DuplicateProperty { block: String, property: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stairs() -> StateDefinition {
+        StateDefinition::new(vec![Property::facing(), Property::half(), Property::waterlogged()])
+    }
+
+    #[test]
+    fn offsets_are_dense_and_unique() {
+        let def = stairs();
+        assert_eq!(def.state_count(), 4 * 2 * 2);
+
+        for offset in 0..def.state_count() {
+            assert_eq!(def.offset(&def.value_indices(offset)), offset);
+        }
+    }
+
+    #[test]
+    fn change_single_value() {
+        let def = stairs();
+        let offset = def.with_value(0, "half", "top").unwrap();
+        assert_eq!(def.value(offset, "half"), Some("top"));
+        assert_eq!(def.value(offset, "facing"), Some("north"));
+        assert_eq!(def.value(offset, "waterlogged"), Some("false"));
+
+        assert!(def.with_value(offset, "half", "middle").is_none());
+        assert!(def.with_value(offset, "shape", "straight").is_none());
+    }
+
+    #[test]
+    fn no_properties_has_one_state() {
+        let def = StateDefinition::default();
+        assert_eq!(def.state_count(), 1);
+        assert_eq!(def.offset(&[]), 0);
+    }
+
+    #[test]
+    fn typed_values() {
+        assert_eq!(Facing::from_name("west"), Some(Facing::West));
+        assert_eq!(Axis::from_name("y"), Some(Axis::Y));
+        assert_eq!(bool::from_name("true"), Some(true));
+        assert_eq!(Half::from_name("up"), None);
+        assert_eq!(Property::int("age", 0, 7).values.len(), 8);
+    }
+
+    #[test]
+    fn parse_state_string() {
+        let parsed = StateString::parse("stone_stairs[facing=north, half=top]").unwrap();
+        assert_eq!(parsed.block, "stone_stairs");
+        assert_eq!(parsed.properties, vec![("facing", "north"), ("half", "top")]);
+        assert_eq!(parsed.to_string(), "stone_stairs[facing=north,half=top]");
+
+        let plain = StateString::parse("stone").unwrap();
+        assert!(plain.properties.is_empty());
+        assert_eq!(plain.to_string(), "stone");
+        assert_eq!(StateString::parse("stone[]").unwrap(), plain);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(StateString::parse("stone[facing=north"), Err(StateParseError::Malformed { .. })));
+        assert!(matches!(StateString::parse("[facing=north]"), Err(StateParseError::Malformed { .. })));
+        assert!(matches!(StateString::parse("stone[facing]"), Err(StateParseError::Malformed { .. })));
+        assert!(matches!(
+            StateString::parse("stone[half=top,half=bottom]"),
+            Err(StateParseError::DuplicateProperty { .. })
+        ));
+    }
+}
@@ -1,12 +1,166 @@
-use super::block::Block;
+use super::{biome::BiomeId, block::BlockState, palette::PalettedContainer, pos::{ChunkPos, LocalPos}};
 
+pub const CHUNK_SIZE: usize = 16;
+pub const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+/// Number of block columns in a chunk
+pub const CHUNK_AREA: usize = CHUNK_SIZE * CHUNK_SIZE;
+
+#[derive(Debug, Clone)]
 pub struct Chunk {
     pub pos: ChunkPos,
-    pub blocks: [[[Block; 16]; 16]; 16],
+    blocks: PalettedContainer<BlockState>,
+    /// Biome of every column, indexed by `z * CHUNK_SIZE + x`
+    biomes: PalettedContainer<BiomeId>,
+}
+
+impl Chunk {
+    /// Creates a chunk filled with air
+    pub fn new(pos: ChunkPos) -> Self {
+        Self::filled(pos, BlockState::AIR)
+    }
+
+    pub fn filled(pos: ChunkPos, state: BlockState) -> Self {
+        Self {
+            pos,
+            blocks: PalettedContainer::new(CHUNK_VOLUME, state),
+            biomes: PalettedContainer::new(CHUNK_AREA, BiomeId::default()),
+        }
+    }
+
+    /// A chunk with the given blocks and column biomes
+    pub fn from_parts(pos: ChunkPos, blocks: PalettedContainer<BlockState>, biomes: PalettedContainer<BiomeId>) -> Self {
+        assert_eq!(blocks.len(), CHUNK_VOLUME, "wrong number of blocks");
+        assert_eq!(biomes.len(), CHUNK_AREA, "wrong number of columns");
+        Self { pos, blocks, biomes }
+    }
+
+    pub fn get(&self, pos: LocalPos) -> BlockState {
+        *self.blocks.get(pos.index())
+    }
+
+    /// Sets the block at the given local position, returning the previous state.
+    pub fn set(&mut self, pos: LocalPos, state: BlockState) -> BlockState {
+        self.blocks.set(pos.index(), state)
+    }
+
+    pub fn fill(&mut self, state: BlockState) {
+        self.blocks.fill(state);
+    }
+
+    /// Iterates over every block along with its local position, x varying fastest.
+    pub fn iter(&self) -> impl Iterator<Item = (LocalPos, BlockState)> + '_ {
+        self.blocks.iter().enumerate().map(|(i, state)| (LocalPos::from_index(i), *state))
+    }
+
+    /// Biome of the column at local `x` and `z`
+    pub fn biome(&self, x: usize, z: usize) -> BiomeId {
+        *self.biomes.get(z * CHUNK_SIZE + x)
+    }
+
+    pub fn set_biome(&mut self, x: usize, z: usize, biome: BiomeId) -> BiomeId {
+        self.biomes.set(z * CHUNK_SIZE + x, biome)
+    }
+
+    /// Drops palette entries that are no longer used by any block or column.
+    pub fn compact(&mut self) {
+        self.blocks.compact();
+        self.biomes.compact();
+    }
+
+    pub fn blocks(&self) -> &PalettedContainer<BlockState> {
+        &self.blocks
+    }
+
+    pub fn biomes(&self) -> &PalettedContainer<BiomeId> {
+        &self.biomes
+    }
 }
 
-pub struct ChunkPos {
-    pub x: i32,
-    pub y: i32,
-    pub z: i32,
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGIN: ChunkPos = ChunkPos { x: 0, y: 0, z: 0 };
+    const STONE: BlockState = BlockState(1);
+
+    fn pattern(pos: LocalPos) -> BlockState {
+        BlockState(((pos.x as u32 * 3 + pos.y as u32 * 5 + pos.z as u32 * 7) % 5) + 1)
+    }
+
+    #[test]
+    fn new_chunk_is_air() {
+        let chunk = Chunk::new(ORIGIN);
+        assert!(chunk.blocks().is_single_value());
+        assert!(chunk.iter().all(|(_, state)| state.is_air()));
+    }
+
+    #[test]
+    fn set_get_round_trip() {
+        let mut chunk = Chunk::new(ORIGIN);
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let pos = LocalPos::new(x, y, z);
+                    chunk.set(pos, pattern(pos));
+                }
+            }
+        }
+
+        for (pos, state) in chunk.iter() {
+            assert_eq!(state, pattern(pos));
+            assert_eq!(chunk.get(pos), state);
+        }
+    }
+
+    #[test]
+    fn palette_shrink_keeps_blocks() {
+        let mut chunk = Chunk::new(ORIGIN);
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    let pos = LocalPos::new(x, y, z);
+                    chunk.set(pos, pattern(pos));
+                }
+            }
+        }
+        assert_eq!(chunk.blocks().bits_per_entry(), 3);
+
+        // Carve the top half back out, leaving stone below
+        for (pos, _) in chunk.clone().iter() {
+            let state = if pos.y < 8 { STONE } else { BlockState::AIR };
+            chunk.set(pos, state);
+        }
+        chunk.compact();
+
+        assert_eq!(chunk.blocks().palette().len(), 2);
+        assert_eq!(chunk.blocks().bits_per_entry(), 1);
+        for (pos, state) in chunk.iter() {
+            assert_eq!(state, if pos.y < 8 { STONE } else { BlockState::AIR });
+        }
+
+        chunk.fill(STONE);
+        assert!(chunk.blocks().is_single_value());
+        assert_eq!(chunk.get(LocalPos::new(15, 15, 15)), STONE);
+    }
+
+    #[test]
+    fn biomes_per_column() {
+        let mut chunk = Chunk::new(ORIGIN);
+        assert!(chunk.biomes().is_single_value());
+        assert_eq!(chunk.set_biome(3, 12, BiomeId(2)), BiomeId::default());
+        assert_eq!(chunk.biome(3, 12), BiomeId(2));
+        assert_eq!(chunk.biome(12, 3), BiomeId::default());
+        assert_eq!(chunk.biomes().palette().len(), 2);
+    }
+
+    #[test]
+    fn iter_positions_match_index() {
+        let mut chunk = Chunk::new(ORIGIN);
+        chunk.set(LocalPos::new(3, 9, 14), STONE);
+        let found: Vec<_> = chunk.iter()
+            .filter(|(_, state)| *state == STONE)
+            .map(|(pos, _)| pos)
+            .collect();
+        assert_eq!(found, vec![LocalPos::new(3, 9, 14)]);
+    }
+}
@@ -1,6 +1,12 @@
-use rustc_hash::FxHashMap;
+use std::{fmt, sync::Mutex};
+
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, de::{Visitor, MapAccess}};
 
+use crate::resources::{manager::{LANG_DIR, ResourceManager}, pack::PackError};
+
+use super::{biome::Biome, registry::RegisteredBlock};
+
 pub struct LangVisitor;
 impl<'de> Visitor<'de> for LangVisitor {
     type Value = LangJson;
@@ -35,4 +41,354 @@ impl<'de> Deserialize<'de> for LangJson {
         D: serde::Deserializer<'de> {
         deserializer.deserialize_map(LangVisitor)
     }
-}
\ No newline at end of file
+}
+
+/// Language used for keys the selected language doesn't have
+pub const FALLBACK_LANGUAGE: &str = "en_us";
+
+/// Value of a format argument
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgValue {
+    Text(String),
+    Number(i64),
+}
+
+impl fmt::Display for ArgValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgValue::Text(text) => f.write_str(text),
+            ArgValue::Number(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+impl From<&str> for ArgValue {
+    fn from(text: &str) -> Self {
+        ArgValue::Text(text.to_string())
+    }
+}
+
+impl From<String> for ArgValue {
+    fn from(text: String) -> Self {
+        ArgValue::Text(text)
+    }
+}
+
+macro_rules! number_arg {
+    ($($t:ty),*) => {
+        $(impl From<$t> for ArgValue {
+            fn from(n: $t) -> Self {
+                ArgValue::Number(n as i64)
+            }
+        })*
+    };
+}
+
+number_arg!(i32, i64, u32, usize);
+
+/// Arguments of a message. Positional arguments are referenced as `{0}`,
+/// `{1}`, ... in the order they were added, named ones as `{name}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Args {
+    positional: Vec<ArgValue>,
+    named: Vec<(String, ArgValue)>,
+}
+
+impl Args {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn arg(mut self, value: impl Into<ArgValue>) -> Self {
+        self.positional.push(value.into());
+        self
+    }
+
+    pub fn named(mut self, name: &str, value: impl Into<ArgValue>) -> Self {
+        self.named.push((name.to_string(), value.into()));
+        self
+    }
+
+    fn get(&self, placeholder: &str) -> Option<&ArgValue> {
+        match placeholder.parse::<usize>() {
+            Ok(i) => self.positional.get(i),
+            Err(_) => self.named.iter().rev().find(|(name, _)| name == placeholder).map(|(_, value)| value),
+        }
+    }
+}
+
+/// Replaces the `{placeholders}` of a message. `{{` and `}}` are literal
+/// braces, placeholders without an argument are kept as they are.
+pub fn format_message(message: &str, args: &Args) -> String {
+    let mut ret = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(i) = rest.find(['{', '}']) {
+        ret.push_str(&rest[..i]);
+        let brace = rest.as_bytes()[i];
+        rest = &rest[i + 1..];
+        if rest.as_bytes().first() == Some(&brace) {
+            ret.push(brace as char);
+            rest = &rest[1..];
+            continue;
+        }
+        if brace == b'}' {
+            ret.push('}');
+            continue;
+        }
+
+        match rest.find('}') {
+            Some(end) => {
+                let placeholder = &rest[..end];
+                match args.get(placeholder.trim()) {
+                    Some(value) => ret.push_str(&value.to_string()),
+                    None => {
+                        ret.push('{');
+                        ret.push_str(placeholder);
+                        ret.push('}');
+                    },
+                }
+                rest = &rest[end + 1..];
+            },
+            None => ret.push('{'),
+        }
+    }
+    ret.push_str(rest);
+    ret
+}
+
+/// Plural forms, appended to a key as `.one`, `.few`, ...
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluralCategory {
+    Zero,
+    One,
+    Two,
+    Few,
+    Many,
+    Other,
+}
+
+impl PluralCategory {
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            PluralCategory::Zero => "zero",
+            PluralCategory::One => "one",
+            PluralCategory::Two => "two",
+            PluralCategory::Few => "few",
+            PluralCategory::Many => "many",
+            PluralCategory::Other => "other",
+        }
+    }
+
+    /// Form of `n` in a language, by the part of its code before the `_`.
+    /// Languages without rules of their own use the English ones.
+    pub fn of(language: &str, n: i64) -> Self {
+        let n = n.unsigned_abs();
+        let (last, last_two) = (n % 10, n % 100);
+        match language.split('_').next().unwrap_or_default() {
+            "ja" | "ko" | "zh" | "th" | "vi" | "id" => PluralCategory::Other,
+            "fr" | "pt" if n <= 1 => PluralCategory::One,
+            "fr" | "pt" => PluralCategory::Other,
+            "ru" | "uk" | "be" if last == 1 && last_two != 11 => PluralCategory::One,
+            "ru" | "uk" | "be" | "pl" if (2..=4).contains(&last) && !(12..=14).contains(&last_two) => PluralCategory::Few,
+            "ru" | "uk" | "be" => PluralCategory::Many,
+            "pl" if n == 1 => PluralCategory::One,
+            "pl" => PluralCategory::Many,
+            "cs" | "sk" if n == 1 => PluralCategory::One,
+            "cs" | "sk" if (2..=4).contains(&n) => PluralCategory::Few,
+            "cs" | "sk" => PluralCategory::Other,
+            _ if n == 1 => PluralCategory::One,
+            _ => PluralCategory::Other,
+        }
+    }
+}
+
+/// Translates keys into the selected language, falling back to
+/// `FALLBACK_LANGUAGE` and then to the key itself. Every missing key is
+/// logged once.
+pub struct Localizer {
+    language: String,
+    entries: FxHashMap<String, String>,
+    fallback: FxHashMap<String, String>,
+    missing: Mutex<FxHashSet<String>>,
+}
+
+impl Localizer {
+    /// Loads a language and the fallback from every resource pack
+    pub fn load(resources: &ResourceManager, language: &str) -> Result<Self, PackError> {
+        let mut ret = Self::new(FxHashMap::default(), FxHashMap::default());
+        ret.fallback = resources.lang(FALLBACK_LANGUAGE)?;
+        ret.set_language(resources, language)?;
+        Ok(ret)
+    }
+
+    /// Selects `FALLBACK_LANGUAGE` with entries that are already loaded
+    pub fn new(entries: FxHashMap<String, String>, fallback: FxHashMap<String, String>) -> Self {
+        Self {
+            language: FALLBACK_LANGUAGE.to_string(),
+            entries,
+            fallback,
+            missing: Mutex::new(FxHashSet::default()),
+        }
+    }
+
+    /// Switches to another language, keeping the current one if it doesn't exist
+    pub fn set_language(&mut self, resources: &ResourceManager, language: &str) -> Result<(), PackError> {
+        if !resources.languages()?.iter().any(|code| code == language) {
+            return Err(PackError::NotFound(format!("{}/{}.json", LANG_DIR, language)));
+        }
+        self.entries = resources.lang(language)?;
+        self.language = language.to_string();
+        self.missing.lock().unwrap().clear();
+        Ok(())
+    }
+
+    /// Code of the selected language, e.g. `en_us`
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// The first of `keys` in the selected language, then in the fallback
+    fn lookup(&self, key: &str, keys: impl Fn(&str) -> Vec<String>) -> Option<&str> {
+        if let Some(message) = keys(&self.language).iter().find_map(|key| self.entries.get(key)) {
+            return Some(message);
+        }
+
+        let fallback = keys(FALLBACK_LANGUAGE).iter().find_map(|key| self.fallback.get(key));
+        if self.missing.lock().unwrap().insert(key.to_string()) {
+            match fallback {
+                Some(_) => log::warn!("Missing translation `{}` in `{}`, using `{}`", key, self.language, FALLBACK_LANGUAGE),
+                None => log::warn!("Missing translation `{}`", key),
+            }
+        }
+        fallback.map(String::as_str)
+    }
+
+    /// Keys missing from the selected language that were looked up so far
+    pub fn missing_keys(&self) -> Vec<String> {
+        let mut ret: Vec<_> = self.missing.lock().unwrap().iter().cloned().collect();
+        ret.sort();
+        ret
+    }
+
+    pub fn translate(&self, key: &str) -> String {
+        self.format(key, &Args::new())
+    }
+
+    pub fn format(&self, key: &str, args: &Args) -> String {
+        match self.lookup(key, |_| vec![key.to_string()]) {
+            Some(message) => format_message(message, args),
+            None => key.to_string(),
+        }
+    }
+
+    /// Uses `<key>.<category>` for the plural form of `count` in each language,
+    /// then `<key>.other` and `<key>`. The count is also passed as `{count}`.
+    pub fn format_plural(&self, key: &str, count: i64, args: &Args) -> String {
+        let keys = |language: &str| vec![
+            format!("{}.{}", key, PluralCategory::of(language, count).suffix()),
+            format!("{}.other", key),
+            key.to_string(),
+        ];
+        match self.lookup(key, keys) {
+            Some(message) => format_message(message, &args.clone().named("count", count)),
+            None => key.to_string(),
+        }
+    }
+
+    /// Translated name of a block, its display name if no language has one
+    pub fn block_name(&self, block: &RegisteredBlock) -> String {
+        let key = block.translation_key();
+        match self.lookup(&key, |_| vec![key.clone()]) {
+            Some(name) => name.to_string(),
+            None => block.display_name.clone(),
+        }
+    }
+
+    /// Translated name of a biome, its display name if no language has one
+    pub fn biome_name(&self, biome: &Biome) -> String {
+        let key = biome.translation_key();
+        match self.lookup(&key, |_| vec![key.clone()]) {
+            Some(name) => name.to_string(),
+            None => biome.display_name.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{resources::pack::{MANIFEST, PACK_FORMAT}, util::test_dir::TestDir};
+
+    use super::*;
+
+    fn entries(pairs: &[(&str, &str)]) -> FxHashMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn format_arguments() {
+        let args = Args::new().arg("Steve").arg(3).named("block", "stone");
+        assert_eq!(format_message("{0} mined {1} {block}", &args), "Steve mined 3 stone");
+        assert_eq!(format_message("{block}: {{literal}} {2} {missing}", &args), "stone: {literal} {2} {missing}");
+        assert_eq!(format_message("unclosed { and } alone", &args), "unclosed { and } alone");
+        assert_eq!(format_message("{ 0 }ü", &args), "Steveü");
+    }
+
+    #[test]
+    fn plural_rules() {
+        let forms = |language: &str| [0, 1, 2, 5, 11, 21, 22, 25].map(|n| PluralCategory::of(language, n).suffix());
+        assert_eq!(forms("en_us"), ["other", "one", "other", "other", "other", "other", "other", "other"]);
+        assert_eq!(forms("fr_fr"), ["one", "one", "other", "other", "other", "other", "other", "other"]);
+        assert_eq!(forms("ru_ru"), ["many", "one", "few", "many", "many", "one", "few", "many"]);
+        assert_eq!(forms("pl_pl"), ["many", "one", "few", "many", "many", "many", "few", "many"]);
+        assert_eq!(forms("ja_jp"), ["other"; 8]);
+    }
+
+    #[test]
+    fn fallback_and_missing_keys() {
+        let fallback = entries(&[
+            ("menu.play", "Play"),
+            ("menu.quit", "Quit"),
+            ("chat.blocks.one", "{count} block"),
+            ("chat.blocks.other", "{count} blocks"),
+        ]);
+        let mut localizer = Localizer::new(fallback.clone(), fallback);
+        localizer.language = "ru_ru".to_string();
+        localizer.entries = entries(&[
+            ("menu.play", "Играть"),
+            ("chat.blocks.one", "{count} блок"),
+            ("chat.blocks.few", "{count} блока"),
+            ("chat.blocks.many", "{count} блоков"),
+        ]);
+
+        assert_eq!(localizer.translate("menu.play"), "Играть");
+        assert_eq!(localizer.translate("menu.quit"), "Quit");
+        assert_eq!(localizer.translate("menu.options"), "menu.options");
+        assert_eq!(localizer.translate("menu.quit"), "Quit");
+        assert_eq!(localizer.missing_keys(), vec!["menu.options", "menu.quit"]);
+
+        let blocks = |n| localizer.format_plural("chat.blocks", n, &Args::new());
+        assert_eq!([blocks(1), blocks(3), blocks(5)], ["1 блок", "3 блока", "5 блоков"]);
+    }
+
+    #[test]
+    fn load_from_packs() {
+        let dir = TestDir::new("lang");
+        fs::create_dir_all(dir.join(LANG_DIR)).unwrap();
+        fs::write(dir.join(MANIFEST), format!(r#"{{ "name": "Test", "format": {} }}"#, PACK_FORMAT)).unwrap();
+        fs::write(dir.join("lang/en_us.json"), r#"{ "block.stone": "Stone", "menu.play": "Play" }"#).unwrap();
+        fs::write(dir.join("lang/de_de.json"), r#"{ "block.stone": "Stein" }"#).unwrap();
+        let resources = ResourceManager::open(&[&dir]).unwrap();
+
+        let mut localizer = Localizer::load(&resources, "de_de").unwrap();
+        assert_eq!(localizer.translate("block.stone"), "Stein");
+        assert_eq!(localizer.translate("menu.play"), "Play");
+
+        assert!(localizer.set_language(&resources, "xx_xx").is_err());
+        assert_eq!(localizer.language(), "de_de");
+        localizer.set_language(&resources, "en_us").unwrap();
+        assert_eq!(localizer.translate("block.stone"), "Stone");
+        assert!(localizer.missing_keys().is_empty());
+    }
+}
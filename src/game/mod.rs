@@ -1,4 +1,11 @@
+pub mod biome;
 pub mod block;
+pub mod block_def;
+pub mod block_state;
 pub mod chunk;
+pub mod palette;
+pub mod pos;
+pub mod registry;
 pub mod static_data;
-pub mod lang;
\ No newline at end of file
+pub mod lang;
+pub mod world;
@@ -0,0 +1,370 @@
+/// Fixed-length storage that keeps one copy of each distinct value in a palette
+/// and refers to it through bit-packed indices.
+#[derive(Debug, Clone)]
+pub struct PalettedContainer<T> {
+    len: usize,
+    storage: Storage<T>,
+}
+
+#[derive(Debug, Clone)]
+enum Storage<T> {
+    /// Every entry holds the same value, nothing is allocated for indices
+    Single(T),
+    Indirect {
+        palette: Vec<T>,
+        indices: PackedArray,
+    },
+}
+
+impl<T: Clone + PartialEq> PalettedContainer<T> {
+    pub fn new(len: usize, value: T) -> Self {
+        Self {
+            len,
+            storage: Storage::Single(value),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> &T {
+        assert!(index < self.len, "index {} out of bounds for length {}", index, self.len);
+        match &self.storage {
+            Storage::Single(value) => value,
+            Storage::Indirect { palette, indices } => &palette[indices.get(index) as usize],
+        }
+    }
+
+    /// Sets the value at `index`, returning the previous value.
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        assert!(index < self.len, "index {} out of bounds for length {}", index, self.len);
+        match &mut self.storage {
+            Storage::Single(current) => {
+                if *current == value {
+                    return value;
+                }
+
+                let old = current.clone();
+                let mut indices = PackedArray::new(1, self.len);
+                indices.set(index, 1);
+                self.storage = Storage::Indirect {
+                    palette: vec![old.clone(), value],
+                    indices,
+                };
+                old
+            },
+            Storage::Indirect { .. } => {
+                let palette_index = self.palette_index_or_insert(value);
+                let Storage::Indirect { palette, indices } = &mut self.storage else { unreachable!() };
+                let old = indices.get(index);
+                indices.set(index, palette_index);
+                palette[old as usize].clone()
+            },
+        }
+    }
+
+    /// Sets every entry to `value` and drops the palette.
+    pub fn fill(&mut self, value: T) {
+        self.storage = Storage::Single(value);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        (0..self.len).map(move |i| self.get(i))
+    }
+
+    /// The distinct values currently referenced by the palette. May contain
+    /// entries that are no longer used until `compact` is called.
+    pub fn palette(&self) -> &[T] {
+        match &self.storage {
+            Storage::Single(value) => std::slice::from_ref(value),
+            Storage::Indirect { palette, .. } => palette,
+        }
+    }
+
+    /// Number of bits used per index, 0 when every entry holds the same value.
+    pub fn bits_per_entry(&self) -> u32 {
+        match &self.storage {
+            Storage::Single(_) => 0,
+            Storage::Indirect { indices, .. } => indices.bits(),
+        }
+    }
+
+    pub fn is_single_value(&self) -> bool {
+        matches!(self.storage, Storage::Single(_))
+    }
+
+    /// Palette index of every entry, `None` when every entry holds the same value
+    pub fn indices(&self) -> Option<&PackedArray> {
+        match &self.storage {
+            Storage::Single(_) => None,
+            Storage::Indirect { indices, .. } => Some(indices),
+        }
+    }
+
+    /// Rebuilds a container from its palette and indices, for example after
+    /// reading them from disk. `None` if they don't fit together.
+    pub fn from_parts(len: usize, mut palette: Vec<T>, indices: Option<PackedArray>) -> Option<Self> {
+        let storage = match indices {
+            None if palette.len() == 1 => Storage::Single(palette.pop().unwrap()),
+            Some(indices) if palette.len() > 1
+                && indices.len() == len
+                && palette.len() <= 1 << indices.bits()
+                && (0..len).all(|i| (indices.get(i) as usize) < palette.len()) => Storage::Indirect { palette, indices },
+            _ => return None,
+        };
+        Some(Self { len, storage })
+    }
+
+    /// Removes unused palette entries and shrinks the indices to the smallest
+    /// width that still fits the palette, collapsing to a single value if possible.
+    pub fn compact(&mut self) {
+        let Storage::Indirect { palette, indices } = &self.storage else { return };
+
+        let mut used = vec![false; palette.len()];
+        for i in 0..self.len {
+            used[indices.get(i) as usize] = true;
+        }
+
+        let mut remap = vec![0u32; palette.len()];
+        let mut new_palette = Vec::new();
+        for (old_index, value) in palette.iter().enumerate() {
+            if used[old_index] {
+                remap[old_index] = new_palette.len() as u32;
+                new_palette.push(value.clone());
+            }
+        }
+
+        if new_palette.len() == 1 {
+            self.storage = Storage::Single(new_palette.pop().unwrap());
+            return;
+        }
+
+        let mut new_indices = PackedArray::new(bits_for(new_palette.len()), self.len);
+        for i in 0..self.len {
+            new_indices.set(i, remap[indices.get(i) as usize]);
+        }
+
+        self.storage = Storage::Indirect {
+            palette: new_palette,
+            indices: new_indices,
+        };
+    }
+
+    fn palette_index_or_insert(&mut self, value: T) -> u32 {
+        let Storage::Indirect { palette, .. } = &self.storage else { unreachable!() };
+        if let Some(i) = palette.iter().position(|v| *v == value) {
+            return i as u32;
+        }
+
+        // Try to reclaim unused entries before widening the indices
+        if palette.len() == 1 << self.bits_per_entry() {
+            self.compact();
+        }
+
+        if let Storage::Single(current) = &self.storage {
+            self.storage = Storage::Indirect {
+                palette: vec![current.clone()],
+                indices: PackedArray::new(1, self.len),
+            };
+        }
+
+        let Storage::Indirect { palette, indices } = &mut self.storage else { unreachable!() };
+        palette.push(value);
+        if palette.len() > 1 << indices.bits() {
+            *indices = indices.resized(indices.bits() + 1);
+        }
+        (palette.len() - 1) as u32
+    }
+}
+
+/// Minimum number of bits needed to index a palette of `len` entries
+fn bits_for(len: usize) -> u32 {
+    (usize::BITS - (len.max(2) - 1).leading_zeros()).max(1)
+}
+
+/// Array of unsigned integers packed into `u64` words. Entries never straddle
+/// two words, so a few high bits of each word may go unused.
+#[derive(Debug, Clone)]
+pub struct PackedArray {
+    bits: u32,
+    len: usize,
+    data: Vec<u64>,
+}
+
+impl PackedArray {
+    pub fn new(bits: u32, len: usize) -> Self {
+        assert!((1..=32).contains(&bits), "unsupported entry width {}", bits);
+        let per_word = (64 / bits) as usize;
+        Self {
+            bits,
+            len,
+            data: vec![0; len.div_ceil(per_word)],
+        }
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> u32 {
+        let (word, shift) = self.locate(index);
+        ((self.data[word] >> shift) & self.mask()) as u32
+    }
+
+    pub fn set(&mut self, index: usize, value: u32) {
+        debug_assert!((value as u64) <= self.mask());
+        let (word, shift) = self.locate(index);
+        let mask = self.mask();
+        self.data[word] = (self.data[word] & !(mask << shift)) | ((value as u64 & mask) << shift);
+    }
+
+    /// Copies every entry into a new array with a different entry width.
+    pub fn resized(&self, bits: u32) -> Self {
+        let mut ret = Self::new(bits, self.len);
+        for i in 0..self.len {
+            ret.set(i, self.get(i));
+        }
+        ret
+    }
+
+    pub fn words(&self) -> &[u64] {
+        &self.data
+    }
+
+    /// The array stored in `words`, `None` if the width is unsupported or
+    /// the number of words doesn't match
+    pub fn from_words(bits: u32, len: usize, words: Vec<u64>) -> Option<Self> {
+        if !(1..=32).contains(&bits) || words.len() != len.div_ceil((64 / bits) as usize) {
+            return None;
+        }
+        Some(Self { bits, len, data: words })
+    }
+
+    fn mask(&self) -> u64 {
+        (1u64 << self.bits) - 1
+    }
+
+    fn locate(&self, index: usize) -> (usize, u32) {
+        let per_word = (64 / self.bits) as usize;
+        (index / per_word, (index % per_word) as u32 * self.bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_array_round_trip() {
+        for bits in 1..=16 {
+            let mut arr = PackedArray::new(bits, 4096);
+            let max = (1u32 << bits) - 1;
+            for i in 0..4096 {
+                arr.set(i, (i as u32 * 7919) & max);
+            }
+            for i in 0..4096 {
+                assert_eq!(arr.get(i), (i as u32 * 7919) & max, "bits {} index {}", bits, i);
+            }
+        }
+    }
+
+    #[test]
+    fn single_value_fast_path() {
+        let mut container = PalettedContainer::new(4096, 0u32);
+        assert!(container.is_single_value());
+        assert_eq!(container.bits_per_entry(), 0);
+
+        // Setting the same value must not allocate a palette
+        container.set(10, 0);
+        assert!(container.is_single_value());
+
+        container.set(10, 5);
+        assert!(!container.is_single_value());
+        assert_eq!(container.bits_per_entry(), 1);
+
+        container.fill(3);
+        assert!(container.is_single_value());
+        assert!(container.iter().all(|v| *v == 3));
+    }
+
+    #[test]
+    fn palette_growth_keeps_data() {
+        let mut container = PalettedContainer::new(4096, 0u32);
+        let value_at = |i: usize| (i % 300) as u32;
+
+        for i in 0..4096 {
+            container.set(i, value_at(i));
+        }
+
+        assert_eq!(container.palette().len(), 300);
+        assert_eq!(container.bits_per_entry(), 9);
+        for (i, value) in container.iter().enumerate() {
+            assert_eq!(*value, value_at(i));
+        }
+    }
+
+    #[test]
+    fn compact_shrinks_palette() {
+        let mut container = PalettedContainer::new(4096, 0u32);
+        for i in 0..4096 {
+            container.set(i, (i % 40) as u32);
+        }
+        assert_eq!(container.bits_per_entry(), 6);
+
+        // Leave only three distinct values behind
+        for i in 0..4096 {
+            container.set(i, (i % 3) as u32 + 100);
+        }
+        container.compact();
+
+        assert_eq!(container.palette().len(), 3);
+        assert_eq!(container.bits_per_entry(), 2);
+        for (i, value) in container.iter().enumerate() {
+            assert_eq!(*value, (i % 3) as u32 + 100);
+        }
+
+        for i in 0..4096 {
+            container.set(i, 7);
+        }
+        container.compact();
+        assert!(container.is_single_value());
+        assert_eq!(*container.get(4095), 7);
+    }
+
+    #[test]
+    fn growth_reuses_unused_entries() {
+        let mut container = PalettedContainer::new(16, 0u32);
+        container.set(0, 1);
+        container.set(0, 2);
+        container.set(0, 3);
+        assert_eq!(container.bits_per_entry(), 2);
+
+        // 0 and 3 are the only live values, so 4 fits without widening
+        container.set(1, 4);
+        assert_eq!(container.bits_per_entry(), 2);
+        assert_eq!(*container.get(0), 3);
+        assert_eq!(*container.get(1), 4);
+        assert_eq!(*container.get(2), 0);
+    }
+
+    #[test]
+    fn set_returns_previous() {
+        let mut container = PalettedContainer::new(8, 'a');
+        assert_eq!(container.set(3, 'b'), 'a');
+        assert_eq!(container.set(3, 'c'), 'b');
+        assert_eq!(container.set(4, 'a'), 'a');
+    }
+}
@@ -0,0 +1,366 @@
+use nalgebra::{Point3, Vector3};
+
+use super::{block_state::Axis, chunk::CHUNK_SIZE};
+
+/// Width of a region in chunks along every axis
+pub const REGION_SIZE: usize = 32;
+
+/// The six faces of a block, in the same order as `DEFAULT_CUBE_MODEL_QUADS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Direction {
+    /// +X
+    East,
+    /// -X
+    West,
+    /// +Y
+    Up,
+    /// -Y
+    Down,
+    /// +Z
+    South,
+    /// -Z
+    North,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::East,
+        Direction::West,
+        Direction::Up,
+        Direction::Down,
+        Direction::South,
+        Direction::North,
+    ];
+
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    pub fn from_index(index: usize) -> Self {
+        Self::ALL[index]
+    }
+
+    pub fn offset(&self) -> (i32, i32, i32) {
+        match self {
+            Direction::East => (1, 0, 0),
+            Direction::West => (-1, 0, 0),
+            Direction::Up => (0, 1, 0),
+            Direction::Down => (0, -1, 0),
+            Direction::South => (0, 0, 1),
+            Direction::North => (0, 0, -1),
+        }
+    }
+
+    pub fn normal(&self) -> Vector3<f32> {
+        let (x, y, z) = self.offset();
+        Vector3::new(x as f32, y as f32, z as f32)
+    }
+
+    pub fn opposite(&self) -> Self {
+        match self {
+            Direction::East => Direction::West,
+            Direction::West => Direction::East,
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::South => Direction::North,
+            Direction::North => Direction::South,
+        }
+    }
+
+    pub fn axis(&self) -> Axis {
+        match self {
+            Direction::East | Direction::West => Axis::X,
+            Direction::Up | Direction::Down => Axis::Y,
+            Direction::South | Direction::North => Axis::Z,
+        }
+    }
+
+    pub fn is_positive(&self) -> bool {
+        matches!(self, Direction::East | Direction::Up | Direction::South)
+    }
+}
+
+/// Position of a block in the world
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct BlockPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl BlockPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn from_parts(chunk: ChunkPos, local: LocalPos) -> Self {
+        chunk.origin().offset(local.x as i32, local.y as i32, local.z as i32)
+    }
+
+    /// The chunk containing this block, rounding towards negative infinity
+    pub fn chunk(&self) -> ChunkPos {
+        let size = CHUNK_SIZE as i32;
+        ChunkPos::new(self.x.div_euclid(size), self.y.div_euclid(size), self.z.div_euclid(size))
+    }
+
+    /// Position of this block inside of its chunk
+    pub fn local(&self) -> LocalPos {
+        let size = CHUNK_SIZE as i32;
+        LocalPos::new(
+            self.x.rem_euclid(size) as usize,
+            self.y.rem_euclid(size) as usize,
+            self.z.rem_euclid(size) as usize,
+        )
+    }
+
+    pub fn offset(&self, x: i32, y: i32, z: i32) -> Self {
+        Self::new(self.x + x, self.y + y, self.z + z)
+    }
+
+    pub fn neighbor(&self, dir: Direction) -> Self {
+        let (x, y, z) = dir.offset();
+        self.offset(x, y, z)
+    }
+
+    /// Center of the block in world space
+    pub fn center(&self) -> Point3<f32> {
+        Point3::from(*self) + Vector3::repeat(0.5)
+    }
+}
+
+/// Minimum corner of the block
+impl From<BlockPos> for Point3<f32> {
+    fn from(pos: BlockPos) -> Self {
+        Point3::new(pos.x as f32, pos.y as f32, pos.z as f32)
+    }
+}
+
+/// The block containing the point
+impl From<Point3<f32>> for BlockPos {
+    fn from(p: Point3<f32>) -> Self {
+        Self::new(p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl ChunkPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    /// The block at local position (0, 0, 0)
+    pub fn origin(&self) -> BlockPos {
+        let size = CHUNK_SIZE as i32;
+        BlockPos::new(self.x * size, self.y * size, self.z * size)
+    }
+
+    pub fn offset(&self, x: i32, y: i32, z: i32) -> Self {
+        Self::new(self.x + x, self.y + y, self.z + z)
+    }
+
+    pub fn neighbor(&self, dir: Direction) -> Self {
+        let (x, y, z) = dir.offset();
+        self.offset(x, y, z)
+    }
+
+    pub fn neighbors(&self) -> [ChunkPos; 6] {
+        Direction::ALL.map(|dir| self.neighbor(dir))
+    }
+
+    /// The 26 chunks sharing a face, edge or corner with this one
+    pub fn surrounding(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        (-1..=1).flat_map(move |x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| (x, y, z))))
+            .filter(|offset| *offset != (0, 0, 0))
+            .map(|(x, y, z)| self.offset(x, y, z))
+    }
+
+    /// The region containing this chunk, rounding towards negative infinity
+    pub fn region(&self) -> RegionPos {
+        let size = REGION_SIZE as i32;
+        RegionPos::new(self.x.div_euclid(size), self.y.div_euclid(size), self.z.div_euclid(size))
+    }
+
+    /// Position of this chunk inside of its region, each component in `0..REGION_SIZE`
+    pub fn region_local(&self) -> (usize, usize, usize) {
+        let size = REGION_SIZE as i32;
+        (self.x.rem_euclid(size) as usize, self.y.rem_euclid(size) as usize, self.z.rem_euclid(size) as usize)
+    }
+
+    /// Squared distance in chunks, used to order work around the camera
+    pub fn distance_squared(&self, other: ChunkPos) -> i64 {
+        let (dx, dy, dz) = ((self.x - other.x) as i64, (self.y - other.y) as i64, (self.z - other.z) as i64);
+        dx * dx + dy * dy + dz * dz
+    }
+}
+
+/// Minimum corner of the chunk
+impl From<ChunkPos> for Point3<f32> {
+    fn from(pos: ChunkPos) -> Self {
+        pos.origin().into()
+    }
+}
+
+/// The chunk containing the point
+impl From<Point3<f32>> for ChunkPos {
+    fn from(p: Point3<f32>) -> Self {
+        BlockPos::from(p).chunk()
+    }
+}
+
+impl From<BlockPos> for ChunkPos {
+    fn from(pos: BlockPos) -> Self {
+        pos.chunk()
+    }
+}
+
+/// Position of a block inside of a chunk, every component is in `0..CHUNK_SIZE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct LocalPos {
+    pub x: u8,
+    pub y: u8,
+    pub z: u8,
+}
+
+impl LocalPos {
+    pub fn new(x: usize, y: usize, z: usize) -> Self {
+        assert!(
+            x < CHUNK_SIZE && y < CHUNK_SIZE && z < CHUNK_SIZE,
+            "local position ({}, {}, {}) is outside of the chunk", x, y, z
+        );
+        Self { x: x as u8, y: y as u8, z: z as u8 }
+    }
+
+    /// Index into a chunk's block storage, x varies fastest then z then y
+    pub fn index(&self) -> usize {
+        (self.y as usize * CHUNK_SIZE + self.z as usize) * CHUNK_SIZE + self.x as usize
+    }
+
+    pub fn from_index(index: usize) -> Self {
+        Self::new(index % CHUNK_SIZE, index / (CHUNK_SIZE * CHUNK_SIZE), (index / CHUNK_SIZE) % CHUNK_SIZE)
+    }
+
+    /// The neighboring position, or `None` if it lies in another chunk
+    pub fn neighbor(&self, dir: Direction) -> Option<Self> {
+        let (dx, dy, dz) = dir.offset();
+        let (x, y, z) = (self.x as i32 + dx, self.y as i32 + dy, self.z as i32 + dz);
+        let range = 0..CHUNK_SIZE as i32;
+        (range.contains(&x) && range.contains(&y) && range.contains(&z))
+            .then(|| Self::new(x as usize, y as usize, z as usize))
+    }
+
+    /// Directions in which this position touches the chunk border
+    pub fn borders(&self) -> impl Iterator<Item = Direction> + '_ {
+        Direction::ALL.into_iter().filter(|dir| self.neighbor(*dir).is_none())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct RegionPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl RegionPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    /// The chunk at region local position (0, 0, 0)
+    pub fn origin(&self) -> ChunkPos {
+        let size = REGION_SIZE as i32;
+        ChunkPos::new(self.x * size, self.y * size, self.z * size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::render::util::cube_model::DEFAULT_CUBE_MODEL_QUADS;
+
+    use super::*;
+
+    #[test]
+    fn negative_block_to_chunk() {
+        assert_eq!(BlockPos::new(0, 0, 0).chunk(), ChunkPos::new(0, 0, 0));
+        assert_eq!(BlockPos::new(15, 15, 15).chunk(), ChunkPos::new(0, 0, 0));
+        assert_eq!(BlockPos::new(16, -1, -16).chunk(), ChunkPos::new(1, -1, -1));
+        assert_eq!(BlockPos::new(-17, -32, -33).chunk(), ChunkPos::new(-2, -2, -3));
+
+        assert_eq!(BlockPos::new(-1, -16, -17).local(), LocalPos::new(15, 0, 15));
+        assert_eq!(BlockPos::new(17, 31, -31).local(), LocalPos::new(1, 15, 1));
+    }
+
+    #[test]
+    fn block_round_trip() {
+        for x in -40..40 {
+            for y in [-1000, -17, -16, -1, 0, 1, 15, 16, 999] {
+                let pos = BlockPos::new(x, y, -x * 3);
+                assert_eq!(BlockPos::from_parts(pos.chunk(), pos.local()), pos);
+            }
+        }
+    }
+
+    #[test]
+    fn chunk_to_region() {
+        assert_eq!(ChunkPos::new(0, 31, -1).region(), RegionPos::new(0, 0, -1));
+        assert_eq!(ChunkPos::new(-32, -33, 32).region(), RegionPos::new(-1, -2, 1));
+        assert_eq!(ChunkPos::new(-1, 33, -32).region_local(), (31, 1, 0));
+
+        let chunk = ChunkPos::new(-45, 7, 70);
+        let (x, y, z) = chunk.region_local();
+        assert_eq!(chunk.region().origin().offset(x as i32, y as i32, z as i32), chunk);
+    }
+
+    #[test]
+    fn local_index_round_trip() {
+        for i in 0..CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE {
+            assert_eq!(LocalPos::from_index(i).index(), i);
+        }
+        assert_eq!(LocalPos::new(1, 0, 0).index(), 1);
+    }
+
+    #[test]
+    fn local_neighbors() {
+        let corner = LocalPos::new(0, 15, 7);
+        assert_eq!(corner.neighbor(Direction::East), Some(LocalPos::new(1, 15, 7)));
+        assert_eq!(corner.neighbor(Direction::West), None);
+        assert_eq!(corner.neighbor(Direction::Up), None);
+        assert_eq!(corner.borders().collect::<Vec<_>>(), vec![Direction::West, Direction::Up]);
+        assert_eq!(LocalPos::new(5, 5, 5).borders().count(), 0);
+    }
+
+    #[test]
+    fn point_conversions() {
+        assert_eq!(BlockPos::from(Point3::new(-0.5, 0.5, -16.01)), BlockPos::new(-1, 0, -17));
+        assert_eq!(ChunkPos::from(Point3::new(-0.5, 16.0, 15.9)), ChunkPos::new(-1, 1, 0));
+        assert_eq!(Point3::from(BlockPos::new(-3, 2, 1)), Point3::new(-3.0, 2.0, 1.0));
+        assert_eq!(Point3::from(ChunkPos::new(-1, 0, 2)), Point3::new(-16.0, 0.0, 32.0));
+        assert_eq!(BlockPos::new(1, 2, 3).center(), Point3::new(1.5, 2.5, 3.5));
+    }
+
+    #[test]
+    fn directions_match_cube_quads() {
+        for dir in Direction::ALL {
+            let quad = &DEFAULT_CUBE_MODEL_QUADS[dir.index()];
+            let center = quad.get_vertex_positions().iter().sum::<Vector3<f32>>() / 4.0;
+            assert_eq!(center, dir.normal() * 0.5, "{:?}", dir);
+
+            assert_eq!(dir.opposite().opposite(), dir);
+            assert_eq!(dir.normal(), -dir.opposite().normal());
+            assert_eq!(Direction::from_index(dir.index()), dir);
+        }
+    }
+
+    #[test]
+    fn ordering() {
+        let mut positions = vec![ChunkPos::new(1, 0, 0), ChunkPos::new(0, 1, 0), ChunkPos::new(0, 0, -1)];
+        positions.sort();
+        assert_eq!(positions, vec![ChunkPos::new(0, 0, -1), ChunkPos::new(0, 1, 0), ChunkPos::new(1, 0, 0)]);
+    }
+}
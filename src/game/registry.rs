@@ -0,0 +1,433 @@
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::render::util::cube_model::CubeModel;
+
+use super::{lang::Localizer, static_data::StaticBlockData, block_def::{CollisionShape, BlockSounds, BlockDrop}, block::BlockState, block_state::{StateDefinition, StateString, StateParseError, PropertyValue, Property}};
+
+/// Compact numeric block ID, only meaningful for the registry that created it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct BlockId(pub u16);
+
+impl BlockId {
+    /// Air is always registered first
+    pub const AIR: BlockId = BlockId(0);
+}
+
+pub const AIR_NAME: &str = "air";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockProperties {
+    /// Whether entities collide with the block
+    pub solid: bool,
+    /// Whether the faces of neighboring blocks can be seen through this one
+    pub transparent: bool,
+    /// Emitted light level, 0-15
+    pub light_emission: u8,
+    pub hardness: f32,
+}
+
+impl BlockProperties {
+    pub const AIR: BlockProperties = BlockProperties {
+        solid: false,
+        transparent: true,
+        light_emission: 0,
+        hardness: 0.0,
+    };
+
+    pub fn is_opaque(&self) -> bool {
+        !self.transparent
+    }
+}
+
+impl Default for BlockProperties {
+    fn default() -> Self {
+        Self {
+            solid: true,
+            transparent: false,
+            light_emission: 0,
+            hardness: 1.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RegisteredBlock {
+    pub id: BlockId,
+    pub name: String,
+    pub display_name: String,
+    pub properties: BlockProperties,
+    pub model: Option<CubeModel>,
+    pub collision: CollisionShape,
+    pub sounds: BlockSounds,
+    pub drops: Vec<BlockDrop>,
+    pub states: StateDefinition,
+    /// ID of this block's first state, the rest follow consecutively
+    pub first_state: BlockState,
+    pub default_state: BlockState,
+}
+
+impl RegisteredBlock {
+    pub fn state_ids(&self) -> impl Iterator<Item = BlockState> {
+        let first = self.first_state.0;
+        (first..first + self.states.state_count() as u32).map(BlockState)
+    }
+
+    /// Key of the block's name in the language files
+    pub fn translation_key(&self) -> String {
+        format!("block.{}", self.name)
+    }
+
+    fn offset(&self, state: BlockState) -> usize {
+        (state.0 - self.first_state.0) as usize
+    }
+}
+
+/// Owns every known block type and hands out the numeric IDs used by chunks,
+/// meshing and saving.
+#[derive(Clone)]
+pub struct BlockRegistry {
+    blocks: Vec<RegisteredBlock>,
+    ids: FxHashMap<String, BlockId>,
+    /// Owning block of every state
+    state_blocks: Vec<BlockId>,
+}
+
+impl BlockRegistry {
+    /// Creates a registry containing only air
+    pub fn new() -> Self {
+        let mut ret = Self {
+            blocks: Vec::new(),
+            ids: FxHashMap::default(),
+            state_blocks: Vec::new(),
+        };
+        ret.insert(AIR_NAME, "Air".to_string(), BlockProperties::AIR, None, StateDefinition::default());
+        ret
+    }
+
+    /// Builds the registry from the loaded block definitions. Blocks are
+    /// registered in name order so IDs don't depend on file system order.
+    pub fn from_static_data(data: StaticBlockData) -> Self {
+        let mut ret = Self::new();
+        let mut definitions: Vec<_> = data.into_iter().collect();
+        definitions.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for def in definitions {
+            let properties = BlockProperties {
+                solid: !def.collision.is_empty(),
+                transparent: !def.opaque,
+                light_emission: def.light_emission,
+                hardness: def.hardness,
+            };
+            let id = ret.insert(&def.name, def.display_name, properties, def.model, StateDefinition::new(def.properties));
+
+            let block = &mut ret.blocks[id.0 as usize];
+            block.collision = def.collision;
+            block.sounds = def.sounds;
+            block.drops = def.drops;
+        }
+        ret
+    }
+
+    /// Replaces the cube models and display names of the registered blocks,
+    /// e.g. after the resource packs were reloaded. Everything else needs a
+    /// restart to change.
+    pub fn update_models(&mut self, data: StaticBlockData) {
+        for def in data {
+            match self.ids.get(&def.name) {
+                Some(id) => {
+                    let block = &mut self.blocks[id.0 as usize];
+                    block.model = def.model;
+                    block.display_name = def.display_name;
+                },
+                None => log::warn!("Block `{}` was added, it will be registered after a restart", def.name),
+            }
+        }
+    }
+
+    /// Replaces the display names from the block definitions with their
+    /// translations under `block.<name>`, blocks without one keep theirs
+    pub fn localize(&mut self, localizer: &Localizer) {
+        for block in &mut self.blocks {
+            block.display_name = localizer.block_name(block);
+        }
+    }
+
+    pub fn register(&mut self, name: &str, display_name: &str, properties: BlockProperties) -> BlockId {
+        self.insert(name, display_name.to_string(), properties, None, StateDefinition::default())
+    }
+
+    /// Registers a block with one state for every combination of `states`
+    pub fn register_with_states(&mut self, name: &str, display_name: &str, properties: BlockProperties, states: Vec<Property>) -> BlockId {
+        self.insert(name, display_name.to_string(), properties, None, StateDefinition::new(states))
+    }
+
+    fn insert(&mut self, name: &str, display_name: String, properties: BlockProperties, model: Option<CubeModel>, states: StateDefinition) -> BlockId {
+        assert!(!self.ids.contains_key(name), "block `{}` registered twice", name);
+        let id = BlockId(u16::try_from(self.blocks.len()).expect("too many registered blocks"));
+        let first_state = BlockState(u32::try_from(self.state_blocks.len()).expect("too many block states"));
+
+        self.state_blocks.extend(std::iter::repeat_n(id, states.state_count()));
+        let collision = if properties.solid { CollisionShape::Full } else { CollisionShape::Empty };
+        self.blocks.push(RegisteredBlock {
+            id,
+            name: name.to_string(),
+            display_name,
+            properties,
+            model,
+            collision,
+            sounds: BlockSounds::default(),
+            drops: Vec::new(),
+            states,
+            first_state,
+            default_state: first_state,
+        });
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    pub fn id(&self, name: &str) -> Option<BlockId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn get(&self, id: BlockId) -> Option<&RegisteredBlock> {
+        self.blocks.get(id.0 as usize)
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<&RegisteredBlock> {
+        self.id(name).and_then(|id| self.get(id))
+    }
+
+    /// Panics if `id` was not created by this registry
+    pub fn name(&self, id: BlockId) -> &str {
+        &self.blocks[id.0 as usize].name
+    }
+
+    /// Panics if `id` was not created by this registry
+    pub fn properties(&self, id: BlockId) -> &BlockProperties {
+        &self.blocks[id.0 as usize].properties
+    }
+
+    pub fn default_state(&self, id: BlockId) -> BlockState {
+        self.blocks[id.0 as usize].default_state
+    }
+
+    pub fn block_of(&self, state: BlockState) -> BlockId {
+        self.state_blocks[state.0 as usize]
+    }
+
+    /// The registered block that owns `state`
+    pub fn block_for_state(&self, state: BlockState) -> &RegisteredBlock {
+        &self.blocks[self.block_of(state).0 as usize]
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.state_blocks.len()
+    }
+
+    /// The value of one property as a string, `None` if the block has no such property
+    pub fn state_value(&self, state: BlockState, property: &str) -> Option<&str> {
+        let block = self.block_for_state(state);
+        block.states.value(block.offset(state), property)
+    }
+
+    /// `state` with one property changed, `None` if the property or value doesn't exist
+    pub fn with_value(&self, state: BlockState, property: &str, value: &str) -> Option<BlockState> {
+        let block = self.block_for_state(state);
+        let offset = block.states.with_value(block.offset(state), property, value)?;
+        Some(BlockState(block.first_state.0 + offset as u32))
+    }
+
+    pub fn get_property<T: PropertyValue>(&self, state: BlockState, property: &str) -> Option<T> {
+        self.state_value(state, property).and_then(T::from_name)
+    }
+
+    pub fn with_property<T: PropertyValue>(&self, state: BlockState, property: &str, value: T) -> Option<BlockState> {
+        self.with_value(state, property, value.name())
+    }
+
+    /// Parses `name[property=value,...]`, properties that are left out keep their default value
+    pub fn parse_state(&self, input: &str) -> Result<BlockState, StateParseError> {
+        let parsed = StateString::parse(input)?;
+        let block = self.get_by_name(parsed.block)
+            .ok_or_else(|| StateParseError::UnknownBlock(parsed.block.to_string()))?;
+
+        let mut indices = block.states.value_indices(block.offset(block.default_state));
+        for (key, value) in parsed.properties {
+            let (i, property) = block.states.property(key)
+                .ok_or_else(|| StateParseError::UnknownProperty {
+                    block: block.name.clone(),
+                    property: key.to_string(),
+                })?;
+            indices[i] = property.value_index(value)
+                .ok_or_else(|| StateParseError::InvalidValue {
+                    block: block.name.clone(),
+                    property: key.to_string(),
+                    value: value.to_string(),
+                })?;
+        }
+
+        Ok(BlockState(block.first_state.0 + block.states.offset(&indices) as u32))
+    }
+
+    /// Formats a state in the syntax accepted by `parse_state`, listing every property
+    pub fn format_state(&self, state: BlockState) -> String {
+        let block = self.block_for_state(state);
+        let indices = block.states.value_indices(block.offset(state));
+        StateString {
+            block: &block.name,
+            properties: block.states.properties().iter().zip(indices)
+                .map(|(p, i)| (p.name.as_str(), p.values[i].as_str()))
+                .collect(),
+        }.to_string()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RegisteredBlock> {
+        self.blocks.iter()
+    }
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<StaticBlockData> for BlockRegistry {
+    fn from(data: StaticBlockData) -> Self {
+        Self::from_static_data(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{game::block_state::{Facing, Half, Axis}, resources::manager::ResourceManager};
+
+    use super::*;
+
+    fn test_registry(names: &[&str]) -> BlockRegistry {
+        let mut registry = BlockRegistry::new();
+        for name in names {
+            registry.register(name, name, BlockProperties::default());
+        }
+        registry
+    }
+
+    #[test]
+    fn air_is_zero() {
+        let registry = BlockRegistry::new();
+        assert_eq!(registry.id(AIR_NAME), Some(BlockId::AIR));
+        assert!(!registry.properties(BlockId::AIR).solid);
+        assert!(registry.properties(BlockId::AIR).transparent);
+    }
+
+    #[test]
+    fn lookup_both_ways() {
+        let registry = test_registry(&["stone", "dirt"]);
+        let dirt = registry.id("dirt").unwrap();
+        assert_eq!(dirt, BlockId(2));
+        assert_eq!(registry.name(dirt), "dirt");
+        assert_eq!(registry.get_by_name("stone").unwrap().id, BlockId(1));
+        assert!(registry.id("missing").is_none());
+    }
+
+    #[test]
+    fn loads_static_data() {
+        let resources = ResourceManager::open(&["assets"]).unwrap();
+        let registry = BlockRegistry::from(StaticBlockData::load(&resources).unwrap());
+        let stone = registry.get_by_name("stone").unwrap();
+        assert_eq!(stone.display_name, "Stone");
+        assert!(stone.model.is_some());
+        assert!(stone.properties.is_opaque());
+        assert!(stone.properties.solid);
+        assert_eq!(stone.collision, CollisionShape::Full);
+        assert_eq!(stone.drops[0].item, "stone");
+    }
+
+    #[test]
+    fn display_names_are_translated() {
+        let resources = ResourceManager::open(&["assets"]).unwrap();
+        let mut registry = BlockRegistry::from(StaticBlockData::load(&resources).unwrap());
+        let mut localizer = Localizer::load(&resources, "en_us").unwrap();
+        registry.localize(&localizer);
+        assert_eq!(registry.get_by_name("grass_block").unwrap().display_name, "Grass Block");
+        assert!(localizer.missing_keys().is_empty(), "{:?}", localizer.missing_keys());
+
+        let mut names = FxHashMap::default();
+        names.insert("block.stone".to_string(), "Pierre".to_string());
+        localizer = Localizer::new(names, FxHashMap::default());
+        registry.update_models(StaticBlockData::load(&resources).unwrap());
+        registry.localize(&localizer);
+        assert_eq!(registry.get_by_name("stone").unwrap().display_name, "Pierre");
+        // Untranslated blocks keep the name from their definition
+        assert_eq!(registry.get_by_name("dirt").unwrap().display_name, "Dirt");
+    }
+
+    fn stairs_registry() -> BlockRegistry {
+        let mut registry = test_registry(&["stone"]);
+        registry.register_with_states(
+            "stone_stairs",
+            "Stone Stairs",
+            BlockProperties::default(),
+            vec![Property::facing(), Property::half(), Property::waterlogged()],
+        );
+        registry.register_with_states("log", "Log", BlockProperties::default(), vec![Property::axis()]);
+        registry
+    }
+
+    #[test]
+    fn states_are_dense() {
+        let registry = stairs_registry();
+        assert_eq!(registry.state_count(), 1 + 1 + 16 + 3);
+
+        let stairs = registry.get_by_name("stone_stairs").unwrap();
+        assert_eq!(stairs.first_state, BlockState(2));
+        assert!(stairs.state_ids().all(|s| registry.block_of(s) == stairs.id));
+        assert_eq!(registry.default_state(registry.id("log").unwrap()), BlockState(18));
+    }
+
+    #[test]
+    fn state_string_round_trip() {
+        let registry = stairs_registry();
+        for state in 0..registry.state_count() as u32 {
+            let formatted = registry.format_state(BlockState(state));
+            assert_eq!(registry.parse_state(&formatted), Ok(BlockState(state)), "{}", formatted);
+        }
+
+        assert_eq!(registry.format_state(BlockState::AIR), "air");
+        let state = registry.parse_state("stone_stairs[facing=east,half=top]").unwrap();
+        assert_eq!(registry.format_state(state), "stone_stairs[facing=east,half=top,waterlogged=false]");
+    }
+
+    #[test]
+    fn typed_property_access() {
+        let registry = stairs_registry();
+        let state = registry.parse_state("stone_stairs[half=top]").unwrap();
+        assert_eq!(registry.get_property::<Facing>(state, "facing"), Some(Facing::North));
+        assert_eq!(registry.get_property::<Half>(state, "half"), Some(Half::Top));
+
+        let state = registry.with_property(state, "waterlogged", true).unwrap();
+        assert_eq!(registry.state_value(state, "waterlogged"), Some("true"));
+        assert_eq!(registry.get_property::<Half>(state, "half"), Some(Half::Top));
+        assert!(registry.with_property(state, "axis", Axis::X).is_none());
+
+        let log = registry.parse_state("log[axis=z]").unwrap();
+        assert_eq!(registry.get_property::<Axis>(log, "axis"), Some(Axis::Z));
+    }
+
+    #[test]
+    fn parse_state_errors() {
+        let registry = stairs_registry();
+        assert!(matches!(registry.parse_state("wood"), Err(StateParseError::UnknownBlock(_))));
+        assert!(matches!(registry.parse_state("stone[axis=x]"), Err(StateParseError::UnknownProperty { .. })));
+        assert!(matches!(registry.parse_state("log[axis=w]"), Err(StateParseError::InvalidValue { .. })));
+    }
+}
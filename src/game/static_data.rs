@@ -1,43 +1,39 @@
-use rustc_hash::FxHashMap;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use crate::resources::{loader::LoadErrors, manager::ResourceManager};
 
-use crate::render::util::cube_model::CubeModel;
-
-use super::lang::LangJson;
-
-pub struct InitBlockData {
-    pub name: String,
-    pub model: CubeModel, // TODO: Custom models besides just cubes
-}
+use super::block_def::{BlockDefinition, BlockDefinitionError, load_block_definitions};
 
+/// Block definitions loaded from `blocks` in the resource packs, sorted by name
 pub struct StaticBlockData {
-    inner: FxHashMap<String, InitBlockData>,
+    definitions: Vec<BlockDefinition>,
 }
 
 impl StaticBlockData {
-    pub fn load() -> Self {
-        let data = include_str!("../../assets/en_us.json");
-        let lang: LangJson = serde_json::from_str(data).unwrap();
-        let mut hash = FxHashMap::default();
-
-        for (id, name) in lang.data {
-            hash.insert(
-                id.clone(), 
-                InitBlockData {
-                    name,
-                    model: CubeModel::new(&id),
-                }
-            );
-        }
-
-        Self {
-            inner: hash
-        }
+    pub fn load(resources: &ResourceManager) -> Result<Self, LoadErrors<BlockDefinitionError>> {
+        Ok(Self {
+            definitions: load_block_definitions(resources)?,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BlockDefinition> {
+        self.definitions.iter().find(|definition| definition.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BlockDefinition> {
+        self.definitions.iter()
+    }
+}
+
+impl IntoIterator for StaticBlockData {
+    type Item = BlockDefinition;
+    type IntoIter = std::vec::IntoIter<BlockDefinition>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.definitions.into_iter()
     }
 }
 
 #[test]
 pub fn test() {
-    StaticBlockData::load();
-}
\ No newline at end of file
+    let resources = ResourceManager::open(&["assets"]).unwrap();
+    StaticBlockData::load(&resources).unwrap();
+}
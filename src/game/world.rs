@@ -0,0 +1,281 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use super::{chunk::{Chunk, CHUNK_SIZE}, pos::{ChunkPos, BlockPos}, block::BlockState};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorldEvent {
+    BlockChanged {
+        pos: BlockPos,
+        old: BlockState,
+        new: BlockState,
+    },
+    ChunkLoaded(ChunkPos),
+    ChunkUnloaded(ChunkPos),
+}
+
+/// Owns every loaded chunk and keeps track of which ones need to be remeshed
+/// or saved after being modified.
+#[derive(Default)]
+pub struct World {
+    chunks: FxHashMap<ChunkPos, Chunk>,
+    dirty_meshes: FxHashSet<ChunkPos>,
+    dirty_saves: FxHashSet<ChunkPos>,
+    listeners: Vec<Sender<WorldEvent>>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a receiver for every change made to the world from now on.
+    /// Dropping the receiver unsubscribes.
+    pub fn subscribe(&mut self) -> Receiver<WorldEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.listeners.push(sender);
+        receiver
+    }
+
+    fn emit(&mut self, event: WorldEvent) {
+        self.listeners.retain(|listener| listener.send(event.clone()).is_ok());
+    }
+
+    /// Adds a chunk to the world, replacing and returning any chunk already at its position
+    pub fn insert_chunk(&mut self, chunk: Chunk) -> Option<Chunk> {
+        let pos = chunk.pos;
+        let old = self.chunks.insert(pos, chunk);
+
+        // Faces on the borders of the neighbors may be hidden, exposed or shaded differently now
+        self.dirty_meshes.insert(pos);
+        for neighbor in pos.surrounding() {
+            if self.chunks.contains_key(&neighbor) {
+                self.dirty_meshes.insert(neighbor);
+            }
+        }
+
+        self.emit(WorldEvent::ChunkLoaded(pos));
+        old
+    }
+
+    /// Unloads a chunk and returns it with whether it changed since it was
+    /// last saved, the caller has to save it then
+    pub fn remove_chunk(&mut self, pos: ChunkPos) -> Option<(Chunk, bool)> {
+        let ret = self.chunks.remove(&pos)?;
+        self.dirty_meshes.remove(&pos);
+        let dirty = self.dirty_saves.remove(&pos);
+        for neighbor in pos.surrounding() {
+            if self.chunks.contains_key(&neighbor) {
+                self.dirty_meshes.insert(neighbor);
+            }
+        }
+
+        self.emit(WorldEvent::ChunkUnloaded(pos));
+        Some((ret, dirty))
+    }
+
+    pub fn get_chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    /// Mutable access to a chunk, the chunk is marked as needing to be remeshed
+    /// and saved. Prefer `set_block` for single changes so neighbors are updated.
+    pub fn get_chunk_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
+        let chunk = self.chunks.get_mut(&pos)?;
+        self.dirty_meshes.insert(pos);
+        self.dirty_saves.insert(pos);
+        Some(chunk)
+    }
+
+    pub fn is_loaded(&self, pos: ChunkPos) -> bool {
+        self.chunks.contains_key(&pos)
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks.values()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// `None` if the chunk containing `pos` isn't loaded
+    pub fn get_block(&self, pos: BlockPos) -> Option<BlockState> {
+        self.chunks.get(&pos.chunk()).map(|chunk| chunk.get(pos.local()))
+    }
+
+    /// Sets a block and returns the previous state, or `None` if the chunk
+    /// containing `pos` isn't loaded.
+    pub fn set_block(&mut self, pos: BlockPos, state: BlockState) -> Option<BlockState> {
+        let chunk_pos = pos.chunk();
+        let local = pos.local();
+        let old = self.chunks.get_mut(&chunk_pos)?.set(local, state);
+        if old == state {
+            return Some(old);
+        }
+
+        self.dirty_meshes.insert(chunk_pos);
+        self.dirty_saves.insert(chunk_pos);
+
+        // Blocks on a border are visible from the neighboring chunks' meshes too,
+        // and blocks on an edge or corner shade the diagonal neighbors
+        let range = |v: u8| {
+            let min = if v == 0 { -1 } else { 0 };
+            let max = if v as usize == CHUNK_SIZE - 1 { 1 } else { 0 };
+            min..=max
+        };
+        for x in range(local.x) {
+            for y in range(local.y) {
+                for z in range(local.z) {
+                    let neighbor = chunk_pos.offset(x, y, z);
+                    if neighbor != chunk_pos && self.chunks.contains_key(&neighbor) {
+                        self.dirty_meshes.insert(neighbor);
+                    }
+                }
+            }
+        }
+
+        self.emit(WorldEvent::BlockChanged { pos, old, new: state });
+        Some(old)
+    }
+
+    /// Marks every loaded chunk for remeshing, e.g. after the block models changed
+    pub fn mark_all_meshes_dirty(&mut self) {
+        self.dirty_meshes.extend(self.chunks.keys().copied());
+    }
+
+    pub fn is_mesh_dirty(&self, pos: ChunkPos) -> bool {
+        self.dirty_meshes.contains(&pos)
+    }
+
+    /// Marks a loaded chunk as not saved yet, e.g. after generating it
+    pub fn mark_save_dirty(&mut self, pos: ChunkPos) {
+        if self.chunks.contains_key(&pos) {
+            self.dirty_saves.insert(pos);
+        }
+    }
+
+    pub fn is_save_dirty(&self, pos: ChunkPos) -> bool {
+        self.dirty_saves.contains(&pos)
+    }
+
+    /// Returns and clears the set of chunks that need a new mesh
+    pub fn take_dirty_meshes(&mut self) -> Vec<ChunkPos> {
+        self.dirty_meshes.drain().collect()
+    }
+
+    /// Returns and clears the set of chunks modified since they were last saved
+    pub fn take_dirty_saves(&mut self) -> Vec<ChunkPos> {
+        self.dirty_saves.drain().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::pos::LocalPos;
+
+    use super::*;
+
+    const STONE: BlockState = BlockState(1);
+
+    fn world_with_chunks(positions: &[(i32, i32, i32)]) -> World {
+        let mut world = World::new();
+        for (x, y, z) in positions {
+            world.insert_chunk(Chunk::new(ChunkPos::new(*x, *y, *z)));
+        }
+        world.take_dirty_meshes();
+        world
+    }
+
+    #[test]
+    fn get_set_across_chunks() {
+        let mut world = world_with_chunks(&[(0, 0, 0), (-1, 0, 0), (-1, -1, -1)]);
+
+        let positions = [
+            BlockPos::new(0, 0, 0),
+            BlockPos::new(15, 15, 15),
+            BlockPos::new(-1, 0, 0),
+            BlockPos::new(-16, 15, 0),
+            BlockPos::new(-1, -1, -1),
+            BlockPos::new(-16, -16, -16),
+        ];
+        for pos in positions {
+            assert_eq!(world.set_block(pos, STONE), Some(BlockState::AIR), "{:?}", pos);
+            assert_eq!(world.get_block(pos), Some(STONE), "{:?}", pos);
+        }
+
+        assert_eq!(world.get_chunk(ChunkPos::new(-1, 0, 0)).unwrap().get(LocalPos::new(15, 0, 0)), STONE);
+        assert_eq!(world.get_chunk(ChunkPos::new(-1, 0, 0)).unwrap().get(LocalPos::new(0, 15, 0)), STONE);
+        assert_eq!(world.get_chunk(ChunkPos::new(-1, -1, -1)).unwrap().get(LocalPos::new(0, 0, 0)), STONE);
+
+        // Not loaded
+        assert_eq!(world.get_block(BlockPos::new(-17, 0, 0)), None);
+        assert_eq!(world.set_block(BlockPos::new(0, 16, 0), STONE), None);
+    }
+
+    #[test]
+    fn dirty_tracking() {
+        let mut world = world_with_chunks(&[(0, 0, 0), (-1, 0, 0), (0, 1, 0)]);
+
+        world.set_block(BlockPos::new(5, 5, 5), STONE);
+        assert_eq!(world.take_dirty_meshes(), vec![ChunkPos::new(0, 0, 0)]);
+        assert_eq!(world.take_dirty_saves(), vec![ChunkPos::new(0, 0, 0)]);
+        assert!(world.take_dirty_meshes().is_empty());
+
+        // Border block, the loaded neighbor on -x needs a new mesh but isn't modified
+        world.set_block(BlockPos::new(0, 3, 3), STONE);
+        let mut dirty = world.take_dirty_meshes();
+        dirty.sort_by_key(|p| p.x);
+        assert_eq!(dirty, vec![ChunkPos::new(-1, 0, 0), ChunkPos::new(0, 0, 0)]);
+        assert_eq!(world.take_dirty_saves(), vec![ChunkPos::new(0, 0, 0)]);
+
+        // Setting the same state again is not a change
+        world.set_block(BlockPos::new(0, 3, 3), STONE);
+        assert!(world.take_dirty_meshes().is_empty());
+
+        // Unloaded chunks hand their changes back instead of keeping them dirty
+        world.set_block(BlockPos::new(5, 5, 5), BlockState::AIR);
+        assert!(world.remove_chunk(ChunkPos::new(0, 0, 0)).unwrap().1);
+        assert!(!world.remove_chunk(ChunkPos::new(-1, 0, 0)).unwrap().1);
+        assert!(world.take_dirty_saves().is_empty());
+    }
+
+    #[test]
+    fn dirty_tracking_diagonal() {
+        let mut world = world_with_chunks(&[(0, 0, 0), (-1, 0, 0), (0, 1, 0)]);
+
+        // Edge block, every loaded chunk touching the edge needs a new mesh
+        world.set_block(BlockPos::new(0, 15, 7), STONE);
+        let mut dirty = world.take_dirty_meshes();
+        dirty.sort();
+        assert_eq!(dirty, vec![ChunkPos::new(-1, 0, 0), ChunkPos::new(0, 0, 0), ChunkPos::new(0, 1, 0)]);
+
+        let mut world = world_with_chunks(&[(0, 0, 0), (-1, 1, 0)]);
+        world.set_block(BlockPos::new(0, 15, 7), STONE);
+        let mut dirty = world.take_dirty_meshes();
+        dirty.sort();
+        assert_eq!(dirty, vec![ChunkPos::new(-1, 1, 0), ChunkPos::new(0, 0, 0)]);
+    }
+
+    #[test]
+    fn notifications() {
+        let mut world = World::new();
+        let events = world.subscribe();
+        let dropped = world.subscribe();
+        drop(dropped);
+
+        world.insert_chunk(Chunk::new(ChunkPos::new(0, -1, 0)));
+        world.set_block(BlockPos::new(1, -2, 3), STONE);
+        world.set_block(BlockPos::new(1, -2, 3), STONE);
+        world.remove_chunk(ChunkPos::new(0, -1, 0));
+
+        let received: Vec<_> = events.try_iter().collect();
+        assert_eq!(received, vec![
+            WorldEvent::ChunkLoaded(ChunkPos::new(0, -1, 0)),
+            WorldEvent::BlockChanged { pos: BlockPos::new(1, -2, 3), old: BlockState::AIR, new: STONE },
+            WorldEvent::ChunkUnloaded(ChunkPos::new(0, -1, 0)),
+        ]);
+        assert_eq!(world.listeners.len(), 1);
+    }
+}
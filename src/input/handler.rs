@@ -24,34 +24,31 @@ pub struct InputHandler {
 
 impl InputHandler {
     pub fn process_event(&mut self, proxy: &mut EventLoopProxy<Events>, event: &WindowEvent) {
-        match event {
-            WindowEvent::KeyboardInput { input, .. } => {
-                if let Some(key) = input.virtual_keycode {
-                    let insert_state = match input.state {
-                        ElementState::Pressed => ButtonState::Pressed,
-                        ElementState::Released => ButtonState::Released,
-                    };
+        if let WindowEvent::KeyboardInput { input, .. } = event {
+            if let Some(key) = input.virtual_keycode {
+                let insert_state = match input.state {
+                    ElementState::Pressed => ButtonState::Pressed,
+                    ElementState::Released => ButtonState::Released,
+                };
 
-                    let mut prev_state = ButtonState::Released;
-                    if let Some(state) = self.key_states.get_mut(&key) {
-                        prev_state = *state;
-                        *state = insert_state
-                    } else {
-                        self.key_states.insert(key, insert_state);
-                    }
+                let mut prev_state = ButtonState::Released;
+                if let Some(state) = self.key_states.get_mut(&key) {
+                    prev_state = *state;
+                    *state = insert_state
+                } else {
+                    self.key_states.insert(key, insert_state);
+                }
 
-                    if prev_state != insert_state {
-                        proxy.send_event(Events::ButtonInput(ButtonInputEvent {
-                            key,
-                            state: match insert_state {
-                                ButtonState::Pressed => ButtonEventState::JustPressed,
-                                ButtonState::Released => ButtonEventState::JustReleased,
-                            }
-                        })).unwrap();
-                    }
+                if prev_state != insert_state {
+                    proxy.send_event(Events::ButtonInput(ButtonInputEvent {
+                        key,
+                        state: match insert_state {
+                            ButtonState::Pressed => ButtonEventState::JustPressed,
+                            ButtonState::Released => ButtonEventState::JustReleased,
+                        }
+                    })).unwrap();
                 }
-            },
-            _ => ()
+            }
         }
     }
 
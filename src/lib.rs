@@ -0,0 +1,9 @@
+pub mod main_loop;
+pub mod render;
+pub mod event;
+pub mod input;
+pub mod util;
+pub mod game;
+pub mod resources;
+pub mod save;
+pub mod worldgen;
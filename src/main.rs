@@ -1,10 +1,4 @@
-use main_loop::MainLoop;
-pub mod main_loop;
-pub mod render;
-pub mod event;
-pub mod input;
-pub mod util;
-pub mod game;
+use voxel::main_loop::MainLoop;
 
 fn main() {
     let main_loop = MainLoop::new();
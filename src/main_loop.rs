@@ -1,4 +1,4 @@
-use std::time::Instant;
+use std::{fs, time::Instant, sync::Arc, path::Path};
 
 use nalgebra::{Vector3, Point3};
 use winit::{
@@ -7,14 +7,25 @@ use winit::{
     window::{WindowBuilder, Window},
 };
 
-use crate::{render::{render_state::RenderState, camera::CameraController}, input::handler::{InputHandler, Movement}, event::events::Events, game::static_data::StaticBlockData};
+use crate::{render::{util::{animation::TICK, texture_atlas::TextureAtlas}, blockstates::BlockStateModels, render_state::{RenderState, SHADER_FILE}, settings::RenderSettings, camera::CameraController, mesher::{MeshTable, ChunkNeighborhood}, mesh_workers::MeshWorkerPool}, input::handler::{InputHandler, Movement}, event::events::{Events, ButtonEventState}, resources::{manager::ResourceManager, watcher::{AssetWatcher, WatchTarget, SHADER_DIR}}, game::{biome::BiomeRegistry, lang::{Localizer, FALLBACK_LANGUAGE}, static_data::StaticBlockData, registry::BlockRegistry, world::World, pos::ChunkPos}, save::{codec::Registries, region::RegionStorage, world::WorldInfo}, worldgen::{cave::CavePass, feature::{FeaturePass, load_features}, ore::{OrePass, load_ores}, pipeline::WorldGenerator, terrain::{TerrainBlocks, TerrainGenerator}}};
+
+/// Seed of a new world until worlds can be created with their own
+const WORLD_SEED: u64 = 0x5EED;
+
+/// Where the world is saved until there can be more than one
+const WORLD_DIR: &str = "saves/world";
 
 pub struct MainLoop {
     pub window: Window,
     event_loop: EventLoop<Events>,
     prev_frame_start: Instant,
     frame_times: Vec<f32>,
-    pub fps: f32,
+}
+
+impl Default for MainLoop {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MainLoop {
@@ -28,7 +39,6 @@ impl MainLoop {
             event_loop,
             prev_frame_start: Instant::now(),
             frame_times: Vec::with_capacity(30),
-            fps: 0.0,
         }
     }
 
@@ -36,18 +46,85 @@ impl MainLoop {
         env_logger::init();
         self.prev_frame_start = Instant::now();
 
-        let static_data = StaticBlockData::load();
+        let mut resources = match ResourceManager::discover(Path::new("assets"), Path::new("resourcepacks")) {
+            Ok(resources) => resources,
+            Err(error) => {
+                log::error!("{}", error);
+                std::process::exit(1);
+            }
+        };
+        let block_data = match StaticBlockData::load(&resources) {
+            Ok(data) => data,
+            Err(errors) => {
+                for error in errors.0 {
+                    log::error!("{}", error);
+                }
+                std::process::exit(1);
+            }
+        };
+        let mut block_registry = BlockRegistry::from(block_data);
+        let mut localizer = match Localizer::load(&resources, FALLBACK_LANGUAGE) {
+            Ok(localizer) => localizer,
+            Err(error) => {
+                log::error!("{}", error);
+                std::process::exit(1);
+            }
+        };
+        block_registry.localize(&localizer);
+        let biomes = match BiomeRegistry::load(&resources, &block_registry) {
+            Ok(biomes) => biomes,
+            Err(errors) => {
+                for error in errors.0 {
+                    log::error!("{}", error);
+                }
+                std::process::exit(1);
+            }
+        };
+        let (mesh_table, texture_atlas) = match load_block_meshes(&resources, &block_registry, &biomes) {
+            Ok(loaded) => loaded,
+            Err(errors) => {
+                for error in errors {
+                    log::error!("{}", error);
+                }
+                std::process::exit(1);
+            }
+        };
+        let mut mesh_workers = MeshWorkerPool::new(MeshWorkerPool::default_thread_count(), Arc::new(mesh_table));
+        let world_info = load_world_info(Path::new(WORLD_DIR));
+        let generator = match create_generator(world_info.seed, &resources, &block_registry, &biomes) {
+            Ok(generator) => generator,
+            Err(errors) => {
+                for error in errors {
+                    log::error!("{}", error);
+                }
+                std::process::exit(1);
+            }
+        };
+        let mut storage = RegionStorage::new(WORLD_DIR);
+        let mut world = create_world(&generator, &mut storage, Registries::new(&block_registry, &biomes));
 
-        let mut render_state = RenderState::new(&self.window).await;
+        let mut render_state = RenderState::new(&self.window, RenderSettings::default(), texture_atlas).await;
         let mut input_handler = InputHandler::default();
         let mut proxy = self.event_loop.create_proxy();
         let mut camera_controller = CameraController::new(Point3::new(0.0, 0.0, -5.0), 1.0);
+        let start = Instant::now();
+        let mut asset_watcher = if cfg!(debug_assertions) {
+            let roots: Vec<_> = resources.packs().iter()
+                .map(|pack| (pack.path(), WatchTarget::Resources))
+                .chain([(Path::new(SHADER_DIR), WatchTarget::Shader)])
+                .collect();
+            AssetWatcher::new(&roots)
+                .map_err(|error| log::warn!("Hot reloading is disabled: {}", error))
+                .ok()
+        } else {
+            None
+        };
     
         self.event_loop.run(move |event, _, control_flow| match event {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == self.window.id() => if !render_state.input(event) {
+            } if window_id == self.window.id() && !render_state.input(event) => {
                 match event {
                     WindowEvent::CloseRequested
                     | WindowEvent::KeyboardInput {
@@ -58,7 +135,10 @@ impl MainLoop {
                                 ..
                             },
                         ..
-                    } => *control_flow = ControlFlow::Exit,
+                    } => {
+                        save_world(&mut world, &mut storage, Registries::new(&block_registry, &biomes));
+                        *control_flow = ControlFlow::Exit;
+                    },
                     
                     WindowEvent::Resized(physical_size) => {
                         render_state.resize(*physical_size);
@@ -103,15 +183,42 @@ impl MainLoop {
 
                         camera_controller.position += sum * move_speed;
                     },
-                    Events::ButtonInput(_input) => {
-                        
+                    Events::ButtonInput(input) => {
+                        if input.key == VirtualKeyCode::F5 && matches!(input.state, ButtonEventState::JustPressed) {
+                            reload_resources(&mut resources, &mut block_registry, &mut localizer, &biomes, &mut render_state, &mut mesh_workers, &mut world);
+                        }
                     }
                 }
             }
 
             Event::RedrawRequested(window_id) if window_id == self.window.id() => {
+                if let Some(changes) = asset_watcher.as_mut().map(AssetWatcher::poll) {
+                    if changes.shader {
+                        reload_shader(&mut render_state);
+                    }
+                    if changes.resources {
+                        reload_resources(&mut resources, &mut block_registry, &mut localizer, &biomes, &mut render_state, &mut mesh_workers, &mut world);
+                    }
+                }
+
                 render_state.camera.transform = camera_controller.get_transform();
                 input_handler.process_input(&mut proxy);
+
+                mesh_workers.set_focus(camera_controller.position.into());
+                for pos in world.take_dirty_meshes() {
+                    match ChunkNeighborhood::from_world(&world, pos) {
+                        Some(neighborhood) => mesh_workers.submit(neighborhood, render_state.settings.meshing_mode),
+                        None => {
+                            mesh_workers.cancel(pos);
+                            render_state.remove_chunk_mesh(pos);
+                        },
+                    }
+                }
+                for mesh in mesh_workers.poll() {
+                    render_state.upload_chunk_mesh(&mesh);
+                }
+
+                render_state.animate_textures((start.elapsed().as_millis() / TICK.as_millis()) as u64);
                 render_state.update();
 
                 match render_state.render() {
@@ -133,9 +240,9 @@ impl MainLoop {
                     for frame_time in self.frame_times.iter() {
                         sum += frame_time;
                     }
-                    self.fps = 1.0 / (sum / (self.frame_times.len() as f32));
+                    let fps = 1.0 / (sum / (self.frame_times.len() as f32));
                     self.frame_times.clear();
-                    println!("Avg. fps: {:.2}", self.fps);
+                    println!("Avg. fps: {:.2}", fps);
                 }
                 self.prev_frame_start = Instant::now();
                 self.window.request_redraw();
@@ -143,4 +250,166 @@ impl MainLoop {
             _ => {}
         });
     }
-}
\ No newline at end of file
+}
+
+/// Bakes the block models and builds the atlas of every texture they use
+fn load_block_meshes(resources: &ResourceManager, registry: &BlockRegistry, biomes: &BiomeRegistry) -> Result<(MeshTable, TextureAtlas), Vec<String>> {
+    let models = BlockStateModels::load(resources, registry)
+        .map_err(|errors| errors.0.iter().map(ToString::to_string).collect::<Vec<_>>())?;
+    let mut texture_atlas = TextureAtlas::new();
+    let mesh_table = MeshTable::from_registry(registry, &models, biomes, &mut texture_atlas);
+    Ok((mesh_table, texture_atlas))
+}
+
+/// Everything a resource reload replaces
+struct LoadedResources {
+    resources: ResourceManager,
+    registry: BlockRegistry,
+    localizer: Localizer,
+    mesh_table: MeshTable,
+    texture_atlas: TextureAtlas,
+}
+
+/// Reopens the resource packs and reloads everything read from them that can
+/// change at runtime, without touching the current state. Blocks keep their
+/// IDs and properties, biomes need a restart to change since the world was
+/// generated with them.
+fn load_resources(resources: &ResourceManager, registry: &BlockRegistry, localizer: &Localizer, biomes: &BiomeRegistry) -> Result<LoadedResources, Vec<String>> {
+    let resources = resources.reopen().map_err(|error| vec![error.to_string()])?;
+    let block_data = StaticBlockData::load(&resources)
+        .map_err(|errors| errors.0.iter().map(ToString::to_string).collect::<Vec<_>>())?;
+    let localizer = Localizer::load(&resources, localizer.language()).map_err(|error| vec![error.to_string()])?;
+    let mut registry = registry.clone();
+    registry.update_models(block_data);
+    registry.localize(&localizer);
+    let (mesh_table, texture_atlas) = load_block_meshes(&resources, &registry, biomes)?;
+    Ok(LoadedResources { resources, registry, localizer, mesh_table, texture_atlas })
+}
+
+/// Reloads the resources and remeshes the world. Everything is swapped in at
+/// once, if anything is invalid the current state is kept and the errors
+/// are logged.
+fn reload_resources(
+    resources: &mut ResourceManager,
+    registry: &mut BlockRegistry,
+    localizer: &mut Localizer,
+    biomes: &BiomeRegistry,
+    render_state: &mut RenderState,
+    mesh_workers: &mut MeshWorkerPool,
+    world: &mut World,
+) {
+    match load_resources(resources, registry, localizer, biomes) {
+        Ok(loaded) => {
+            *resources = loaded.resources;
+            *registry = loaded.registry;
+            *localizer = loaded.localizer;
+            render_state.set_texture_atlas(loaded.texture_atlas);
+            *mesh_workers = MeshWorkerPool::new(MeshWorkerPool::default_thread_count(), Arc::new(loaded.mesh_table));
+            world.mark_all_meshes_dirty();
+            log::info!("Reloaded {} resource packs", resources.packs().len());
+        },
+        Err(errors) => {
+            for error in errors {
+                log::error!("Failed to reload resources: {}", error);
+            }
+        },
+    }
+}
+
+/// Recompiles the shader from `src/shader`, keeping the current one if it is invalid
+fn reload_shader(render_state: &mut RenderState) {
+    let path = Path::new(SHADER_DIR).join(SHADER_FILE);
+    let result = fs::read_to_string(&path)
+        .map_err(|error| error.to_string())
+        .and_then(|source| render_state.reload_shader(&source));
+    match result {
+        Ok(()) => log::info!("Reloaded `{}`", path.display()),
+        Err(error) => log::error!("Failed to reload `{}`: {}", path.display(), error),
+    }
+}
+
+/// Terrain, then caves carved into it, then ores in the remaining stone and
+/// trees and boulders on the surface
+fn create_generator(seed: u64, resources: &ResourceManager, registry: &BlockRegistry, biomes: &BiomeRegistry) -> Result<WorldGenerator, Vec<String>> {
+    let blocks = TerrainBlocks::from_registry(registry).map_err(|error| vec![error])?;
+    let ores = load_ores(resources, registry)
+        .map_err(|errors| errors.0.iter().map(ToString::to_string).collect::<Vec<_>>())?;
+    let features = load_features(resources, registry, biomes)
+        .map_err(|errors| errors.0.iter().map(ToString::to_string).collect::<Vec<_>>())?;
+    Ok(WorldGenerator::new()
+        .with_pass(TerrainGenerator::new(seed, blocks, biomes.clone()))
+        .with_pass(CavePass::new(seed, blocks.water))
+        .with_pass(OrePass::new(seed, ores))
+        .with_pass(FeaturePass::new(seed, biomes.clone(), features)))
+}
+
+/// The saved world's info, written first for a new world. A world file that
+/// can't be read stops the game rather than continuing the world with
+/// another seed.
+fn load_world_info(dir: &Path) -> WorldInfo {
+    match WorldInfo::load(dir) {
+        Ok(Some(info)) => info,
+        Ok(None) => {
+            let info = WorldInfo::new(WORLD_SEED);
+            if let Err(error) = info.save(dir) {
+                log::error!("Failed to save the world: {}", error);
+            }
+            info
+        },
+        Err(error) => {
+            log::error!("Failed to load the world: {}", error);
+            std::process::exit(1);
+        },
+    }
+}
+
+/// Chunks around the origin until chunks are loaded around the player.
+/// Saved chunks are loaded first, the missing ones are generated afterwards
+/// so they pick up the trees and boulders the saved chunks around them place
+/// into them. Generated chunks are saved right away so they don't have to be
+/// generated again. A saved chunk that can't be read stops the game like an
+/// unreadable world file, generating it again would overwrite it for good.
+fn create_world(generator: &WorldGenerator, storage: &mut RegionStorage, registries: Registries) -> World {
+    let mut world = World::new();
+    let mut missing = Vec::new();
+
+    for cx in -4..4 {
+        for cy in -3..3 {
+            for cz in -4..4 {
+                let pos = ChunkPos::new(cx, cy, cz);
+                match storage.load_chunk(pos, registries) {
+                    Ok(Some(chunk)) => {
+                        world.insert_chunk(chunk);
+                    },
+                    Ok(None) => missing.push(pos),
+                    Err(error) => {
+                        log::error!("Failed to load the world: {}", error);
+                        std::process::exit(1);
+                    },
+                }
+            }
+        }
+    }
+    for pos in missing {
+        generator.generate_into(&mut world, pos);
+        world.mark_save_dirty(pos);
+    }
+
+    save_world(&mut world, storage, registries);
+    world
+}
+
+/// Writes every chunk changed since it was loaded or saved
+fn save_world(world: &mut World, storage: &mut RegionStorage, registries: Registries) {
+    for pos in world.take_dirty_saves() {
+        // Chunks are only dirty while loaded, `remove_chunk` hands the others back
+        let chunk = world.get_chunk(pos).expect("dirty chunks are loaded");
+        if let Err(error) = storage.save_chunk(chunk, registries) {
+            log::error!("Failed to save chunk: {}", error);
+        }
+    }
+    match storage.flush() {
+        Ok(()) => log::info!("Saved the world to `{}`", storage.dir().display()),
+        Err(error) => log::error!("Failed to save the world: {}", error),
+    }
+}
@@ -0,0 +1,580 @@
+use std::sync::Arc;
+
+use image::DynamicImage;
+use nalgebra::Vector3;
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    game::{block::BlockState, block_state::StateDefinition, pos::Direction, registry::{BlockRegistry, RegisteredBlock}},
+    resources::{loader::{LoadErrors, parse_json}, manager::ResourceManager, pack::PackError},
+};
+
+use super::{model::{BakedModel, BakedQuad, ModelLoader, face_uv}, util::{animation::{ANIMATION_SUFFIX, AnimatedTexture, Animation}, shapes::Quad}};
+
+/// Directory inside of a resource pack holding one blockstate file per block
+pub const BLOCKSTATES_DIR: &str = "blockstates";
+
+/// A problem with one field of a blockstate file
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("blockstate `{block}`: `{field}`: {reason}")]
+pub struct BlockStateError {
+    pub block: String,
+    /// Path of the offending field, e.g. `variants.facing=north.x`
+    pub field: String,
+    pub reason: String,
+}
+
+/// A model placed with a rotation
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelVariant {
+    pub model: String,
+    /// Degrees around the x-axis, clockwise looking from the east, applied first
+    #[serde(default)]
+    pub x: u32,
+    /// Degrees around the y-axis, clockwise looking from above
+    #[serde(default)]
+    pub y: u32,
+    /// Keep the textures aligned with the world instead of rotating them along
+    #[serde(default)]
+    pub uvlock: bool,
+}
+
+/// `property: "value"` pairs that must all match, values can be alternatives
+/// separated by `|`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ConditionJson {
+    Or {
+        #[serde(rename = "OR")]
+        or: Vec<ConditionJson>,
+    },
+    And(FxHashMap<String, String>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PartJson {
+    /// Applies to every state when left out
+    when: Option<ConditionJson>,
+    apply: ModelVariant,
+}
+
+/// `blockstates/<name>.json`, with either `variants` or `multipart`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockStateJson {
+    /// `property=value,...` to the model of the states matching it, `""` for every state
+    variants: Option<FxHashMap<String, ModelVariant>>,
+    /// Every part whose condition matches is added to the state
+    multipart: Option<Vec<PartJson>>,
+}
+
+/// Which states a variant or part applies to, by property value index
+#[derive(Debug, Clone)]
+enum Rule {
+    /// Property index and the accepted value indices
+    All(Vec<(usize, Vec<usize>)>),
+    Any(Vec<Rule>),
+}
+
+impl Rule {
+    fn matches(&self, indices: &[usize]) -> bool {
+        match self {
+            Rule::All(values) => values.iter().all(|(property, accepted)| accepted.contains(&indices[*property])),
+            Rule::Any(rules) => rules.iter().any(|rule| rule.matches(indices)),
+        }
+    }
+}
+
+/// Baked quads of every block state with a blockstate file, along with the
+/// textures they use
+#[derive(Default)]
+pub struct BlockStateModels {
+    /// Indexed by state ID, `None` for blocks without a blockstate file.
+    /// States using the same models share their quads.
+    states: Vec<Option<Arc<[BakedQuad]>>>,
+    textures: FxHashMap<String, DynamicImage>,
+    /// Animations of the textures that have an animation file
+    animations: FxHashMap<String, Animation>,
+}
+
+impl BlockStateModels {
+    /// Reads `blockstates/<name>.json` of every registered block that has one,
+    /// along with the models and textures they use
+    pub fn load(resources: &ResourceManager, registry: &BlockRegistry) -> Result<Self, LoadErrors<BlockStateError>> {
+        let files = resources.list(BLOCKSTATES_DIR).map_err(|e| LoadErrors(vec![BlockStateError {
+            block: String::new(),
+            field: String::new(),
+            reason: e.to_string(),
+        }]))?;
+
+        let mut sources = FxHashMap::default();
+        let mut errors = Vec::new();
+        for block in registry.iter() {
+            let file = format!("{}.json", block.name);
+            if !files.contains(&file) {
+                continue;
+            }
+            match resources.read_to_string(&format!("{}/{}", BLOCKSTATES_DIR, file)) {
+                Ok(json) => { sources.insert(block.name.clone(), json); },
+                Err(e) => errors.push(BlockStateError {
+                    block: block.name.clone(),
+                    field: String::new(),
+                    reason: e.to_string(),
+                }),
+            }
+        }
+
+        let mut ret = match Self::bake(registry, &sources, &mut ModelLoader::new(resources)) {
+            Ok(ret) if errors.is_empty() => ret,
+            Ok(_) => return Err(LoadErrors(errors)),
+            Err(e) => {
+                errors.extend(e.0);
+                return Err(LoadErrors(errors));
+            },
+        };
+
+        // First block using each texture, for the error messages
+        let mut used: Vec<(&str, String)> = Vec::new();
+        for block in registry.iter() {
+            for quad in block.state_ids().flat_map(|state| ret.get(state).unwrap_or_default()) {
+                if !used.iter().any(|(_, texture)| *texture == quad.texture) {
+                    used.push((&block.name, quad.texture.clone()));
+                }
+            }
+        }
+
+        for (block, texture) in used {
+            let image = match resources.load_image(&texture) {
+                Ok(image) => image,
+                Err(e) => {
+                    errors.push(BlockStateError {
+                        block: block.to_string(),
+                        field: String::new(),
+                        reason: format!("failed to load texture: {}", e),
+                    });
+                    continue;
+                },
+            };
+            match load_animation(resources, &texture, &image) {
+                Ok(Some(animation)) => { ret.animations.insert(texture.clone(), animation); },
+                Ok(None) => {},
+                Err(reason) => errors.push(BlockStateError {
+                    block: block.to_string(),
+                    field: String::new(),
+                    reason,
+                }),
+            }
+            ret.textures.insert(texture, image);
+        }
+
+        if errors.is_empty() {
+            Ok(ret)
+        } else {
+            Err(LoadErrors(errors))
+        }
+    }
+
+    /// Bakes the blockstate JSON of every block in `sources`, keyed by block
+    /// name. Textures are not loaded.
+    pub fn bake(registry: &BlockRegistry, sources: &FxHashMap<String, String>, loader: &mut ModelLoader) -> Result<Self, LoadErrors<BlockStateError>> {
+        let mut ret = Self {
+            states: vec![None; registry.state_count()],
+            textures: FxHashMap::default(),
+            animations: FxHashMap::default(),
+        };
+        let mut models = FxHashMap::default();
+        let mut errors = Vec::new();
+
+        for block in registry.iter() {
+            let Some(json) = sources.get(&block.name) else { continue };
+            match bake_block(block, json, loader, &mut models) {
+                Ok(states) => {
+                    for (state, quads) in block.state_ids().zip(states) {
+                        ret.states[state.0 as usize] = Some(quads);
+                    }
+                },
+                Err(e) => errors.push(e),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(ret)
+        } else {
+            Err(LoadErrors(errors))
+        }
+    }
+
+    /// Quads of a state in block space, `None` if its block has no blockstate file
+    pub fn get(&self, state: BlockState) -> Option<&[BakedQuad]> {
+        self.states.get(state.0 as usize)?.as_deref()
+    }
+
+    /// Texture path and image of every texture used by the baked quads
+    pub fn textures(&self) -> impl Iterator<Item = (&str, &DynamicImage)> {
+        self.textures.iter().map(|(path, image)| (path.as_str(), image))
+    }
+
+    /// Animation of a texture, `None` if it is a still image
+    pub fn animation(&self, texture: &str) -> Option<&Animation> {
+        self.animations.get(texture)
+    }
+}
+
+/// Reads the animation file next to a texture, if there is one
+fn load_animation(resources: &ResourceManager, texture: &str, image: &DynamicImage) -> Result<Option<Animation>, String> {
+    let file = format!("{}{}", texture, ANIMATION_SUFFIX);
+    let json = match resources.read_to_string(&file) {
+        Ok(json) => json,
+        Err(PackError::NotFound(_)) => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let origin = resources.origin(&file);
+    let strip_len = AnimatedTexture::strip_len(image).ok_or_else(|| format!(
+        "{}: `{}` is {}x{}, which isn't a strip of square frames",
+        origin.display(), texture, image.width(), image.height(),
+    ))?;
+    Animation::parse(&json, strip_len).map(Some).map_err(|e| format!("{}: {}", origin.display(), e))
+}
+
+/// Quads of every state of one block, in state order
+fn bake_block(
+    block: &RegisteredBlock,
+    json: &str,
+    loader: &mut ModelLoader,
+    models: &mut FxHashMap<String, BakedModel>,
+) -> Result<Vec<Arc<[BakedQuad]>>, BlockStateError> {
+    let error = |field: &str, reason: String| BlockStateError {
+        block: block.name.clone(),
+        field: field.to_string(),
+        reason,
+    };
+
+    let parsed: BlockStateJson = parse_json(json).map_err(|(field, reason)| error(&field, reason))?;
+
+    let states = &block.states;
+    let (rules, multipart) = match (parsed.variants, parsed.multipart) {
+        (Some(variants), None) => {
+            let mut variants: Vec<_> = variants.into_iter().collect();
+            variants.sort_by(|a, b| a.0.cmp(&b.0));
+
+            let mut rules = Vec::new();
+            for (key, variant) in variants {
+                let field = format!("variants.{}", key);
+                let pairs = key.split(',').filter(|pair| !pair.is_empty()).map(|pair| {
+                    pair.split_once('=').ok_or_else(|| error(&field, format!("`{}` is not `property=value`", pair)))
+                }).collect::<Result<Vec<_>, _>>()?;
+                let rule = parse_condition(states, pairs.into_iter(), &field).map_err(|(field, reason)| error(&field, reason))?;
+                rules.push((field, rule, variant));
+            }
+            (rules, false)
+        },
+        (None, Some(parts)) => {
+            let mut rules = Vec::new();
+            for (i, part) in parts.into_iter().enumerate() {
+                let field = format!("multipart[{}]", i);
+                let rule = match &part.when {
+                    Some(condition) => compile_condition(states, condition, &format!("{}.when", field))
+                        .map_err(|(field, reason)| error(&field, reason))?,
+                    None => Rule::All(Vec::new()),
+                };
+                rules.push((format!("{}.apply", field), rule, part.apply));
+            }
+            (rules, true)
+        },
+        _ => return Err(error("", "expected exactly one of `variants` and `multipart`".to_string())),
+    };
+
+    for (field, _, variant) in &rules {
+        for (axis, degrees) in [("x", variant.x), ("y", variant.y)] {
+            if degrees % 90 != 0 || degrees >= 360 {
+                return Err(error(&format!("{}.{}", field, axis), format!("{} is not 0, 90, 180 or 270", degrees)));
+            }
+        }
+        if !models.contains_key(&variant.model) {
+            let model = loader.bake(&variant.model).map_err(|e| error(&format!("{}.model", field), e.to_string()))?;
+            models.insert(variant.model.clone(), model);
+        }
+    }
+
+    // States applying the same variants share their quads
+    let mut baked: FxHashMap<Vec<&ModelVariant>, Arc<[BakedQuad]>> = FxHashMap::default();
+    let mut ret = Vec::with_capacity(states.state_count());
+    for offset in 0..states.state_count() {
+        let indices = states.value_indices(offset);
+        let applied: Vec<_> = rules.iter().filter(|(_, rule, _)| rule.matches(&indices)).collect();
+
+        if !multipart && applied.len() != 1 {
+            let state = states.properties().iter().zip(&indices)
+                .map(|(p, i)| format!("{}={}", p.name, p.values[*i]))
+                .collect::<Vec<_>>()
+                .join(",");
+            let reason = match applied.as_slice() {
+                [] => format!("no variant matches `{}`", state),
+                [a, b, ..] => format!("`{}` and `{}` both match `{}`", a.0, b.0, state),
+                _ => unreachable!(),
+            };
+            return Err(error("variants", reason));
+        }
+
+        let variants: Vec<_> = applied.iter().map(|(_, _, variant)| variant).collect();
+        let quads = baked.entry(variants.clone()).or_insert_with(|| {
+            variants.iter()
+                .flat_map(|variant| models[&variant.model].quads.iter().map(|quad| rotate_quad(quad, variant)))
+                .collect()
+        });
+        ret.push(quads.clone());
+    }
+
+    Ok(ret)
+}
+
+fn compile_condition(states: &StateDefinition, condition: &ConditionJson, field: &str) -> Result<Rule, (String, String)> {
+    match condition {
+        ConditionJson::Or { or } => {
+            let rules = or.iter().enumerate()
+                .map(|(i, condition)| compile_condition(states, condition, &format!("{}.OR[{}]", field, i)))
+                .collect::<Result<_, _>>()?;
+            Ok(Rule::Any(rules))
+        },
+        ConditionJson::And(values) => {
+            let mut values: Vec<_> = values.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+            values.sort();
+            parse_condition(states, values.into_iter(), field)
+        },
+    }
+}
+
+/// Looks up every `property`, `value|value...` pair in the block's properties
+fn parse_condition<'a>(
+    states: &StateDefinition,
+    pairs: impl Iterator<Item = (&'a str, &'a str)>,
+    field: &str,
+) -> Result<Rule, (String, String)> {
+    let mut ret = Vec::new();
+    for (name, values) in pairs {
+        let (i, property) = states.property(name)
+            .ok_or_else(|| (field.to_string(), format!("unknown property `{}`", name)))?;
+        let accepted = values.split('|').map(|value| {
+            property.value_index(value)
+                .ok_or_else(|| (field.to_string(), format!("`{}` is not a value of `{}`", value, name)))
+        }).collect::<Result<_, _>>()?;
+        ret.push((i, accepted));
+    }
+    Ok(Rule::All(ret))
+}
+
+/// Rotates a vector relative to the block center by whole quarter turns
+fn rotate(v: Vector3<f32>, x: u32, y: u32) -> Vector3<f32> {
+    let mut v = v;
+    for _ in 0..x / 90 {
+        v = Vector3::new(v.x, v.z, -v.y);
+    }
+    for _ in 0..y / 90 {
+        v = Vector3::new(-v.z, v.y, v.x);
+    }
+    v
+}
+
+fn rotate_direction(dir: Direction, x: u32, y: u32) -> Direction {
+    let normal = rotate(dir.normal(), x, y);
+    Direction::ALL.into_iter()
+        .find(|d| d.normal().dot(&normal) > 0.5)
+        .expect("quarter turns keep directions axis aligned")
+}
+
+fn rotate_quad(quad: &BakedQuad, variant: &ModelVariant) -> BakedQuad {
+    let (x, y) = (variant.x, variant.y);
+    let center = Vector3::repeat(0.5);
+    let positions = quad.quad.get_vertex_positions().map(|pos| rotate(pos - center, x, y) + center);
+    let rotated = Quad::new_unchecked(positions);
+
+    let mut uvs = quad.uvs;
+    if variant.uvlock {
+        // Project the texture onto the side the face ended up on
+        let normal = rotated.normal();
+        if let Some(dir) = Direction::ALL.into_iter().find(|d| d.normal().dot(&normal) > 0.999) {
+            uvs = positions.map(|pos| face_uv(dir, pos));
+        }
+    }
+
+    BakedQuad {
+        quad: rotated,
+        uvs,
+        texture: quad.texture.clone(),
+        cullface: quad.cullface.map(|dir| rotate_direction(dir, x, y)),
+        shade: quad.shade,
+        tint: quad.tint,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use crate::game::{block_state::Property, registry::BlockProperties, static_data::StaticBlockData};
+
+    use super::*;
+
+    const MODELS: &[(&str, &str)] = &[
+        ("cube", r##"{
+            "textures": { "all": "stone.png" },
+            "elements": [{
+                "from": [0, 0, 0], "to": [16, 16, 16],
+                "faces": {
+                    "down": { "texture": "#all", "cullface": "down" },
+                    "up": { "texture": "#all", "cullface": "up" },
+                    "north": { "texture": "#all", "cullface": "north" },
+                    "south": { "texture": "#all", "cullface": "south" },
+                    "west": { "texture": "#all", "cullface": "west" },
+                    "east": { "texture": "#all", "cullface": "east" }
+                }
+            }]
+        }"##),
+        // Half block at the north side
+        ("half", r#"{
+            "elements": [{
+                "from": [0, 0, 0], "to": [16, 16, 8],
+                "faces": {
+                    "up": { "texture": "top.png", "cullface": "up" },
+                    "north": { "texture": "side.png", "cullface": "north" }
+                }
+            }]
+        }"#),
+        ("post", r#"{ "elements": [{ "from": [6, 0, 6], "to": [10, 16, 10], "faces": { "up": { "texture": "post.png" } } }] }"#),
+        ("arm", r#"{ "elements": [{ "from": [7, 12, 0], "to": [9, 15, 6], "faces": { "north": { "texture": "post.png", "cullface": "north" } } }] }"#),
+    ];
+
+    fn registry() -> BlockRegistry {
+        let mut registry = BlockRegistry::new();
+        registry.register("stone", "Stone", BlockProperties::default());
+        registry.register_with_states("half", "Half", BlockProperties::default(), vec![
+            Property::new("facing", &["north", "east", "south", "west", "up", "down"]),
+            Property::half(),
+        ]);
+        registry.register_with_states("fence", "Fence", BlockProperties::default(), vec![
+            Property::of::<bool>("north"),
+            Property::of::<bool>("east"),
+        ]);
+        registry
+    }
+
+    fn bake(blockstates: &[(&str, &str)]) -> Result<(BlockRegistry, BlockStateModels), LoadErrors<BlockStateError>> {
+        let registry = registry();
+        let sources = blockstates.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let mut loader = ModelLoader::from_sources(MODELS.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect());
+        let models = BlockStateModels::bake(&registry, &sources, &mut loader)?;
+        Ok((registry, models))
+    }
+
+    const HALF: &str = r#"{
+        "variants": {
+            "facing=north": { "model": "half" },
+            "facing=east": { "model": "half", "y": 90 },
+            "facing=south": { "model": "half", "y": 180, "uvlock": true },
+            "facing=west": { "model": "half", "y": 270 },
+            "facing=up": { "model": "half", "x": 270 },
+            "facing=down": { "model": "half", "x": 90 }
+        }
+    }"#;
+
+    fn bounds(quads: &[BakedQuad]) -> (Vector3<f32>, Vector3<f32>) {
+        let positions = quads.iter().flat_map(|quad| quad.quad.get_vertex_positions().iter());
+        positions.fold((Vector3::repeat(f32::MAX), Vector3::repeat(f32::MIN)), |(min, max), p| (min.inf(p), max.sup(p)))
+    }
+
+    #[test]
+    fn variants_rotate_models() {
+        let (registry, models) = bake(&[("half", HALF)]).unwrap();
+        assert!(models.get(registry.parse_state("stone").unwrap()).is_none());
+
+        let quads = |state: &str| models.get(registry.parse_state(state).unwrap()).unwrap();
+        let cullfaces = |state: &str| quads(state).iter().map(|quad| quad.cullface.unwrap()).collect::<Vec<_>>();
+
+        assert_eq!(cullfaces("half[facing=north]"), vec![Direction::Up, Direction::North]);
+        assert_eq!(cullfaces("half[facing=east]"), vec![Direction::Up, Direction::East]);
+        assert_eq!(cullfaces("half[facing=south]"), vec![Direction::Up, Direction::South]);
+        assert_eq!(cullfaces("half[facing=west]"), vec![Direction::Up, Direction::West]);
+        assert_eq!(cullfaces("half[facing=up]"), vec![Direction::South, Direction::Up]);
+        assert_eq!(cullfaces("half[facing=down]"), vec![Direction::North, Direction::Down]);
+
+        let (min, max) = bounds(quads("half[facing=east]"));
+        assert_eq!((min, max), (Vector3::new(0.5, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0)));
+        let (min, max) = bounds(quads("half[facing=down]"));
+        assert_eq!((min, max), (Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.5, 1.0)));
+
+        // Winding still faces outwards
+        for quad in quads("half[facing=west]") {
+            assert_eq!(quad.quad.normal(), quad.cullface.unwrap().normal());
+        }
+
+        // Every state with the same facing shares its quads
+        let top = models.get(registry.parse_state("half[facing=east,half=top]").unwrap()).unwrap();
+        assert!(std::ptr::eq(top, quads("half[facing=east,half=bottom]")));
+    }
+
+    #[test]
+    fn uvlock_keeps_textures_aligned() {
+        let (registry, models) = bake(&[("half", HALF)]).unwrap();
+        let quads = |state: &str| models.get(registry.parse_state(state).unwrap()).unwrap();
+
+        // Rotated 180 degrees the top face's texture is upside down without the lock
+        let up = &quads("half[facing=south]")[0];
+        for (pos, uv) in up.quad.get_vertex_positions().iter().zip(up.uvs) {
+            assert_eq!(uv, Vector2::new(pos.x, pos.z));
+        }
+    }
+
+    #[test]
+    fn multipart() {
+        let (registry, models) = bake(&[("fence", r#"{
+            "multipart": [
+                { "apply": { "model": "post" } },
+                { "when": { "north": "true" }, "apply": { "model": "arm" } },
+                { "when": { "east": "true" }, "apply": { "model": "arm", "y": 90 } },
+                { "when": { "OR": [{ "north": "true" }, { "east": "true" }] }, "apply": { "model": "post", "x": 180 } }
+            ]
+        }"#)]).unwrap();
+        let quads = |state: &str| models.get(registry.parse_state(state).unwrap()).unwrap();
+
+        assert_eq!(quads("fence[north=false,east=false]").len(), 1);
+        assert_eq!(quads("fence[north=true,east=false]").len(), 3);
+        assert_eq!(quads("fence[north=false,east=true]").len(), 3);
+        let both: Vec<_> = quads("fence[north=true,east=true]").iter().map(|quad| quad.cullface).collect();
+        assert_eq!(both, vec![None, Some(Direction::North), Some(Direction::East), None]);
+    }
+
+    #[test]
+    fn errors() {
+        let field = |json: &str| bake(&[("half", json)]).err().unwrap().0[0].field.clone();
+
+        assert_eq!(field(r#"{ "variants": { "color=red": { "model": "half" } } }"#), "variants.color=red");
+        assert_eq!(field(r#"{ "variants": { "facing=sideways": { "model": "half" } } }"#), "variants.facing=sideways");
+        assert_eq!(field(r#"{ "variants": { "facing=north": { "model": "half" } } }"#), "variants");
+        assert_eq!(field(r#"{ "variants": { "": { "model": "half" }, "half=top": { "model": "half" } } }"#), "variants");
+        assert_eq!(field(r#"{ "variants": { "": { "model": "half", "y": 45 } } }"#), "variants..y");
+        assert_eq!(field(r#"{ "variants": { "": { "model": "missing" } } }"#), "variants..model");
+        assert_eq!(field(r#"{ "multipart": [{ "when": { "north": "true" }, "apply": { "model": "half" } }] }"#), "multipart[0].when");
+        assert_eq!(field(r#"{ "variants": { "": { "model": "half", "z": 90 } } }"#), "variants..z");
+        assert_eq!(field(r#"{ }"#), "");
+
+        // Errors of every block are collected
+        let errors = bake(&[("half", r#"{ }"#), ("stone", r#"{ "variants": { "": { "model": "missing" } } }"#)]).err().unwrap();
+        assert_eq!(errors.0.len(), 2);
+    }
+
+    #[test]
+    fn shipped_blockstates_load() {
+        let resources = ResourceManager::open(&["assets"]).unwrap();
+        let registry = BlockRegistry::from_static_data(StaticBlockData::load(&resources).unwrap());
+        let models = BlockStateModels::load(&resources, &registry).unwrap_or_else(|e| panic!("{}", e));
+
+        let stone = models.get(registry.parse_state("stone").unwrap()).unwrap();
+        assert_eq!(stone.len(), 6);
+        assert!(stone.iter().all(|quad| models.textures().any(|(path, _)| path == quad.texture)));
+    }
+}
@@ -1,3 +1,34 @@
+use wgpu::{Device, util::DeviceExt};
+
+use crate::game::pos::ChunkPos;
+
+use super::mesher::ChunkMesh;
+
 pub struct Buffers {
     
-}
\ No newline at end of file
+}
+
+/// GPU copy of a chunk mesh
+pub struct ChunkBuffer {
+    pub pos: ChunkPos,
+    pub vertex_buffer: wgpu::Buffer,
+    pub vertex_count: u32,
+}
+
+impl ChunkBuffer {
+    pub fn new(device: &Device, mesh: &ChunkMesh) -> Self {
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Chunk Vertex Buffer"),
+                contents: bytemuck::cast_slice(mesh.vertices.as_slice()),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+
+        Self {
+            pos: mesh.pos,
+            vertex_buffer,
+            vertex_count: mesh.vertices.len() as u32,
+        }
+    }
+}
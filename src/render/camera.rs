@@ -6,7 +6,7 @@ use winit::event::DeviceEvent;
 
 use crate::util::constants::DEFAULT_MOUSE_SENS;
 
-use super::util::math::perspective;
+use super::util::math::{perspective, perspective_reversed_z};
 
 #[derive(Debug)]
 pub struct Camera {
@@ -16,6 +16,8 @@ pub struct Camera {
     pub fov_y: f32,
     pub z_near: f32,
     pub z_far: f32,
+    /// Project the near plane to a depth of 1 and the far plane to 0
+    pub reversed_z: bool,
     pub buffer: Option<wgpu::Buffer>,
 }
 
@@ -28,6 +30,7 @@ impl Default for Camera {
             fov_y: 45.0, 
             z_near: 0.01, 
             z_far: 10000.0,
+            reversed_z: false,
             buffer: None,
         }
     }
@@ -35,7 +38,11 @@ impl Default for Camera {
 
 impl Camera {
     pub fn calculate_projection_matrix(&self) -> Matrix4<f32> {
-        let proj = perspective(self.fov_y, self.aspect, self.z_near, self.z_far);
+        let proj = if self.reversed_z {
+            perspective_reversed_z(self.fov_y, self.aspect, self.z_near, self.z_far)
+        } else {
+            perspective(self.fov_y, self.aspect, self.z_near, self.z_far)
+        };
         proj * self.transform.to_matrix()
     }
 
@@ -48,7 +55,7 @@ impl Camera {
             }
         );
         self.buffer = Some(buffer);
-        &self.buffer.as_ref().unwrap()
+        self.buffer.as_ref().unwrap()
     }
 
     pub fn get_bind_group_and_layout(&mut self, device: &Device) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
@@ -103,6 +110,12 @@ impl CameraUniform {
     }
 }
 
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl From<&Camera> for CameraUniform {
     fn from(camera: &Camera) -> Self {
         Self {
@@ -38,7 +38,7 @@ impl FaceLighting {
             }
         );
         self.buffer = Some(buffer);
-        &self.buffer.as_ref().unwrap()
+        self.buffer.as_ref().unwrap()
     }
 
     pub fn get_bind_group_and_layout(&mut self, device: &Device) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
@@ -0,0 +1,257 @@
+use std::{
+    sync::{mpsc::{self, Receiver, Sender}, Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::game::pos::ChunkPos;
+
+use super::{mesher::{ChunkNeighborhood, MeshTable, ChunkMesh, mesh_chunk}, settings::MeshingMode};
+
+struct MeshJob {
+    neighborhood: ChunkNeighborhood,
+    mode: MeshingMode,
+    generation: u64,
+}
+
+struct MeshResult {
+    mesh: ChunkMesh,
+    generation: u64,
+}
+
+/// Jobs waiting for a worker, at most one per chunk
+#[derive(Default)]
+struct JobQueue {
+    jobs: FxHashMap<ChunkPos, MeshJob>,
+    /// Jobs closest to this chunk are handed out first
+    focus: ChunkPos,
+    shutdown: bool,
+}
+
+impl JobQueue {
+    /// Queues a job, replacing any job for the same chunk that hasn't started yet
+    fn push(&mut self, job: MeshJob) {
+        self.jobs.insert(job.neighborhood.pos, job);
+    }
+
+    fn pop(&mut self) -> Option<MeshJob> {
+        let pos = *self.jobs.keys().min_by_key(|pos| (pos.distance_squared(self.focus), **pos))?;
+        self.jobs.remove(&pos)
+    }
+}
+
+struct Shared {
+    queue: Mutex<JobQueue>,
+    available: Condvar,
+}
+
+/// Meshes chunks on background threads. Chunks are submitted as snapshots of
+/// their blocks and finished meshes are picked up with `poll` on the render
+/// thread. Resubmitting or cancelling a chunk discards its older jobs.
+pub struct MeshWorkerPool {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+    results: Receiver<MeshResult>,
+    /// Generation of the newest job of every chunk whose mesh hasn't been received yet
+    pending: FxHashMap<ChunkPos, u64>,
+    next_generation: u64,
+}
+
+impl MeshWorkerPool {
+    pub fn new(threads: usize, table: Arc<MeshTable>) -> Self {
+        assert!(threads > 0, "mesh worker pool needs at least one thread");
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(JobQueue::default()),
+            available: Condvar::new(),
+        });
+        let (sender, results) = mpsc::channel();
+
+        let workers = (0..threads).map(|i| {
+            let shared = shared.clone();
+            let table = table.clone();
+            let sender = sender.clone();
+            thread::Builder::new()
+                .name(format!("mesh worker {}", i))
+                .spawn(move || worker(&shared, &table, sender))
+                .expect("failed to spawn mesh worker")
+        }).collect();
+
+        Self {
+            shared,
+            workers,
+            results,
+            pending: FxHashMap::default(),
+            next_generation: 0,
+        }
+    }
+
+    /// One thread per core, leaving one for the render thread
+    pub fn default_thread_count() -> usize {
+        thread::available_parallelism().map_or(1, |n| n.get().saturating_sub(1).max(1))
+    }
+
+    /// Queues a chunk to be meshed, superseding any earlier job for the same chunk
+    pub fn submit(&mut self, neighborhood: ChunkNeighborhood, mode: MeshingMode) {
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        self.pending.insert(neighborhood.pos, generation);
+
+        self.shared.queue.lock().unwrap().push(MeshJob { neighborhood, mode, generation });
+        self.shared.available.notify_one();
+    }
+
+    /// Drops any queued or running job for a chunk, e.g. when it is unloaded
+    pub fn cancel(&mut self, pos: ChunkPos) {
+        self.pending.remove(&pos);
+        self.shared.queue.lock().unwrap().jobs.remove(&pos);
+    }
+
+    /// Prioritizes chunks nearest to `pos`, usually the chunk containing the camera
+    pub fn set_focus(&mut self, pos: ChunkPos) {
+        self.shared.queue.lock().unwrap().focus = pos;
+    }
+
+    /// Finished meshes of the latest job of each chunk, without blocking
+    pub fn poll(&mut self) -> Vec<ChunkMesh> {
+        let mut ret = Vec::new();
+        for result in self.results.try_iter() {
+            let pos = result.mesh.pos;
+            if self.pending.get(&pos) == Some(&result.generation) {
+                self.pending.remove(&pos);
+                ret.push(result.mesh);
+            }
+        }
+        ret
+    }
+
+    /// Number of chunks whose newest mesh hasn't been returned by `poll` yet
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+impl Drop for MeshWorkerPool {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().shutdown = true;
+        self.shared.available.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn worker(shared: &Shared, table: &MeshTable, results: Sender<MeshResult>) {
+    loop {
+        let job = {
+            let mut queue = shared.queue.lock().unwrap();
+            loop {
+                if queue.shutdown {
+                    return;
+                }
+                if let Some(job) = queue.pop() {
+                    break job;
+                }
+                queue = shared.available.wait(queue).unwrap();
+            }
+        };
+
+        let mesh = mesh_chunk(&job.neighborhood, table, job.mode);
+        if results.send(MeshResult { mesh, generation: job.generation }).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::game::{block::BlockState, chunk::Chunk, pos::LocalPos};
+    use crate::render::mesher::StateMeshInfo;
+
+    use super::*;
+
+    const STONE: BlockState = BlockState(1);
+
+    fn table() -> Arc<MeshTable> {
+        Arc::new(MeshTable::new(vec![
+            StateMeshInfo { visible: false, opaque: false, textures: [0; 6], tints: [None; 6], quads: None },
+            StateMeshInfo { visible: true, opaque: true, textures: [0; 6], tints: [None; 6], quads: None },
+        ]))
+    }
+
+    /// A chunk with `blocks` separate stone blocks, `6 * blocks` quads
+    fn neighborhood(pos: ChunkPos, blocks: usize) -> ChunkNeighborhood {
+        let mut chunk = Chunk::new(pos);
+        for i in 0..blocks {
+            chunk.set(LocalPos::new(i * 2 % 16, i * 2 / 16 * 2, 0), STONE);
+        }
+        ChunkNeighborhood::new(&chunk, [None; 6])
+    }
+
+    fn wait_for_all(pool: &mut MeshWorkerPool) -> Vec<ChunkMesh> {
+        let start = Instant::now();
+        let mut ret = Vec::new();
+        while pool.pending_count() > 0 {
+            assert!(start.elapsed() < Duration::from_secs(10), "meshing timed out");
+            ret.extend(pool.poll());
+            thread::sleep(Duration::from_millis(1));
+        }
+        ret
+    }
+
+    #[test]
+    fn meshes_every_chunk() {
+        let mut pool = MeshWorkerPool::new(3, table());
+        for x in 0..8 {
+            pool.submit(neighborhood(ChunkPos::new(x, 0, 0), x as usize + 1), MeshingMode::Naive);
+        }
+
+        let mut meshes = wait_for_all(&mut pool);
+        meshes.sort_by_key(|mesh| mesh.pos);
+        assert_eq!(meshes.len(), 8);
+        for (x, mesh) in meshes.iter().enumerate() {
+            assert_eq!(mesh.pos, ChunkPos::new(x as i32, 0, 0));
+            assert_eq!(mesh.quad_count(), 6 * (x + 1));
+        }
+    }
+
+    #[test]
+    fn only_latest_job_is_returned() {
+        let mut pool = MeshWorkerPool::new(2, table());
+        let pos = ChunkPos::new(0, 0, 0);
+        for blocks in 1..=20 {
+            pool.submit(neighborhood(pos, blocks), MeshingMode::Naive);
+        }
+        pool.submit(neighborhood(ChunkPos::new(5, 0, 0), 1), MeshingMode::Naive);
+        pool.cancel(ChunkPos::new(5, 0, 0));
+
+        let meshes = wait_for_all(&mut pool);
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].pos, pos);
+        assert_eq!(meshes[0].quad_count(), 6 * 20);
+        assert!(pool.poll().is_empty());
+    }
+
+    #[test]
+    fn nearest_jobs_first() {
+        let mut queue = JobQueue { focus: ChunkPos::new(10, 0, 0), ..Default::default() };
+        for x in [0, 12, 7, 10, 30] {
+            queue.push(MeshJob {
+                neighborhood: neighborhood(ChunkPos::new(x, 0, 0), 0),
+                mode: MeshingMode::Naive,
+                generation: 0,
+            });
+        }
+        // Replaces the queued job instead of adding another one
+        queue.push(MeshJob {
+            neighborhood: neighborhood(ChunkPos::new(7, 0, 0), 0),
+            mode: MeshingMode::Naive,
+            generation: 1,
+        });
+
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop()).map(|job| job.neighborhood.pos.x).collect();
+        assert_eq!(order, vec![10, 12, 7, 0, 30]);
+    }
+}
@@ -0,0 +1,957 @@
+use nalgebra::{Vector3, Vector2};
+use rustc_hash::FxHashMap;
+
+use crate::game::{
+    biome::{BiomeId, BiomeRegistry, TintType},
+    block::BlockState,
+    chunk::{Chunk, CHUNK_AREA, CHUNK_SIZE},
+    pos::{ChunkPos, Direction, LocalPos},
+    registry::BlockRegistry,
+    world::World,
+};
+
+use super::{
+    blockstates::BlockStateModels,
+    model::{self, BakedQuad},
+    settings::MeshingMode,
+    util::{animation::AnimatedTexture, cube_model::DEFAULT_CUBE_MODEL_QUADS, vertex::VertexRaw, shapes::Quad, texture_atlas::TextureAtlas},
+};
+
+/// Width of a chunk plus a one block border on each side
+pub const PADDED_SIZE: usize = CHUNK_SIZE + 2;
+
+/// What the mesher needs to know about every block state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StateMeshInfo {
+    /// Whether the block has any geometry at all
+    pub visible: bool,
+    /// Whether the block hides the faces of its neighbors
+    pub opaque: bool,
+    /// Atlas pointer index of every face, indexed by `Direction`
+    pub textures: [u32; 6],
+    /// Biome color of every face, indexed by `Direction`
+    pub tints: [Option<TintType>; 6],
+    /// First index and count of the state's quads in the table, `None` for
+    /// full cubes, which are meshed from `textures` and can be merged
+    pub quads: Option<(u32, u32)>,
+}
+
+/// A quad of a block model in block space
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelQuad {
+    pub positions: [Vector3<f32>; 4],
+    /// Texture coordinates of every corner, `0.0..=1.0` across the texture
+    pub uvs: [Vector2<f32>; 4],
+    /// Atlas pointer index
+    pub texture: u32,
+    /// Hidden when the neighbor in this direction is opaque
+    pub cullface: Option<Direction>,
+    pub tint: Option<TintType>,
+}
+
+/// Mesh info for every block state, indexed by state ID, the quads of every
+/// state that isn't a full cube and the tint colors of every biome
+#[derive(Debug, Clone, Default)]
+pub struct MeshTable {
+    states: Vec<StateMeshInfo>,
+    quads: Vec<ModelQuad>,
+    /// Linear grass and foliage colors, indexed by `BiomeId`
+    biome_tints: Vec<[[f32; 3]; 2]>,
+}
+
+impl MeshTable {
+    pub fn new(states: Vec<StateMeshInfo>) -> Self {
+        Self { states, quads: Vec::new(), biome_tints: Vec::new() }
+    }
+
+    /// `quads` are referenced by the `quads` ranges of `states`
+    pub fn with_quads(states: Vec<StateMeshInfo>, quads: Vec<ModelQuad>) -> Self {
+        Self { states, quads, biome_tints: Vec::new() }
+    }
+
+    /// Takes the tint colors from `biomes`, without them tinted faces stay white
+    pub fn with_biome_tints(mut self, biomes: &BiomeRegistry) -> Self {
+        self.biome_tints = biomes.iter()
+            .map(|(_, biome)| [TintType::Grass, TintType::Foliage].map(|tint| biome.tint(tint).to_linear()))
+            .collect();
+        self
+    }
+
+    /// Adds the textures of every block model to `atlas`. States with baked
+    /// blockstate models use those, the others fall back to their block's cube model.
+    pub fn from_registry(registry: &BlockRegistry, models: &BlockStateModels, biomes: &BiomeRegistry, atlas: &mut TextureAtlas) -> Self {
+        let blocks: Vec<_> = registry.iter().map(|block| {
+            let textures = block.model.as_ref().map_or([0; 6], |model| {
+                let indices: Vec<_> = model.textures.iter()
+                    .map(|texture| atlas.add_texture(texture.clone()))
+                    .collect();
+                model.face_textures.map(|i| indices[i])
+            });
+
+            StateMeshInfo {
+                visible: block.model.is_some(),
+                opaque: block.properties.is_opaque(),
+                textures,
+                tints: block.model.as_ref().map_or([None; 6], |model| model.tints),
+                quads: None,
+            }
+        }).collect();
+
+        // Sorted so atlas indices don't depend on hash order
+        let mut textures: Vec<_> = models.textures().collect();
+        textures.sort_by_key(|(path, _)| *path);
+        let texture_indices: FxHashMap<&str, u32> = textures.into_iter()
+            .map(|(path, image)| (path, match models.animation(path) {
+                Some(animation) => atlas.add_animated_texture(AnimatedTexture::new(image, animation.clone())),
+                None => atlas.add_texture(image.clone()),
+            }))
+            .collect();
+
+        let mut quads = Vec::new();
+        // States sharing their baked quads share them in the table as well
+        let mut ranges: FxHashMap<*const BakedQuad, (u32, u32)> = FxHashMap::default();
+        let states = (0..registry.state_count() as u32).map(|state| {
+            let block = blocks[registry.block_of(BlockState(state)).0 as usize];
+            let Some(baked) = models.get(BlockState(state)) else { return block };
+            let texture = |quad: &BakedQuad| texture_indices.get(quad.texture.as_str()).copied().unwrap_or(0);
+
+            if let Some((textures, tints)) = cube_textures(baked, texture) {
+                return StateMeshInfo { visible: true, textures, tints, quads: None, ..block };
+            }
+
+            let range = *ranges.entry(baked.as_ptr()).or_insert_with(|| {
+                let first = quads.len() as u32;
+                quads.extend(baked.iter().map(|quad| ModelQuad {
+                    positions: *quad.quad.get_vertex_positions(),
+                    uvs: quad.uvs,
+                    texture: texture(quad),
+                    cullface: quad.cullface,
+                    tint: quad.tint,
+                }));
+                (first, baked.len() as u32)
+            });
+            StateMeshInfo { visible: true, textures: [0; 6], tints: [None; 6], quads: Some(range), ..block }
+        }).collect();
+
+        Self::with_quads(states, quads).with_biome_tints(biomes)
+    }
+
+    /// Unknown states are treated like air
+    pub fn get(&self, state: BlockState) -> StateMeshInfo {
+        self.states.get(state.0 as usize).copied().unwrap_or_default()
+    }
+
+    /// The model quads of a state, empty for full cubes
+    pub fn quads(&self, info: &StateMeshInfo) -> &[ModelQuad] {
+        info.quads.map_or(&[], |(first, count)| &self.quads[first as usize..(first + count) as usize])
+    }
+
+    /// Vertex color of a face in `biome`, white if it isn't tinted
+    pub fn tint(&self, tint: Option<TintType>, biome: BiomeId) -> [f32; 3] {
+        let colors = tint.zip(self.biome_tints.get(biome.0 as usize));
+        colors.map_or([1.0; 3], |(tint, colors)| colors[tint as usize])
+    }
+}
+
+/// Face textures and tints of a baked model that is just the six faces of a
+/// full block with the default texture coordinates, so it can be meshed like any cube
+fn cube_textures(quads: &[BakedQuad], texture: impl Fn(&BakedQuad) -> u32) -> Option<([u32; 6], [Option<TintType>; 6])> {
+    let mut ret = [None; 6];
+    let mut tints = [None; 6];
+    for quad in quads {
+        let dir = quad.cullface?;
+        let expected = face_quad(dir, Vector3::zeros(), Vector3::repeat(1.0));
+        let positions = quad.quad.get_vertex_positions();
+        let matches = positions.iter().zip(expected.get_vertex_positions()).zip(quad.uvs)
+            .all(|((pos, expected), uv)| (pos - expected).norm() < 1e-5 && (uv - model::face_uv(dir, *pos)).norm() < 1e-5);
+        if !matches || !quad.shade || ret[dir.index()].is_some() {
+            return None;
+        }
+        ret[dir.index()] = Some(texture(quad));
+        tints[dir.index()] = quad.tint;
+    }
+
+    let mut textures = [0; 6];
+    for (texture, found) in textures.iter_mut().zip(ret) {
+        *texture = found?;
+    }
+    Some((textures, tints))
+}
+
+/// Copy of a chunk's blocks surrounded by the adjacent layer of each of its
+/// 26 neighbors, which is everything needed to mesh the chunk on its own.
+#[derive(Debug, Clone)]
+pub struct ChunkNeighborhood {
+    pub pos: ChunkPos,
+    blocks: Vec<BlockState>,
+    /// Biomes of the chunk's own columns
+    biomes: Vec<BiomeId>,
+}
+
+impl ChunkNeighborhood {
+    /// Builds the neighborhood of `chunk` from its face neighbors only, indexed
+    /// by `Direction`. Missing neighbors are treated as air.
+    pub fn new(chunk: &Chunk, neighbors: [Option<&Chunk>; 6]) -> Self {
+        Self::from_fn(chunk, |pos| {
+            Direction::ALL.into_iter()
+                .find(|dir| chunk.pos.neighbor(*dir) == pos)
+                .and_then(|dir| neighbors[dir.index()])
+        })
+    }
+
+    /// Builds the neighborhood of `chunk`, looking up each surrounding chunk
+    /// with `get_chunk`. Missing neighbors are treated as air.
+    pub fn from_fn<'a>(chunk: &Chunk, get_chunk: impl Fn(ChunkPos) -> Option<&'a Chunk>) -> Self {
+        let mut ret = Self {
+            pos: chunk.pos,
+            blocks: vec![BlockState::AIR; PADDED_SIZE * PADDED_SIZE * PADDED_SIZE],
+            biomes: chunk.biomes().iter().copied().collect(),
+        };
+        debug_assert_eq!(ret.biomes.len(), CHUNK_AREA);
+
+        for (pos, state) in chunk.iter() {
+            ret.set(pos.x as i32, pos.y as i32, pos.z as i32, state);
+        }
+
+        let size = CHUNK_SIZE as i32;
+        for neighbor_pos in chunk.pos.surrounding() {
+            let Some(neighbor) = get_chunk(neighbor_pos) else { continue };
+            let offset = [
+                neighbor_pos.x - chunk.pos.x,
+                neighbor_pos.y - chunk.pos.y,
+                neighbor_pos.z - chunk.pos.z,
+            ];
+
+            // Only the layer of the neighbor touching this chunk, along each
+            // axis the neighbor is offset in
+            let range = |offset: i32| match offset {
+                -1 => -1..=-1,
+                1 => size..=size,
+                _ => 0..=size - 1,
+            };
+            for y in range(offset[1]) {
+                for z in range(offset[2]) {
+                    for x in range(offset[0]) {
+                        let local = LocalPos::new(
+                            x.rem_euclid(size) as usize,
+                            y.rem_euclid(size) as usize,
+                            z.rem_euclid(size) as usize,
+                        );
+                        ret.set(x, y, z, neighbor.get(local));
+                    }
+                }
+            }
+        }
+
+        ret
+    }
+
+    /// `None` if the chunk at `pos` isn't loaded
+    pub fn from_world(world: &World, pos: ChunkPos) -> Option<Self> {
+        let chunk = world.get_chunk(pos)?;
+        Some(Self::from_fn(chunk, |pos| world.get_chunk(pos)))
+    }
+
+    /// Block at a position relative to the chunk's origin, each component in `-1..=CHUNK_SIZE`
+    pub fn get(&self, x: i32, y: i32, z: i32) -> BlockState {
+        self.blocks[Self::index(x, y, z)]
+    }
+
+    /// Biome of a column of the chunk, each component in `0..CHUNK_SIZE`
+    pub fn biome(&self, x: i32, z: i32) -> BiomeId {
+        self.biomes[z as usize * CHUNK_SIZE + x as usize]
+    }
+
+    fn set(&mut self, x: i32, y: i32, z: i32, state: BlockState) {
+        self.blocks[Self::index(x, y, z)] = state;
+    }
+
+    fn index(x: i32, y: i32, z: i32) -> usize {
+        let (x, y, z) = ((x + 1) as usize, (y + 1) as usize, (z + 1) as usize);
+        debug_assert!(x < PADDED_SIZE && y < PADDED_SIZE && z < PADDED_SIZE);
+        (y * PADDED_SIZE + z) * PADDED_SIZE + x
+    }
+}
+
+/// CPU side mesh of a single chunk in world space
+#[derive(Debug, Clone)]
+pub struct ChunkMesh {
+    pub pos: ChunkPos,
+    pub vertices: Vec<VertexRaw>,
+}
+
+impl ChunkMesh {
+    pub fn quad_count(&self) -> usize {
+        self.vertices.len() / 6
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+}
+
+/// Emits quads for every face of a visible block that isn't hidden by an opaque neighbor
+pub fn mesh_chunk(neighborhood: &ChunkNeighborhood, table: &MeshTable, mode: MeshingMode) -> ChunkMesh {
+    let faces = match mode {
+        MeshingMode::Naive => naive_faces(neighborhood, table),
+        MeshingMode::Greedy => greedy_faces(neighborhood, table),
+    };
+
+    let origin = neighborhood.pos.origin();
+    let origin = Vector3::new(origin.x, origin.y, origin.z);
+    let mut vertices = Vec::with_capacity(faces.len() * 6);
+
+    for face in faces {
+        let quad = face_quad(face.dir, (origin + face.min).cast(), (origin + face.max).cast());
+        let local = face_quad(face.dir, face.min.cast(), face.max.cast());
+        let info = table.get(face.state);
+        let texture = info.textures[face.dir.index()];
+        let tint = table.tint(info.tints[face.dir.index()], face.biome);
+
+        let mut corners = quad.get_corners();
+        for (i, corner) in corners.iter_mut().enumerate() {
+            corner.ao = face.ao[i] as u32;
+            corner.texture = texture;
+            corner.tint = tint;
+            corner.tex_coord = face_uv(face.dir, local.get_vertex_positions()[i]);
+        }
+
+        // Split along the diagonal between the less occluded corners so the
+        // shading doesn't depend on the orientation of the quad
+        let flipped = face.ao[0] + face.ao[2] > face.ao[1] + face.ao[3];
+        vertices.extend(Quad::triangulate(corners, flipped).map(VertexRaw::from));
+    }
+
+    vertices.extend(model_vertices(neighborhood, table));
+
+    ChunkMesh {
+        pos: neighborhood.pos,
+        vertices,
+    }
+}
+
+/// Vertices of every state with model quads, skipping the quads whose cullface
+/// neighbor is opaque. Model quads are never merged and aren't occluded.
+fn model_vertices(neighborhood: &ChunkNeighborhood, table: &MeshTable) -> Vec<VertexRaw> {
+    let origin = neighborhood.pos.origin();
+    let origin = Vector3::new(origin.x, origin.y, origin.z);
+    let size = CHUNK_SIZE as i32;
+    let mut ret = Vec::new();
+
+    for y in 0..size {
+        for z in 0..size {
+            for x in 0..size {
+                let info = table.get(neighborhood.get(x, y, z));
+                let offset = (origin + Vector3::new(x, y, z)).cast();
+                for quad in table.quads(&info) {
+                    if let Some(dir) = quad.cullface {
+                        let (dx, dy, dz) = dir.offset();
+                        if table.get(neighborhood.get(x + dx, y + dy, z + dz)).opaque {
+                            continue;
+                        }
+                    }
+
+                    let tint = table.tint(quad.tint, neighborhood.biome(x, z));
+                    let mut corners = Quad::new_unchecked(quad.positions.map(|pos| pos + offset)).get_corners();
+                    for (corner, uv) in corners.iter_mut().zip(quad.uvs) {
+                        corner.tex_coord = uv;
+                        corner.texture = quad.texture;
+                        corner.tint = tint;
+                    }
+                    ret.extend(Quad::triangulate(corners, false).map(VertexRaw::from));
+                }
+            }
+        }
+    }
+
+    ret
+}
+
+/// One side of an axis aligned box of blocks that share the same state, in
+/// block coordinates relative to the chunk's origin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Face {
+    dir: Direction,
+    state: BlockState,
+    min: Vector3<i32>,
+    max: Vector3<i32>,
+    /// Occlusion of every corner, in the same order as the unit cube quads
+    ao: [u8; 4],
+    /// Biome of the first block, every block of a tinted face shares it
+    biome: BiomeId,
+}
+
+/// Whether the cube face of the block at `pos` pointing in `dir` can be seen
+fn face_visible(neighborhood: &ChunkNeighborhood, table: &MeshTable, pos: Vector3<i32>, dir: Direction) -> bool {
+    let (dx, dy, dz) = dir.offset();
+    let info = table.get(neighborhood.get(pos.x, pos.y, pos.z));
+    info.visible && info.quads.is_none()
+        && !table.get(neighborhood.get(pos.x + dx, pos.y + dy, pos.z + dz)).opaque
+}
+
+/// Counts the opaque blocks around each corner of a block face, in the layer
+/// the face points into. Two occluding sides hide the corner completely.
+fn face_ao(neighborhood: &ChunkNeighborhood, table: &MeshTable, pos: Vector3<i32>, dir: Direction) -> [u8; 4] {
+    let (dx, dy, dz) = dir.offset();
+    let front = pos + Vector3::new(dx, dy, dz);
+    let n = dir.axis() as usize;
+    let (u, v) = ((n + 1) % 3, (n + 2) % 3);
+    let opaque = |p: Vector3<i32>| table.get(neighborhood.get(p.x, p.y, p.z)).opaque as u8;
+
+    DEFAULT_CUBE_MODEL_QUADS[dir.index()].get_vertex_positions().map(|corner| {
+        let mut side_u = Vector3::zeros();
+        side_u[u] = corner[u].signum() as i32;
+        let mut side_v = Vector3::zeros();
+        side_v[v] = corner[v].signum() as i32;
+
+        let (a, b) = (opaque(front + side_u), opaque(front + side_v));
+        if a + b == 2 {
+            3
+        } else {
+            a + b + opaque(front + side_u + side_v)
+        }
+    })
+}
+
+fn naive_faces(neighborhood: &ChunkNeighborhood, table: &MeshTable) -> Vec<Face> {
+    let mut faces = Vec::new();
+    let size = CHUNK_SIZE as i32;
+
+    for y in 0..size {
+        for z in 0..size {
+            for x in 0..size {
+                let pos = Vector3::new(x, y, z);
+                for dir in Direction::ALL {
+                    if face_visible(neighborhood, table, pos, dir) {
+                        faces.push(Face {
+                            dir,
+                            state: neighborhood.get(x, y, z),
+                            min: pos,
+                            max: pos.add_scalar(1),
+                            ao: face_ao(neighborhood, table, pos, dir),
+                            biome: neighborhood.biome(x, z),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    faces
+}
+
+/// State, corner occlusion and biome of a tinted face, faces only merge if these match
+type FaceKey = (BlockState, [u8; 4], Option<BiomeId>);
+
+/// Sweeps every layer of the chunk in each direction and merges runs of
+/// matching faces into rectangles, first along `u` and then along `v`. Faces
+/// only match if their corners are evenly occluded, since merging anything
+/// else would stretch the shading over the whole rectangle. Tinted faces also
+/// need the same biome.
+fn greedy_faces(neighborhood: &ChunkNeighborhood, table: &MeshTable) -> Vec<Face> {
+    let mut faces = Vec::new();
+    let size = CHUNK_SIZE;
+    let mut mask: Vec<Option<FaceKey>> = vec![None; size * size];
+
+    for dir in Direction::ALL {
+        // Axis along the face normal, and the two axes spanning the face
+        let n = dir.axis() as usize;
+        let (u, v) = ((n + 1) % 3, (n + 2) % 3);
+        let at = |d: usize, a: usize, b: usize| {
+            let mut pos = Vector3::zeros();
+            pos[n] = d as i32;
+            pos[u] = a as i32;
+            pos[v] = b as i32;
+            pos
+        };
+
+        for d in 0..size {
+            for b in 0..size {
+                for a in 0..size {
+                    let pos = at(d, a, b);
+                    mask[b * size + a] = face_visible(neighborhood, table, pos, dir).then(|| {
+                        let state = neighborhood.get(pos.x, pos.y, pos.z);
+                        let tinted = table.get(state).tints[dir.index()].is_some();
+                        (state, face_ao(neighborhood, table, pos, dir), tinted.then(|| neighborhood.biome(pos.x, pos.z)))
+                    });
+                }
+            }
+
+            for b in 0..size {
+                let mut a = 0;
+                while a < size {
+                    let Some(key @ (state, ao, biome)) = mask[b * size + a] else {
+                        a += 1;
+                        continue;
+                    };
+                    let mergeable = ao.iter().all(|corner| *corner == ao[0]);
+                    let limit = if mergeable { size } else { 1 };
+
+                    let mut width = 1;
+                    while a + width < size && width < limit && mask[b * size + a + width] == Some(key) {
+                        width += 1;
+                    }
+
+                    let mut height = 1;
+                    'grow: while b + height < size && height < limit {
+                        for i in 0..width {
+                            if mask[(b + height) * size + a + i] != Some(key) {
+                                break 'grow;
+                            }
+                        }
+                        height += 1;
+                    }
+
+                    for row in b..b + height {
+                        mask[row * size + a..row * size + a + width].fill(None);
+                    }
+
+                    faces.push(Face {
+                        dir,
+                        state,
+                        min: at(d, a, b),
+                        max: at(d + 1, a + width, b + height),
+                        ao,
+                        biome: biome.unwrap_or_default(),
+                    });
+                    a += width;
+                }
+            }
+        }
+    }
+
+    faces
+}
+
+/// The side of the box `min..max` facing `dir`, with the same winding as the
+/// unit cube quads.
+fn face_quad(dir: Direction, min: Vector3<f32>, max: Vector3<f32>) -> Quad {
+    let unit = DEFAULT_CUBE_MODEL_QUADS[dir.index()].get_vertex_positions();
+    Quad::new_unchecked(unit.map(|corner| {
+        Vector3::from_fn(|i, _| if corner[i] < 0.0 { min[i] } else { max[i] })
+    }))
+}
+
+/// Texture coordinates of a chunk local position on a face, one unit per
+/// block. Textures on the sides are upright and every face is seen unmirrored
+/// from the outside.
+fn face_uv(dir: Direction, pos: Vector3<f32>) -> Vector2<f32> {
+    match dir {
+        Direction::East => Vector2::new(-pos.z, -pos.y),
+        Direction::West => Vector2::new(pos.z, -pos.y),
+        Direction::Up => Vector2::new(pos.x, pos.z),
+        Direction::Down => Vector2::new(pos.x, -pos.z),
+        Direction::South => Vector2::new(pos.x, -pos.y),
+        Direction::North => Vector2::new(-pos.x, -pos.y),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: BlockState = BlockState(1);
+    const GLASS: BlockState = BlockState(2);
+
+    fn table() -> MeshTable {
+        MeshTable::new(vec![
+            StateMeshInfo { visible: false, opaque: false, textures: [0; 6], tints: [None; 6], quads: None },
+            StateMeshInfo { visible: true, opaque: true, textures: [1, 1, 0, 2, 1, 1], tints: [None; 6], quads: None },
+            StateMeshInfo { visible: true, opaque: false, textures: [3; 6], tints: [None; 6], quads: None },
+        ])
+    }
+
+    fn mesh(chunk: &Chunk, neighbors: [Option<&Chunk>; 6]) -> ChunkMesh {
+        mesh_chunk(&ChunkNeighborhood::new(chunk, neighbors), &table(), MeshingMode::Naive)
+    }
+
+    #[test]
+    fn empty_chunk() {
+        let chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        assert!(mesh(&chunk, [None; 6]).is_empty());
+    }
+
+    #[test]
+    fn single_block() {
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        chunk.set(LocalPos::new(4, 4, 4), STONE);
+        assert_eq!(mesh(&chunk, [None; 6]).quad_count(), 6);
+    }
+
+    #[test]
+    fn adjacent_blocks_hide_shared_faces() {
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        chunk.set(LocalPos::new(4, 4, 4), STONE);
+        chunk.set(LocalPos::new(5, 4, 4), STONE);
+        assert_eq!(mesh(&chunk, [None; 6]).quad_count(), 10);
+
+        // 2x2x2 cube
+        for x in 4..6 {
+            for y in 4..6 {
+                for z in 4..6 {
+                    chunk.set(LocalPos::new(x, y, z), STONE);
+                }
+            }
+        }
+        assert_eq!(mesh(&chunk, [None; 6]).quad_count(), 24);
+    }
+
+    #[test]
+    fn transparent_neighbors_show_faces() {
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        chunk.set(LocalPos::new(4, 4, 4), STONE);
+        chunk.set(LocalPos::new(5, 4, 4), GLASS);
+        // Stone shows all 6 faces, glass hides the face towards stone
+        assert_eq!(mesh(&chunk, [None; 6]).quad_count(), 11);
+    }
+
+    #[test]
+    fn full_chunk_uses_neighbors() {
+        let chunk = Chunk::filled(ChunkPos::new(0, 0, 0), STONE);
+        assert_eq!(mesh(&chunk, [None; 6]).quad_count(), 6 * CHUNK_SIZE * CHUNK_SIZE);
+
+        let full = Chunk::filled(ChunkPos::new(0, 0, 0), STONE);
+        assert_eq!(mesh(&chunk, [Some(&full); 6]).quad_count(), 0);
+
+        // Only the +Y neighbor is missing
+        let mut neighbors = [Some(&full); 6];
+        neighbors[Direction::Up.index()] = None;
+        assert_eq!(mesh(&chunk, neighbors).quad_count(), CHUNK_SIZE * CHUNK_SIZE);
+    }
+
+    #[test]
+    fn neighbor_border_slices() {
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        chunk.set(LocalPos::new(15, 3, 3), STONE);
+        chunk.set(LocalPos::new(3, 0, 3), STONE);
+
+        let mut east = Chunk::new(ChunkPos::new(1, 0, 0));
+        east.set(LocalPos::new(0, 3, 3), STONE);
+        let mut down = Chunk::new(ChunkPos::new(0, -1, 0));
+        down.set(LocalPos::new(3, 15, 3), STONE);
+
+        let mut neighbors = [None; 6];
+        neighbors[Direction::East.index()] = Some(&east);
+        neighbors[Direction::Down.index()] = Some(&down);
+
+        let neighborhood = ChunkNeighborhood::new(&chunk, neighbors);
+        assert_eq!(neighborhood.get(16, 3, 3), STONE);
+        assert_eq!(neighborhood.get(3, -1, 3), STONE);
+        assert_eq!(neighborhood.get(-1, 3, 3), BlockState::AIR);
+        assert_eq!(mesh_chunk(&neighborhood, &table(), MeshingMode::Naive).quad_count(), 10);
+    }
+
+    #[test]
+    fn vertices_in_world_space() {
+        let mut chunk = Chunk::new(ChunkPos::new(-1, 2, 0));
+        chunk.set(LocalPos::new(0, 0, 0), STONE);
+        let mesh = mesh(&chunk, [None; 6]);
+
+        for v in &mesh.vertices {
+            assert!((-16.0..=-15.0).contains(&v.position[0]));
+            assert!((32.0..=33.0).contains(&v.position[1]));
+            assert!((0.0..=1.0).contains(&v.position[2]));
+        }
+    }
+
+    /// Every unit face covered by `faces`, panicking if any two faces overlap
+    fn unit_faces(faces: &[Face]) -> Vec<(Direction, BlockState, Vector3<i32>, [u8; 4])> {
+        let mut ret = Vec::new();
+        for face in faces {
+            let n = face.dir.axis() as usize;
+            assert_eq!(face.max[n] - face.min[n], 1);
+            for x in face.min.x..face.max.x {
+                for y in face.min.y..face.max.y {
+                    for z in face.min.z..face.max.z {
+                        ret.push((face.dir, face.state, Vector3::new(x, y, z), face.ao));
+                    }
+                }
+            }
+        }
+
+        let len = ret.len();
+        ret.sort_by_key(|(dir, state, pos, ao)| (*dir, *state, pos.x, pos.y, pos.z, *ao));
+        ret.dedup();
+        assert_eq!(ret.len(), len, "faces overlap");
+        ret
+    }
+
+    fn assert_equivalent(neighborhood: &ChunkNeighborhood) -> (usize, usize) {
+        let naive = naive_faces(neighborhood, &table());
+        let greedy = greedy_faces(neighborhood, &table());
+        assert_eq!(unit_faces(&naive), unit_faces(&greedy));
+        (naive.len(), greedy.len())
+    }
+
+    /// Rolling hills of stone with a glass layer at sea level
+    fn terrain_chunk(pos: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::new(pos);
+        let origin = pos.origin();
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let (wx, wz) = ((origin.x + x as i32) as f32, (origin.z + z as i32) as f32);
+                let height = (8.0 + 3.0 * (wx * 0.2).sin() + 3.0 * (wz * 0.15).cos()) as usize;
+                for y in 0..CHUNK_SIZE {
+                    if y < height {
+                        chunk.set(LocalPos::new(x, y, z), STONE);
+                    } else if y < 6 {
+                        chunk.set(LocalPos::new(x, y, z), GLASS);
+                    }
+                }
+            }
+        }
+        chunk
+    }
+
+    #[test]
+    fn greedy_merges_flat_surfaces() {
+        let chunk = Chunk::filled(ChunkPos::new(0, 0, 0), STONE);
+        let neighborhood = ChunkNeighborhood::new(&chunk, [None; 6]);
+        assert_eq!(assert_equivalent(&neighborhood), (6 * CHUNK_SIZE * CHUNK_SIZE, 6));
+
+        let mesh = mesh_chunk(&neighborhood, &table(), MeshingMode::Greedy);
+        assert_eq!(mesh.quad_count(), 6);
+        // The texture repeats once per block
+        for v in &mesh.vertices {
+            assert!(v.tex_coord.iter().all(|c| c.abs() == 0.0 || c.abs() == 16.0), "{:?}", v.tex_coord);
+        }
+    }
+
+    #[test]
+    fn greedy_keeps_states_apart() {
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        for x in 0..4 {
+            chunk.set(LocalPos::new(x, 0, 0), if x % 2 == 0 { STONE } else { GLASS });
+        }
+        let (naive, greedy) = assert_equivalent(&ChunkNeighborhood::new(&chunk, [None; 6]));
+        assert_eq!(naive, 4 * 4 + 2 + 3);
+        // Alternating states leave nothing to merge, stone faces behind glass stay visible
+        assert_eq!(greedy, naive);
+    }
+
+    #[test]
+    fn greedy_matches_naive_on_terrain() {
+        let mut total_naive = 0;
+        let mut total_greedy = 0;
+        for cx in -2..2 {
+            for cz in -2..2 {
+                let chunk = terrain_chunk(ChunkPos::new(cx, 0, cz));
+                let (naive, greedy) = assert_equivalent(&ChunkNeighborhood::new(&chunk, [None; 6]));
+                total_naive += naive;
+                total_greedy += greedy;
+            }
+        }
+
+        assert!(total_greedy * 2 < total_naive, "terrain quads: naive {}, greedy {}", total_naive, total_greedy);
+    }
+
+    #[test]
+    fn ambient_occlusion() {
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        chunk.set(LocalPos::new(5, 5, 5), STONE);
+        chunk.set(LocalPos::new(6, 6, 5), STONE);
+        chunk.set(LocalPos::new(5, 6, 6), STONE);
+        let mesh = mesh(&chunk, [None; 6]);
+
+        // Top of the block at the bottom of the step
+        let top: Vec<_> = mesh.vertices.iter()
+            .filter(|v| v.normal == [0.0, 1.0, 0.0] && v.position[1] == 6.0)
+            .collect();
+        assert_eq!(top.len(), 6);
+
+        let ao_at = |x: f32, z: f32| top.iter().find(|v| v.position[0] == x && v.position[2] == z).unwrap().ao;
+        assert_eq!(ao_at(5.0, 5.0), 0);
+        assert_eq!(ao_at(6.0, 5.0), 1);
+        assert_eq!(ao_at(5.0, 6.0), 1);
+        assert_eq!(ao_at(6.0, 6.0), 3);
+
+        // Both triangles share the diagonal between the less occluded corners
+        let count = |x: f32, z: f32| top.iter().filter(|v| v.position[0] == x && v.position[2] == z).count();
+        assert_eq!(count(6.0, 5.0), 2);
+        assert_eq!(count(5.0, 6.0), 2);
+
+        // Nothing above the step
+        assert!(mesh.vertices.iter().filter(|v| v.position[1] == 7.0 && v.normal[1] == 1.0).all(|v| v.ao == 0));
+    }
+
+    #[test]
+    fn ambient_occlusion_across_diagonal_chunks() {
+        let mut world = World::new();
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        chunk.set(LocalPos::new(15, 15, 0), STONE);
+        world.insert_chunk(chunk);
+        // Only touches the chunk above along an edge
+        let mut diagonal = Chunk::new(ChunkPos::new(1, 1, 0));
+        diagonal.set(LocalPos::new(0, 0, 0), STONE);
+        world.insert_chunk(diagonal);
+
+        let neighborhood = ChunkNeighborhood::from_world(&world, ChunkPos::new(0, 0, 0)).unwrap();
+        assert_eq!(neighborhood.get(16, 16, 0), STONE);
+        assert_eq!(face_ao(&neighborhood, &table(), Vector3::new(15, 15, 0), Direction::Up).iter().max(), Some(&1));
+    }
+
+    #[test]
+    fn greedy_keeps_uneven_occlusion_apart() {
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        for x in 0..8 {
+            for z in 0..8 {
+                chunk.set(LocalPos::new(x, 0, z), STONE);
+            }
+        }
+        chunk.set(LocalPos::new(3, 1, 3), STONE);
+        let neighborhood = ChunkNeighborhood::new(&chunk, [None; 6]);
+        assert_equivalent(&neighborhood);
+
+        let greedy = greedy_faces(&neighborhood, &table());
+        for face in greedy.iter().filter(|f| f.ao.iter().any(|ao| *ao != f.ao[0])) {
+            assert_eq!(face.max - face.min, Vector3::new(1, 1, 1));
+        }
+    }
+
+    #[test]
+    fn face_textures_and_uvs() {
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        chunk.set(LocalPos::new(3, 4, 5), STONE);
+        let mesh = mesh(&chunk, [None; 6]);
+
+        for quad in mesh.vertices.chunks(6) {
+            let normal = Vector3::from(quad[0].normal);
+            let dir = Direction::ALL.into_iter().find(|dir| dir.normal() == normal).unwrap();
+            assert!(quad.iter().all(|v| v.texture == [1, 1, 0, 2, 1, 1][dir.index()]));
+
+            // Covers exactly one copy of the texture
+            let min = quad.iter().fold(Vector2::repeat(f32::MAX), |acc, v| acc.inf(&Vector2::from(v.tex_coord)));
+            let max = quad.iter().fold(Vector2::repeat(f32::MIN), |acc, v| acc.sup(&Vector2::from(v.tex_coord)));
+            assert_eq!(max - min, Vector2::new(1.0, 1.0));
+
+            // The top of the texture is at the top of the block on the sides
+            if dir.axis() != crate::game::block_state::Axis::Y {
+                let top = quad.iter().find(|v| v.position[1] == 5.0).unwrap();
+                assert_eq!(top.tex_coord[1], min.y);
+            }
+        }
+    }
+
+    fn baked(json: &str) -> Vec<BakedQuad> {
+        let mut loader = model::ModelLoader::from_sources([("m".to_string(), json.to_string())].into_iter().collect());
+        loader.bake("m").unwrap().quads
+    }
+
+    #[test]
+    fn model_quads_are_culled_by_cullface() {
+        // Bottom slab, its top face never culled
+        let slab = baked(r#"{
+            "elements": [{
+                "from": [0, 0, 0], "to": [16, 8, 16],
+                "faces": {
+                    "up": { "texture": "slab.png" },
+                    "down": { "texture": "slab.png", "cullface": "down" },
+                    "east": { "texture": "slab.png", "cullface": "east" }
+                }
+            }]
+        }"#);
+        let quads: Vec<_> = slab.iter().map(|quad| ModelQuad {
+            positions: *quad.quad.get_vertex_positions(),
+            uvs: quad.uvs,
+            texture: 5,
+            cullface: quad.cullface,
+            tint: quad.tint,
+        }).collect();
+
+        let mut states = table().states;
+        states.push(StateMeshInfo { visible: true, opaque: false, textures: [0; 6], tints: [None; 6], quads: Some((0, 3)) });
+        let table = MeshTable::with_quads(states, quads);
+        let slab_state = BlockState(3);
+
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        chunk.set(LocalPos::new(4, 4, 4), slab_state);
+        let neighborhood = ChunkNeighborhood::new(&chunk, [None; 6]);
+        let mesh = mesh_chunk(&neighborhood, &table, MeshingMode::Greedy);
+        assert_eq!(mesh.quad_count(), 3);
+        assert!(mesh.vertices.iter().all(|v| v.texture == 5));
+        assert!(mesh.vertices.iter().any(|v| v.position[1] == 4.5));
+
+        // Stone below hides the bottom, while the slab doesn't hide the stone's top
+        chunk.set(LocalPos::new(4, 3, 4), STONE);
+        chunk.set(LocalPos::new(5, 4, 4), GLASS);
+        let neighborhood = ChunkNeighborhood::new(&chunk, [None; 6]);
+        let mesh = mesh_chunk(&neighborhood, &table, MeshingMode::Greedy);
+        assert_eq!(mesh.quad_count(), 2 + 6 + 6);
+    }
+
+    #[test]
+    fn full_cube_models_are_meshed_as_cubes() {
+        let cube = baked(r#"{
+            "elements": [{
+                "from": [0, 0, 0], "to": [16, 16, 16],
+                "faces": {
+                    "down": { "texture": "0.png", "cullface": "down" },
+                    "up": { "texture": "1.png", "cullface": "up" },
+                    "north": { "texture": "2.png", "cullface": "north" },
+                    "south": { "texture": "3.png", "cullface": "south" },
+                    "west": { "texture": "4.png", "cullface": "west" },
+                    "east": { "texture": "5.png", "cullface": "east" }
+                }
+            }]
+        }"#);
+        let texture = |quad: &BakedQuad| quad.texture[..1].parse().unwrap();
+        assert_eq!(cube_textures(&cube, texture), Some(([5, 4, 1, 0, 3, 2], [None; 6])));
+
+        // Missing faces, smaller boxes and moved textures all need the model path
+        assert_eq!(cube_textures(&cube[1..], texture), None);
+        let mut shifted = cube.clone();
+        shifted[0].uvs[0].x += 0.5;
+        assert_eq!(cube_textures(&shifted, texture), None);
+        let slab = baked(r#"{ "elements": [{ "from": [0, 0, 0], "to": [16, 8, 16], "faces": { "up": { "texture": "0.png", "cullface": "up" } } }] }"#);
+        assert_eq!(cube_textures(&slab, texture), None);
+    }
+
+    #[test]
+    fn tinted_faces_take_the_biome_color() {
+        use crate::game::biome::{Biome, Color};
+
+        let biome = |name: &str, grass: [u8; 3]| Biome {
+            name: name.to_string(),
+            display_name: name.to_string(),
+            temperature: 0.0,
+            humidity: 0.0,
+            surface: STONE,
+            underwater: STONE,
+            filler: STONE,
+            filler_depth: 3,
+            height_offset: 0.0,
+            height_scale: 1.0,
+            tree_density: 0.0,
+            grass_color: Color(grass),
+            foliage_color: Color::WHITE,
+        };
+        let biomes = BiomeRegistry::new(vec![biome("a", [255, 0, 0]), biome("b", [0, 0, 255])]);
+        let mut states = table().states;
+        states[1].tints[Direction::Up.index()] = Some(TintType::Grass);
+        let table = MeshTable::new(states).with_biome_tints(&biomes);
+
+        // A flat layer, the right half in the second biome
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                chunk.set(LocalPos::new(x, 0, z), STONE);
+                if x >= 8 {
+                    chunk.set_biome(x, z, BiomeId(1));
+                }
+            }
+        }
+        let mesh = mesh_chunk(&ChunkNeighborhood::new(&chunk, [None; 6]), &table, MeshingMode::Greedy);
+
+        // Untinted faces still merge across the border, the top is split in two
+        assert_eq!(mesh.quad_count(), 4 + 1 + 2);
+        for quad in mesh.vertices.chunks(6) {
+            let min_x = quad.iter().map(|v| v.position[0]).fold(f32::INFINITY, f32::min);
+            let expected = match quad[0].normal[1] > 0.5 {
+                true if min_x < 8.0 => [1.0, 0.0, 0.0],
+                true => [0.0, 0.0, 1.0],
+                false => [1.0; 3],
+            };
+            assert!(quad.iter().all(|v| v.tint == expected), "{:?}", quad);
+        }
+    }
+}
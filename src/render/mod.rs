@@ -1,5 +1,10 @@
 pub mod buffers;
+pub mod blockstates;
 pub mod camera;
+pub mod mesher;
+pub mod model;
+pub mod mesh_workers;
 pub mod render_state;
+pub mod settings;
 pub mod util;
 pub mod face_lighting;
\ No newline at end of file
@@ -0,0 +1,587 @@
+use nalgebra::{Vector3, Vector2, Rotation3, Unit};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Deserializer, de::Error as _};
+use thiserror::Error;
+
+use crate::{game::{biome::TintType, pos::Direction}, resources::{loader::parse_json, manager::ResourceManager}};
+
+use super::util::{cube_model::DEFAULT_CUBE_MODEL_QUADS, shapes::Quad};
+
+/// Directory inside of a resource pack holding the block models
+pub const MODELS_DIR: &str = "models";
+
+/// Parents can't be nested deeper than this, which also catches cycles
+const MAX_PARENT_DEPTH: usize = 32;
+
+/// A problem with one field of a block model, or of one of its parents
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("model `{model}`: `{field}`: {reason}")]
+pub struct ModelError {
+    pub model: String,
+    /// Path of the offending field, e.g. `elements[0].faces.up.texture`
+    pub field: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RotationAxis {
+    X,
+    Y,
+    Z,
+}
+
+impl RotationAxis {
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ElementRotation {
+    /// Pivot in sixteenths of a block
+    pub origin: [f32; 3],
+    pub axis: RotationAxis,
+    /// Counter clockwise degrees looking down the positive axis, one of
+    /// `-45`, `-22.5`, `0`, `22.5` and `45`
+    #[serde(deserialize_with = "rotation_angle")]
+    pub angle: f32,
+    /// Scale the rotated faces back up to span the whole block, for crosses
+    #[serde(default)]
+    pub rescale: bool,
+}
+
+/// Steeper angles would stretch rescaled faces far outside of the block
+fn rotation_angle<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let angle = f32::deserialize(deserializer)?;
+    if angle.abs() <= 45.0 && angle % 22.5 == 0.0 {
+        Ok(angle)
+    } else {
+        Err(D::Error::custom(format!("{} is not -45, -22.5, 0, 22.5 or 45", angle)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ElementFace {
+    /// `[u0, v0, u1, v1]` in sixteenths of the texture, defaults to the area
+    /// the face covers on its side of the block
+    pub uv: Option<[f32; 4]>,
+    /// Texture path relative to the assets, or `#variable`
+    pub texture: String,
+    /// Degrees to rotate the texture clockwise, a multiple of 90
+    #[serde(default)]
+    pub rotation: u32,
+    /// The face is hidden when the neighbor in this direction is opaque
+    pub cullface: Option<DirectionName>,
+    /// Biome color the texture is multiplied with
+    pub tint: Option<TintType>,
+}
+
+/// Direction as written in model files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DirectionName {
+    East,
+    West,
+    Up,
+    Down,
+    South,
+    North,
+}
+
+impl From<DirectionName> for Direction {
+    fn from(name: DirectionName) -> Self {
+        Direction::from_index(name as usize)
+    }
+}
+
+/// Axis aligned box in sixteenths of a block
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelElement {
+    pub from: [f32; 3],
+    pub to: [f32; 3],
+    pub rotation: Option<ElementRotation>,
+    /// Whether the faces are darkened by directional lighting and AO
+    #[serde(default = "default_true")]
+    pub shade: bool,
+    #[serde(default)]
+    pub faces: FxHashMap<DirectionName, ElementFace>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// `models/<name>.json`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelJson {
+    parent: Option<String>,
+    #[serde(default)]
+    textures: FxHashMap<String, String>,
+    /// Replaces the parent's elements when present
+    elements: Option<Vec<ModelElement>>,
+}
+
+/// A model with its parents merged in, textures may still be variables
+#[derive(Debug, Clone, Default)]
+pub struct BlockModel {
+    pub name: String,
+    pub textures: FxHashMap<String, String>,
+    pub elements: Vec<ModelElement>,
+}
+
+/// One face of a compiled model in block space, `0.0..=1.0` for faces inside
+/// of the block
+#[derive(Debug, Clone)]
+pub struct BakedQuad {
+    pub quad: Quad,
+    /// Texture coordinates of every corner, `0.0..=1.0` across the texture
+    pub uvs: [Vector2<f32>; 4],
+    /// Texture path relative to the assets
+    pub texture: String,
+    pub cullface: Option<Direction>,
+    pub shade: bool,
+    pub tint: Option<TintType>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BakedModel {
+    pub quads: Vec<BakedQuad>,
+}
+
+impl BakedModel {
+    /// Every texture the model uses, without duplicates
+    pub fn textures(&self) -> Vec<&str> {
+        let mut ret: Vec<&str> = Vec::new();
+        for quad in &self.quads {
+            if !ret.contains(&quad.texture.as_str()) {
+                ret.push(&quad.texture);
+            }
+        }
+        ret
+    }
+}
+
+enum ModelSource<'a> {
+    Resources(&'a ResourceManager),
+    Memory(FxHashMap<String, String>),
+}
+
+/// Reads models by name, `block/slab` is `models/block/slab.json` in the
+/// resource packs, and resolves their parents
+pub struct ModelLoader<'a> {
+    source: ModelSource<'a>,
+    cache: FxHashMap<String, ModelJson>,
+}
+
+impl<'a> ModelLoader<'a> {
+    pub fn new(resources: &'a ResourceManager) -> Self {
+        Self {
+            source: ModelSource::Resources(resources),
+            cache: FxHashMap::default(),
+        }
+    }
+
+    /// Loader over model JSON by name instead of files
+    pub fn from_sources(sources: FxHashMap<String, String>) -> Self {
+        Self {
+            source: ModelSource::Memory(sources),
+            cache: FxHashMap::default(),
+        }
+    }
+
+    fn read(&mut self, name: &str) -> Result<&ModelJson, ModelError> {
+        let error = |field: &str, reason: String| ModelError {
+            model: name.to_string(),
+            field: field.to_string(),
+            reason,
+        };
+
+        if !self.cache.contains_key(name) {
+            let json = match &self.source {
+                ModelSource::Resources(resources) => {
+                    let path = format!("{}/{}.json", MODELS_DIR, name);
+                    resources.read_to_string(&path)
+                        .map_err(|e| error("", format!("failed to read `{}`: {}", resources.origin(&path).display(), e)))?
+                },
+                ModelSource::Memory(sources) => sources.get(name).cloned()
+                    .ok_or_else(|| error("", "no such model".to_string()))?,
+            };
+
+            let model: ModelJson = parse_json(&json).map_err(|(field, reason)| error(&field, reason))?;
+            self.cache.insert(name.to_string(), model);
+        }
+
+        Ok(&self.cache[name])
+    }
+
+    /// Loads a model and merges in its parents. Child textures override the
+    /// parent's, child elements replace them.
+    pub fn load(&mut self, name: &str) -> Result<BlockModel, ModelError> {
+        let mut chain = vec![name.to_string()];
+        loop {
+            let parent = self.read(chain.last().unwrap())?.parent.clone();
+            let Some(parent) = parent else { break };
+            if chain.contains(&parent) || chain.len() >= MAX_PARENT_DEPTH {
+                chain.push(parent);
+                return Err(ModelError {
+                    model: name.to_string(),
+                    field: "parent".to_string(),
+                    reason: format!("parent chain loops or is too deep: {}", chain.join(" -> ")),
+                });
+            }
+            chain.push(parent);
+        }
+
+        let mut ret = BlockModel { name: name.to_string(), ..Default::default() };
+        for model in chain.iter().rev() {
+            let json = &self.cache[model];
+            ret.textures.extend(json.textures.clone());
+            if let Some(elements) = &json.elements {
+                ret.elements = elements.clone();
+            }
+        }
+        Ok(ret)
+    }
+
+    pub fn bake(&mut self, name: &str) -> Result<BakedModel, ModelError> {
+        self.load(name)?.bake()
+    }
+}
+
+impl BlockModel {
+    /// Follows `#variable` references to a texture path
+    pub fn resolve_texture<'a>(&'a self, texture: &'a str) -> Option<&'a str> {
+        let mut current = texture;
+        for _ in 0..=self.textures.len() {
+            match current.strip_prefix('#') {
+                Some(variable) => current = self.textures.get(variable)?,
+                None => return Some(current),
+            }
+        }
+        // Variables referring to each other
+        None
+    }
+
+    /// Compiles every element face into quads
+    pub fn bake(&self) -> Result<BakedModel, ModelError> {
+        let mut quads = Vec::new();
+        for (i, element) in self.elements.iter().enumerate() {
+            let error = |field: &str, reason: String| ModelError {
+                model: self.name.clone(),
+                field: format!("elements[{}]{}", i, field),
+                reason,
+            };
+
+            for axis in 0..3 {
+                if element.from[axis] > element.to[axis] {
+                    return Err(error(".from", "must not be greater than `to`".to_string()));
+                }
+            }
+
+            // Keep the order of the faces stable
+            for dir in Direction::ALL {
+                let Some(face) = element.faces.iter()
+                    .find(|(name, _)| Direction::from(**name) == dir)
+                    .map(|(_, face)| face) else { continue };
+
+                let field = format!(".faces.{:?}", dir).to_lowercase();
+                if face.rotation % 90 != 0 || face.rotation >= 360 {
+                    return Err(error(&format!("{}.rotation", field), format!("{} is not 0, 90, 180 or 270", face.rotation)));
+                }
+                let texture = self.resolve_texture(&face.texture).ok_or_else(|| {
+                    error(&format!("{}.texture", field), format!("`{}` doesn't resolve to a texture", face.texture))
+                })?;
+
+                if let Some(quad) = bake_face(element, dir, face, texture) {
+                    quads.push(quad);
+                }
+            }
+        }
+
+        Ok(BakedModel { quads })
+    }
+}
+
+/// Texture position of a point in block space on a face, with the same
+/// orientation as the textures of full blocks
+pub(super) fn face_uv(dir: Direction, pos: Vector3<f32>) -> Vector2<f32> {
+    match dir {
+        Direction::East => Vector2::new(1.0 - pos.z, 1.0 - pos.y),
+        Direction::West => Vector2::new(pos.z, 1.0 - pos.y),
+        Direction::Up => Vector2::new(pos.x, pos.z),
+        Direction::Down => Vector2::new(pos.x, 1.0 - pos.z),
+        Direction::South => Vector2::new(pos.x, 1.0 - pos.y),
+        Direction::North => Vector2::new(1.0 - pos.x, 1.0 - pos.y),
+    }
+}
+
+/// `None` for faces without area
+fn bake_face(element: &ModelElement, dir: Direction, face: &ElementFace, texture: &str) -> Option<BakedQuad> {
+    let min = Vector3::from(element.from) / 16.0;
+    let max = Vector3::from(element.to) / 16.0;
+
+    let unit = DEFAULT_CUBE_MODEL_QUADS[dir.index()].get_vertex_positions();
+    let mut corners = unit.map(|corner| Vector3::from_fn(|i, _| if corner[i] < 0.0 { min[i] } else { max[i] }));
+
+    // Default uvs are where the face is on the side of the block
+    let mut uvs = corners.map(|corner| face_uv(dir, corner));
+    let uv_min = uvs.iter().fold(Vector2::repeat(f32::MAX), |acc, uv| acc.inf(uv));
+    let uv_max = uvs.iter().fold(Vector2::repeat(f32::MIN), |acc, uv| acc.sup(uv));
+    if uv_min.x >= uv_max.x || uv_min.y >= uv_max.y {
+        return None;
+    }
+
+    if let Some([u0, v0, u1, v1]) = face.uv {
+        let (from, to) = (Vector2::new(u0, v0) / 16.0, Vector2::new(u1, v1) / 16.0);
+        for uv in &mut uvs {
+            let t = (*uv - uv_min).component_div(&(uv_max - uv_min));
+            *uv = from + (to - from).component_mul(&t);
+        }
+    }
+
+    // Rotating the texture clockwise moves every uv one corner back
+    uvs.rotate_left((face.rotation / 90) as usize);
+
+    if let Some(rotation) = &element.rotation {
+        let mut axis = Vector3::zeros();
+        axis[rotation.axis.index()] = 1.0;
+        let rot = Rotation3::from_axis_angle(&Unit::new_normalize(axis), rotation.angle.to_radians());
+        let origin = Vector3::from(rotation.origin) / 16.0;
+
+        // Stretch the other two axes so a 45 degree plane still spans the block
+        let scale = if rotation.rescale {
+            let s = 1.0 / rotation.angle.to_radians().cos().abs();
+            Vector3::from_fn(|i, _| if i == rotation.axis.index() { 1.0 } else { s })
+        } else {
+            Vector3::repeat(1.0)
+        };
+
+        for corner in &mut corners {
+            *corner = origin + rot * (*corner - origin).component_mul(&scale);
+        }
+    }
+
+    Some(BakedQuad {
+        quad: Quad::new_unchecked(corners),
+        uvs,
+        texture: texture.to_string(),
+        cullface: face.cullface.map(Direction::from),
+        shade: element.shade,
+        tint: face.tint,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loader(models: &[(&str, &str)]) -> ModelLoader<'static> {
+        ModelLoader::from_sources(models.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+    }
+
+    const CUBE: &str = r##"{
+        "elements": [{
+            "from": [0, 0, 0], "to": [16, 16, 16],
+            "faces": {
+                "down":  { "texture": "#down", "cullface": "down" },
+                "up":    { "texture": "#up", "cullface": "up" },
+                "north": { "texture": "#north", "cullface": "north" },
+                "south": { "texture": "#south", "cullface": "south" },
+                "west":  { "texture": "#west", "cullface": "west" },
+                "east":  { "texture": "#east", "cullface": "east" }
+            }
+        }]
+    }"##;
+
+    const CUBE_ALL: &str = r##"{
+        "parent": "block/cube",
+        "textures": {
+            "down": "#all", "up": "#all", "north": "#all",
+            "south": "#all", "west": "#all", "east": "#all"
+        }
+    }"##;
+
+    fn assert_close(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).norm() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn cube_matches_unit_cube() {
+        let mut loader = loader(&[
+            ("block/cube", CUBE),
+            ("block/cube_all", CUBE_ALL),
+            ("stone", r#"{ "parent": "block/cube_all", "textures": { "all": "stone.png" } }"#),
+        ]);
+        let model = loader.bake("stone").unwrap();
+        assert_eq!(model.quads.len(), 6);
+        assert_eq!(model.textures(), vec!["stone.png"]);
+
+        for (dir, quad) in Direction::ALL.into_iter().zip(&model.quads) {
+            assert_eq!(quad.cullface, Some(dir));
+            let expected = DEFAULT_CUBE_MODEL_QUADS[dir.index()].translated(Vector3::repeat(0.5));
+            for (a, b) in quad.quad.get_vertex_positions().iter().zip(expected.get_vertex_positions()) {
+                assert_close(*a, *b);
+            }
+
+            let mut uvs = quad.uvs.to_vec();
+            uvs.sort_by(|a, b| (a.x, a.y).partial_cmp(&(b.x, b.y)).unwrap());
+            assert_eq!(uvs, vec![Vector2::new(0.0, 0.0), Vector2::new(0.0, 1.0), Vector2::new(1.0, 0.0), Vector2::new(1.0, 1.0)]);
+        }
+    }
+
+    #[test]
+    fn slab_uses_lower_half_of_side_textures() {
+        let mut loader = loader(&[("slab", r##"{
+            "textures": { "side": "side.png", "top": "top.png" },
+            "elements": [{
+                "from": [0, 0, 0], "to": [16, 8, 16],
+                "faces": {
+                    "up": { "texture": "#top" },
+                    "north": { "texture": "#side", "cullface": "north" }
+                }
+            }]
+        }"##)]);
+        let model = loader.bake("slab").unwrap();
+        assert_eq!(model.quads.len(), 2);
+
+        let up = &model.quads[0];
+        assert!(up.quad.get_vertex_positions().iter().all(|p| p.y == 0.5));
+        assert_eq!(up.cullface, None);
+        assert_eq!(up.texture, "top.png");
+
+        let north = &model.quads[1];
+        assert_eq!(north.texture, "side.png");
+        for (pos, uv) in north.quad.get_vertex_positions().iter().zip(north.uvs) {
+            // The bottom of the block is at the bottom of the texture
+            assert_eq!(uv.y, 1.0 - pos.y);
+            assert!(uv.y >= 0.5);
+        }
+    }
+
+    #[test]
+    fn explicit_and_rotated_uvs() {
+        let mut loader = loader(&[("m", r#"{
+            "elements": [{
+                "from": [0, 0, 0], "to": [16, 16, 16],
+                "faces": {
+                    "up": { "texture": "a.png", "uv": [0, 0, 8, 4] },
+                    "down": { "texture": "a.png", "rotation": 90 }
+                }
+            }]
+        }"#)]);
+        let model = loader.bake("m").unwrap();
+
+        let up = &model.quads[0];
+        for (pos, uv) in up.quad.get_vertex_positions().iter().zip(up.uvs) {
+            assert_eq!(uv, Vector2::new(pos.x * 0.5, pos.z * 0.25));
+        }
+
+        let down = &model.quads[1];
+        let unrotated = DEFAULT_CUBE_MODEL_QUADS[Direction::Down.index()].translated(Vector3::repeat(0.5));
+        let expected: Vec<_> = unrotated.get_vertex_positions().iter().map(|p| face_uv(Direction::Down, *p)).collect();
+        assert_eq!(down.uvs[0], expected[1]);
+        assert_eq!(down.uvs[3], expected[0]);
+    }
+
+    #[test]
+    fn cross_with_rotation() {
+        let mut loader = loader(&[("cross", r##"{
+            "textures": { "cross": "flower.png" },
+            "elements": [
+                {
+                    "from": [0.8, 0, 8], "to": [15.2, 16, 8], "shade": false,
+                    "rotation": { "origin": [8, 8, 8], "axis": "y", "angle": 45, "rescale": true },
+                    "faces": { "north": { "texture": "#cross" }, "south": { "texture": "#cross" } }
+                },
+                {
+                    "from": [8, 0, 0.8], "to": [8, 16, 15.2], "shade": false,
+                    "rotation": { "origin": [8, 8, 8], "axis": "y", "angle": 45, "rescale": true },
+                    "faces": { "west": { "texture": "#cross" }, "east": { "texture": "#cross" } }
+                }
+            ]
+        }"##)]);
+        let model = loader.bake("cross").unwrap();
+        assert_eq!(model.quads.len(), 4);
+
+        for quad in &model.quads {
+            assert!(!quad.shade);
+            assert_eq!(quad.cullface, None);
+            for pos in quad.quad.get_vertex_positions() {
+                // Diagonal through the block, reaching close to the corners
+                assert!((pos.x - 0.5).abs() > 0.44 && (pos.z - 0.5).abs() > 0.44, "{:?}", pos);
+                assert!(((pos.x - 0.5).abs() - (pos.z - 0.5).abs()).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn children_override_textures_and_elements() {
+        let mut loader = loader(&[
+            ("block/cube", CUBE),
+            ("block/cube_all", CUBE_ALL),
+            ("grass", r#"{ "parent": "block/cube_all", "textures": { "all": "dirt.png", "up": "grass_top.png" } }"#),
+            ("post", r##"{
+                "parent": "grass",
+                "elements": [{ "from": [6, 0, 6], "to": [10, 16, 10], "faces": { "up": { "texture": "#up" } } }]
+            }"##),
+        ]);
+
+        let grass = loader.bake("grass").unwrap();
+        assert_eq!(grass.quads[Direction::Up.index()].texture, "grass_top.png");
+        assert_eq!(grass.quads[Direction::North.index()].texture, "dirt.png");
+
+        let post = loader.bake("post").unwrap();
+        assert_eq!(post.quads.len(), 1);
+        assert_eq!(post.quads[0].texture, "grass_top.png");
+    }
+
+    #[test]
+    fn errors() {
+        let mut loader = loader(&[
+            ("a", r#"{ "parent": "b" }"#),
+            ("b", r#"{ "parent": "a" }"#),
+            ("orphan", r#"{ "parent": "missing" }"#),
+            ("unresolved", r##"{ "elements": [{ "from": [0, 0, 0], "to": [16, 16, 16], "faces": { "up": { "texture": "#top" } } }] }"##),
+            ("looping", r##"{ "textures": { "a": "#b", "b": "#a" }, "elements": [{ "from": [0, 0, 0], "to": [16, 16, 16], "faces": { "up": { "texture": "#a" } } }] }"##),
+            ("inverted", r#"{ "elements": [{ "from": [0, 8, 0], "to": [16, 0, 16] }] }"#),
+            ("rotated", r#"{ "elements": [{ "from": [0, 0, 0], "to": [16, 16, 16], "faces": { "up": { "texture": "a.png", "rotation": 45 } } }] }"#),
+            ("typo", r#"{ "elements": [{ "from": [0, 0, 0], "to": [16, 16, 16], "faces": { "top": { "texture": "a.png" } } }] }"#),
+            ("steep", r#"{ "elements": [{ "from": [0, 0, 0], "to": [16, 16, 16], "rotation": { "origin": [8, 8, 8], "axis": "y", "angle": 90, "rescale": true } }] }"#),
+            ("uneven", r#"{ "elements": [{ "from": [0, 0, 0], "to": [16, 16, 16], "rotation": { "origin": [8, 8, 8], "axis": "x", "angle": -30 } }] }"#),
+        ]);
+
+        let field = |loader: &mut ModelLoader, name: &str| loader.bake(name).unwrap_err().field;
+        assert_eq!(field(&mut loader, "a"), "parent");
+        assert_eq!(field(&mut loader, "orphan"), "");
+        assert_eq!(loader.bake("orphan").unwrap_err().model, "missing");
+        assert_eq!(field(&mut loader, "unresolved"), "elements[0].faces.up.texture");
+        assert_eq!(field(&mut loader, "looping"), "elements[0].faces.up.texture");
+        assert_eq!(field(&mut loader, "inverted"), "elements[0].from");
+        assert_eq!(field(&mut loader, "rotated"), "elements[0].faces.up.rotation");
+        assert!(field(&mut loader, "typo").starts_with("elements[0].faces"));
+        assert_eq!(field(&mut loader, "steep"), "elements[0].rotation.angle");
+        let uneven = loader.bake("uneven").unwrap_err();
+        assert_eq!(uneven.field, "elements[0].rotation.angle");
+        assert!(uneven.reason.starts_with("-30 is not -45, -22.5, 0, 22.5 or 45"), "{}", uneven.reason);
+    }
+
+    #[test]
+    fn shipped_models_bake() {
+        let resources = ResourceManager::open(&["assets"]).unwrap();
+        let mut loader = ModelLoader::new(&resources);
+        for file in resources.list(&format!("{}/block", MODELS_DIR)).unwrap() {
+            let name = format!("block/{}", file.trim_end_matches(".json"));
+            let mut model = loader.load(&name).unwrap_or_else(|e| panic!("{}", e));
+            // Templates leave their texture variables to the models using them
+            for variable in ["all", "side", "end", "torch", "cross", "down", "up", "north", "south", "west", "east"] {
+                model.textures.entry(variable.to_string()).or_insert_with(|| "stone.png".to_string());
+            }
+            model.bake().unwrap_or_else(|e| panic!("{}", e));
+        }
+    }
+}
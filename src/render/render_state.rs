@@ -1,7 +1,14 @@
-use wgpu::{include_wgsl, util::DeviceExt, Extent3d};
+use rustc_hash::FxHashMap;
+use wgpu::include_wgsl;
 use winit::{window::Window, event::WindowEvent};
 
-use super::{util::{vertex::*, cube_model::{CubeModel, self}, texture::TextureArray, texture_atlas::TextureAtlas}, camera::{Camera, CameraUniform}, face_lighting::{FaceLightingUniform, FaceLighting}};
+use crate::game::pos::ChunkPos;
+
+use super::{util::{vertex::*, texture_atlas::TextureAtlas, texture::DepthTexture}, camera::{Camera, CameraUniform}, face_lighting::{FaceLightingUniform, FaceLighting}, buffers::ChunkBuffer, mesher::ChunkMesh, settings::RenderSettings};
+
+/// File name of the shader inside of `src/shader`. Release builds only use the
+/// copy built into the binary.
+pub const SHADER_FILE: &str = "shader.wgsl";
 
 pub struct RenderState {
     surface: wgpu::Surface,
@@ -9,21 +16,23 @@ pub struct RenderState {
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
-    texture_atlas: TextureAtlas,
+    pub settings: RenderSettings,
+    depth_texture: DepthTexture,
+    pub texture_atlas: TextureAtlas,
     pub camera: Camera,
     camera_bind_group: wgpu::BindGroup,
     pub face_lighting: FaceLighting,
     face_lighting_bind_group: wgpu::BindGroup,
+    render_pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    // instance_buffer: wgpu::Buffer,
+    chunk_buffers: FxHashMap<ChunkPos, ChunkBuffer>,
     bind_group: wgpu::BindGroup,
-    pub num_indices: u32,
 }
 
 impl RenderState {
     // Creating some of the wgpu types requires async code
-    pub async fn new(window: &Window) -> Self {
+    /// Takes ownership of the atlas with every texture the meshes will refer to
+    pub async fn new(window: &Window, settings: RenderSettings, mut texture_atlas: TextureAtlas) -> Self {
         let size = window.inner_size();
         let instance = wgpu::Instance::new(wgpu::Backends::VULKAN);
         let surface = unsafe { instance.create_surface(window) };
@@ -54,38 +63,20 @@ impl RenderState {
         };
         surface.configure(&device, &config);
 
-        let shader = device.create_shader_module(include_wgsl!("../shader/shader.wgsl"));
-
-        let model = CubeModel::default();
-        let mut model_verts = Vec::new();
-
-        for quad in cube_model::DEFAULT_CUBE_MODEL_QUADS.iter() {
-            for v in quad.get_vertices() {
-                model_verts.push(VertexRaw::from(v));
-            }
-        }
-
-        let vertex_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(model_verts.as_slice()),
-                usage: wgpu::BufferUsages::VERTEX,
-            }
-        );
-
-        let mut texture_atlas = TextureAtlas::new();
+        let depth_texture = DepthTexture::new(&device, &config, settings.depth.format);
 
-        for dyn_tex in model.textures {
-            texture_atlas.add_texture(dyn_tex);
-        }
+        let shader = device.create_shader_module(include_wgsl!("../shader/shader.wgsl"));
 
         let (texture_bind_group_layout, texture_bind_group) = 
-            texture_atlas.get_bind_group_and_layout(&device);
+            texture_atlas.get_bind_group_and_layout(&device, settings.texture_filter);
 
         texture_atlas.write_buffer(&queue);
 
-        let mut camera = Camera::default();
-        camera.aspect = config.width as f32 / config.height as f32;
+        let mut camera = Camera {
+            aspect: config.width as f32 / config.height as f32,
+            reversed_z: settings.depth.reversed_z,
+            ..Default::default()
+        };
 
         let (camera_bind_group_layout, camera_bind_group) = 
             camera.get_bind_group_and_layout(&device);
@@ -106,42 +97,7 @@ impl RenderState {
             }
         );
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[
-                    VertexRaw::desc(),
-                ],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Cw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
+        let render_pipeline = create_render_pipeline(&device, &render_pipeline_layout, &shader, config.format, &settings);
 
         Self {
             surface,
@@ -149,24 +105,72 @@ impl RenderState {
             queue,
             config,
             size,
+            settings,
+            depth_texture,
             texture_atlas,
             camera,
             camera_bind_group,
             face_lighting,
             face_lighting_bind_group,
+            render_pipeline_layout,
             render_pipeline,
-            vertex_buffer,
+            chunk_buffers: FxHashMap::default(),
             bind_group: texture_bind_group,
-            num_indices: model_verts.len() as u32,
         }
     }
 
+    /// Replaces the atlas, e.g. after the resource packs were reloaded. Uploaded
+    /// meshes keep their atlas indices, so they have to be rebuilt as well.
+    pub fn set_texture_atlas(&mut self, mut texture_atlas: TextureAtlas) {
+        let (_, bind_group) = texture_atlas.get_bind_group_and_layout(&self.device, self.settings.texture_filter);
+        texture_atlas.write_buffer(&self.queue);
+        self.bind_group = bind_group;
+        self.texture_atlas = texture_atlas;
+    }
+
+    /// Compiles new shader source and swaps the pipeline. Invalid WGSL keeps
+    /// the current pipeline and returns the compiler output.
+    pub fn reload_shader(&mut self, source: &str) -> Result<(), String> {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(SHADER_FILE),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let render_pipeline = create_render_pipeline(&self.device, &self.render_pipeline_layout, &shader, self.config.format, &self.settings);
+        if let Some(error) = pollster::block_on(self.device.pop_error_scope()) {
+            return Err(error.to_string());
+        }
+
+        self.render_pipeline = render_pipeline;
+        Ok(())
+    }
+
+    /// Shows the frames of the animated textures at `tick`
+    pub fn animate_textures(&mut self, tick: u64) {
+        self.texture_atlas.animate(&self.queue, tick);
+    }
+
+    /// Uploads a chunk mesh, replacing the previous mesh of that chunk
+    pub fn upload_chunk_mesh(&mut self, mesh: &ChunkMesh) {
+        if mesh.is_empty() {
+            self.chunk_buffers.remove(&mesh.pos);
+            return;
+        }
+
+        self.chunk_buffers.insert(mesh.pos, ChunkBuffer::new(&self.device, mesh));
+    }
+
+    pub fn remove_chunk_mesh(&mut self, pos: ChunkPos) {
+        self.chunk_buffers.remove(&pos);
+    }
+
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
+            self.depth_texture = DepthTexture::new(&self.device, &self.config, self.settings.depth.format);
             self.camera.aspect = self.config.width as f32 / self.config.height as f32
         }
     }
@@ -221,15 +225,25 @@ impl RenderState {
                         }
                     })
                 ],
-                depth_stencil_attachment: None,
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.settings.depth.clear_value()),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
             });
         
             render_pass.set_pipeline(&self.render_pipeline);
             render_pass.set_bind_group(0, &self.bind_group, &[]);
             render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
             render_pass.set_bind_group(2, &self.face_lighting_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.draw(0..self.num_indices, 0..1);
+
+            for chunk in self.chunk_buffers.values() {
+                render_pass.set_vertex_buffer(0, chunk.vertex_buffer.slice(..));
+                render_pass.draw(0..chunk.vertex_count, 0..1);
+            }
         }
     
         self.queue.submit(std::iter::once(encoder.finish()));
@@ -237,4 +251,55 @@ impl RenderState {
     
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    settings: &RenderSettings,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[
+                VertexRaw::desc(),
+            ],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Cw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: settings.depth.format,
+            depth_write_enabled: true,
+            depth_compare: settings.depth.compare_function(),
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
@@ -0,0 +1,81 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MeshingMode {
+    /// One quad per visible block face
+    Naive,
+    /// Coplanar neighboring faces that look the same are merged into larger quads
+    #[default]
+    Greedy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthSettings {
+    pub format: wgpu::TextureFormat,
+    /// Compare function for a regular depth range, mirrored when `reversed_z` is set
+    pub compare: wgpu::CompareFunction,
+    /// Map the near plane to a depth of 1 and the far plane to 0
+    pub reversed_z: bool,
+}
+
+impl Default for DepthSettings {
+    fn default() -> Self {
+        Self {
+            format: wgpu::TextureFormat::Depth32Float,
+            compare: wgpu::CompareFunction::Less,
+            reversed_z: false,
+        }
+    }
+}
+
+impl DepthSettings {
+    /// The compare function to use for the depth range in use
+    pub fn compare_function(&self) -> wgpu::CompareFunction {
+        use wgpu::CompareFunction::*;
+
+        if !self.reversed_z {
+            return self.compare;
+        }
+
+        match self.compare {
+            Less => Greater,
+            LessEqual => GreaterEqual,
+            Greater => Less,
+            GreaterEqual => LessEqual,
+            other => other,
+        }
+    }
+
+    /// Depth of the far plane, which the depth buffer is cleared to
+    pub fn clear_value(&self) -> f32 {
+        if self.reversed_z { 0.0 } else { 1.0 }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RenderSettings {
+    pub meshing_mode: MeshingMode,
+    /// Only read when the texture atlas is uploaded
+    pub texture_filter: wgpu::FilterMode,
+    /// Only read when the render pipeline is created
+    pub depth: DepthSettings,
+}
+
+#[cfg(test)]
+mod tests {
+    use wgpu::CompareFunction;
+
+    use super::*;
+
+    #[test]
+    fn reversed_z_flips_depth() {
+        let regular = DepthSettings::default();
+        assert_eq!(regular.compare_function(), CompareFunction::Less);
+        assert_eq!(regular.clear_value(), 1.0);
+
+        let reversed = DepthSettings { reversed_z: true, compare: CompareFunction::LessEqual, ..regular };
+        assert_eq!(reversed.compare_function(), CompareFunction::GreaterEqual);
+        assert_eq!(reversed.clear_value(), 0.0);
+
+        let always = DepthSettings { compare: CompareFunction::Always, ..reversed };
+        assert_eq!(always.compare_function(), CompareFunction::Always);
+    }
+}
@@ -0,0 +1,219 @@
+use std::time::Duration;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::resources::loader::parse_json;
+
+/// Appended to the path of a texture for its animation, e.g. `water.png.anim.json`
+pub const ANIMATION_SUFFIX: &str = ".anim.json";
+
+/// Frame times are given in ticks of this length
+pub const TICK: Duration = Duration::from_millis(50);
+
+/// A problem with one field of an animation file
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("`{field}`: {reason}")]
+pub struct AnimationError {
+    /// Path of the offending field, e.g. `frames[2].index`
+    pub field: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+enum FrameJson {
+    Index(u32),
+    Timed(Frame),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnimationJson {
+    /// Every frame of the strip in order if missing
+    frames: Option<Vec<FrameJson>>,
+    /// Ticks of frames without their own time
+    #[serde(default = "default_frame_time")]
+    frametime: u32,
+    #[serde(default)]
+    interpolate: bool,
+}
+
+fn default_frame_time() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Frame {
+    /// Index into the strip, counted from the top
+    pub index: u32,
+    /// Ticks the frame is shown for
+    pub time: u32,
+}
+
+/// Which frames of a vertical strip are shown and for how long. Frames are
+/// square, as wide as the strip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Animation {
+    pub frames: Vec<Frame>,
+    /// Blend into the next frame over the time of a frame instead of switching
+    pub interpolate: bool,
+}
+
+impl Animation {
+    /// Parses the animation of a strip with `strip_len` frames
+    pub fn parse(json: &str, strip_len: u32) -> Result<Self, AnimationError> {
+        let error = |field: &str, reason: String| AnimationError { field: field.to_string(), reason };
+
+        let json: AnimationJson = parse_json(json).map_err(|(field, reason)| error(&field, reason))?;
+
+        if json.frametime == 0 {
+            return Err(error("frametime", "must be at least 1".to_string()));
+        }
+
+        let frames = match json.frames {
+            Some(frames) => frames.into_iter().enumerate().map(|(i, frame)| {
+                let Frame { index, time } = match frame {
+                    FrameJson::Index(index) => Frame { index, time: json.frametime },
+                    FrameJson::Timed(frame) => frame,
+                };
+                if index >= strip_len {
+                    return Err(error(&format!("frames[{}].index", i), format!("the strip only has {} frames", strip_len)));
+                }
+                if time == 0 {
+                    return Err(error(&format!("frames[{}].time", i), "must be at least 1".to_string()));
+                }
+                Ok(Frame { index, time })
+            }).collect::<Result<Vec<_>, _>>()?,
+            None => (0..strip_len).map(|index| Frame { index, time: json.frametime }).collect(),
+        };
+
+        if frames.is_empty() {
+            return Err(error("frames", "needs at least one frame".to_string()));
+        }
+
+        Ok(Self { frames, interpolate: json.interpolate })
+    }
+
+    /// Ticks until the animation repeats
+    pub fn duration(&self) -> u64 {
+        self.frames.iter().map(|frame| frame.time as u64).sum()
+    }
+
+    /// Position in `frames` and ticks into that frame at `tick`
+    pub fn frame_at(&self, tick: u64) -> (usize, u32) {
+        let mut tick = tick % self.duration();
+        for (i, frame) in self.frames.iter().enumerate() {
+            if tick < frame.time as u64 {
+                return (i, tick as u32);
+            }
+            tick -= frame.time as u64;
+        }
+        unreachable!("tick is less than the duration")
+    }
+
+    /// Identifies what `tick` looks like, so unchanged frames aren't uploaded again
+    pub fn key_at(&self, tick: u64) -> (usize, u32) {
+        let (frame, progress) = self.frame_at(tick);
+        (frame, if self.interpolate { progress } else { 0 })
+    }
+}
+
+/// A strip split into frames along with its animation
+#[derive(Debug, Clone)]
+pub struct AnimatedTexture {
+    frames: Vec<RgbaImage>,
+    pub animation: Animation,
+}
+
+impl AnimatedTexture {
+    /// Number of square frames in a strip, `None` if it isn't a whole number
+    pub fn strip_len(strip: &DynamicImage) -> Option<u32> {
+        let (width, height) = (strip.width(), strip.height());
+        (width > 0 && height % width == 0).then(|| height / width)
+    }
+
+    /// The animation has to be parsed for this strip
+    pub fn new(strip: &DynamicImage, animation: Animation) -> Self {
+        let strip = strip.to_rgba8();
+        let size = strip.width();
+        let frames = (0..strip.height() / size)
+            .map(|i| image::imageops::crop_imm(&strip, 0, i * size, size, size).to_image())
+            .collect();
+        Self { frames, animation }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.frames[0].width()
+    }
+
+    /// The image shown at `tick`
+    pub fn image_at(&self, tick: u64) -> RgbaImage {
+        let (i, progress) = self.animation.frame_at(tick);
+        let frame = self.animation.frames[i];
+        let current = &self.frames[frame.index as usize];
+        if !self.animation.interpolate || progress == 0 {
+            return current.clone();
+        }
+
+        let next = &self.frames[self.animation.frames[(i + 1) % self.animation.frames.len()].index as usize];
+        let t = progress as f32 / frame.time as f32;
+        RgbaImage::from_fn(current.width(), current.height(), |x, y| {
+            let (a, b) = (current.get_pixel(x, y), next.get_pixel(x, y));
+            Rgba([0, 1, 2, 3].map(|c| (a[c] as f32 + (b[c] as f32 - a[c] as f32) * t).round() as u8))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(colors: &[u8]) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(2, 2 * colors.len() as u32, |_, y| {
+            Rgba([colors[y as usize / 2], 0, 0, 255])
+        }))
+    }
+
+    #[test]
+    fn frame_order_and_times() {
+        let animation = Animation::parse(r#"{ "frametime": 2, "frames": [2, { "index": 0, "time": 3 }, 1] }"#, 3).unwrap();
+        assert_eq!(animation.duration(), 7);
+        let frames: Vec<_> = (0..8).map(|tick| animation.frame_at(tick)).collect();
+        assert_eq!(frames, vec![(0, 0), (0, 1), (1, 0), (1, 1), (1, 2), (2, 0), (2, 1), (0, 0)]);
+        // Without interpolation only frame changes need an upload
+        assert_eq!(animation.key_at(1), animation.key_at(0));
+
+        let texture = AnimatedTexture::new(&strip(&[10, 20, 30]), animation);
+        assert_eq!(texture.size(), 2);
+        assert_eq!(texture.image_at(0).get_pixel(1, 1)[0], 30);
+        assert_eq!(texture.image_at(4).get_pixel(0, 0)[0], 10);
+        assert_eq!(texture.image_at(12).get_pixel(0, 0)[0], 20);
+    }
+
+    #[test]
+    fn interpolation_blends_into_next_frame() {
+        let animation = Animation::parse(r#"{ "frametime": 4, "interpolate": true }"#, 2).unwrap();
+        assert_eq!(animation.frames, vec![Frame { index: 0, time: 4 }, Frame { index: 1, time: 4 }]);
+        assert_ne!(animation.key_at(1), animation.key_at(0));
+
+        let texture = AnimatedTexture::new(&strip(&[0, 200]), animation);
+        let red: Vec<_> = (0..8).map(|tick| texture.image_at(tick).get_pixel(0, 0)[0]).collect();
+        assert_eq!(red, vec![0, 50, 100, 150, 200, 150, 100, 50]);
+    }
+
+    #[test]
+    fn invalid_animations() {
+        let field = |json: &str| Animation::parse(json, 2).unwrap_err().field;
+        assert_eq!(field(r#"{ "frames": [0, 2] }"#), "frames[1].index");
+        assert_eq!(field(r#"{ "frames": [{ "index": 0, "time": 0 }] }"#), "frames[0].time");
+        assert_eq!(field(r#"{ "frames": [] }"#), "frames");
+        assert_eq!(field(r#"{ "frametime": 0 }"#), "frametime");
+        assert_eq!(field(r#"{ "frame_time": 2 }"#), "frame_time");
+
+        assert_eq!(AnimatedTexture::strip_len(&strip(&[0, 1, 2])), Some(3));
+        assert_eq!(AnimatedTexture::strip_len(&DynamicImage::new_rgba8(16, 24)), None);
+    }
+}
@@ -1,10 +1,9 @@
-use std::{fs::File, io::Read};
-
-use guillotiere::AtlasAllocator;
 use image::DynamicImage;
 use nalgebra::Vector3;
 use once_cell::sync::Lazy;
 
+use crate::{game::biome::TintType, resources::manager::ResourceManager};
+
 use super::shapes::Quad;
 
 /// Quads of a cube model with width 1.0 centered at the origin
@@ -17,29 +16,31 @@ pub static DEFAULT_CUBE_MODEL_QUADS: Lazy<[Quad; 6]> = Lazy::new(|| {[
     Quad::new_z_center(Vector3::new(0.0, 0.0, -0.5), 1.0, 1.0, false),
 ]});
 
+#[derive(Clone)]
 pub struct CubeModel {
     pub textures: Vec<DynamicImage>,
+    /// Index into `textures` for every face, indexed by `Direction`
     pub face_textures: [usize; 6],
+    /// Biome color of every face, indexed by `Direction`
+    pub tints: [Option<TintType>; 6],
 }
 
 impl CubeModel {
-    pub fn new(texture_path: &str) -> Self {
-        let path = format!("assets/{}", texture_path);
-        println!("{}", path);
-        let file = File::open(path).unwrap();
-        let image_tex = image::load_from_memory(
-            file.bytes().map(|b| { b.unwrap() })
-            .collect::<Vec<_>>().as_slice()
-        ).unwrap();
-        
+    /// Reads the texture through the resource packs, see `from_image`
+    pub fn load(resources: &ResourceManager, texture: &str) -> Result<Self, String> {
+        Ok(Self::from_image(resources.load_image(texture)?))
+    }
+
+    /// A single texture for every face, or top, side and bottom textures
+    /// stacked vertically in an image three times as high as it is wide
+    pub fn from_image(image_tex: DynamicImage) -> Self {
         let mut textures = Vec::new();
-        let face_textures;
 
         let img_height = image_tex.height();
         let img_width = image_tex.width();
         let aspect_ratio = img_height as f32 / img_width as f32;
         
-        if aspect_ratio == 3.0 {
+        let face_textures = if aspect_ratio == 3.0 {
             let single_height = img_width;
 
             // Top
@@ -49,21 +50,41 @@ impl CubeModel {
             // Bottom
             textures.push(image_tex.crop_imm(0, 2 * single_height, img_width, single_height));
 
-            face_textures = [0, 1, 1, 1, 1, 2];
+            // East, west, up, down, south, north
+            [1, 1, 0, 2, 1, 1]
         } else {
             textures.push(image_tex);
-            face_textures = [0; 6];
-        }
+            [0; 6]
+        };
 
         Self {
             textures,
             face_textures,
+            tints: [None; 6],
         }
     }
 }
 
-impl Default for CubeModel {
-    fn default() -> Self {
-        Self::new("stone.png")
+#[cfg(test)]
+mod tests {
+    use image::{RgbaImage, Rgba};
+
+    use crate::game::pos::Direction;
+
+    use super::*;
+
+    #[test]
+    fn faces_of_stacked_textures() {
+        let colors = [Rgba([255, 0, 0, 255]), Rgba([0, 255, 0, 255]), Rgba([0, 0, 255, 255])];
+        let image = RgbaImage::from_fn(4, 12, |_, y| colors[y as usize / 4]);
+        let model = CubeModel::from_image(DynamicImage::ImageRgba8(image));
+        assert_eq!(model.textures.len(), 3);
+
+        let color = |dir: Direction| *model.textures[model.face_textures[dir.index()]].to_rgba8().get_pixel(0, 0);
+        assert_eq!(color(Direction::Up), colors[0]);
+        assert_eq!(color(Direction::Down), colors[2]);
+        for dir in [Direction::East, Direction::West, Direction::South, Direction::North] {
+            assert_eq!(color(dir), colors[1]);
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,12 +1,77 @@
 use nalgebra::Matrix4;
 
+/// Perspective projection mapping `z_near` to a depth of 0 and `z_far` to 1,
+/// the depth range wgpu clips to
 pub fn perspective(fov_y: f32, aspect: f32, z_near: f32, z_far: f32) -> Matrix4<f32> {
     let f = (fov_y / 2.0).tan().recip();
 
     Matrix4::new(
         f / aspect, 0.0, 0.0, 0.0, 
         0.0, f, 0.0, 0.0, 
-        0.0, 0.0, (z_far + z_near) / (z_near - z_far), (2.0 * z_far * z_near) / (z_near - z_far), 
+        0.0, 0.0, z_far / (z_near - z_far), (z_far * z_near) / (z_near - z_far), 
         0.0, 0.0, -1.0, 0.0
     )
-}
\ No newline at end of file
+}
+
+/// Perspective projection mapping `z_near` to a depth of 1 and `z_far` to 0.
+/// Paired with a floating point depth buffer this spreads the precision
+/// evenly over the view distance instead of bunching it up near the camera.
+pub fn perspective_reversed_z(fov_y: f32, aspect: f32, z_near: f32, z_far: f32) -> Matrix4<f32> {
+    let f = (fov_y / 2.0).tan().recip();
+
+    Matrix4::new(
+        f / aspect, 0.0, 0.0, 0.0, 
+        0.0, f, 0.0, 0.0, 
+        0.0, 0.0, z_near / (z_far - z_near), (z_far * z_near) / (z_far - z_near), 
+        0.0, 0.0, -1.0, 0.0
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+
+    use super::*;
+
+    fn depth(proj: &Matrix4<f32>, distance: f32) -> f32 {
+        let clip = proj * Vector4::new(0.0, 0.0, -distance, 1.0);
+        clip.z / clip.w
+    }
+
+    #[test]
+    fn regular_range() {
+        let proj = perspective(1.0, 1.5, 0.1, 1000.0);
+        assert!(depth(&proj, 0.1).abs() < 1e-6);
+        assert!((depth(&proj, 1000.0) - 1.0).abs() < 1e-6);
+
+        let mut last = depth(&proj, 0.1);
+        for distance in [0.5, 1.0, 10.0, 100.0, 999.0] {
+            let d = depth(&proj, distance);
+            assert!(d > last && d < 1.0);
+            last = d;
+        }
+    }
+
+    #[test]
+    fn reversed_z_range() {
+        let proj = perspective_reversed_z(1.0, 1.5, 0.1, 1000.0);
+        assert!((depth(&proj, 0.1) - 1.0).abs() < 1e-6);
+        assert!(depth(&proj, 1000.0).abs() < 1e-6);
+
+        let mut last = depth(&proj, 0.1);
+        for distance in [0.5, 1.0, 10.0, 100.0, 999.0] {
+            let d = depth(&proj, distance);
+            assert!(d < last && d > 0.0);
+            last = d;
+        }
+    }
+
+    #[test]
+    fn same_screen_position() {
+        let regular = perspective(1.0, 1.5, 0.1, 1000.0);
+        let reversed = perspective_reversed_z(1.0, 1.5, 0.1, 1000.0);
+        let point = Vector4::new(3.0, -2.0, -20.0, 1.0);
+        let (a, b) = (regular * point, reversed * point);
+        assert_eq!(a.xy() / a.w, b.xy() / b.w);
+    }
+}
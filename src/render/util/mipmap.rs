@@ -0,0 +1,258 @@
+use std::f32::consts::PI;
+
+use image::{RgbaImage, Rgba};
+
+/// Downsampling filter used to generate mip levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MipFilter {
+    /// Averages every 2x2 block, cheap and soft
+    #[default]
+    Box,
+    /// Kaiser windowed sinc over 8x8 texels, keeps distant textures sharper
+    Kaiser,
+}
+
+impl MipFilter {
+    /// Radius of the filter in texels of the destination level
+    fn radius(&self) -> f32 {
+        match self {
+            MipFilter::Box => 0.5,
+            MipFilter::Kaiser => 2.0,
+        }
+    }
+
+    /// Texels of the source level read past the 2x2 block a destination
+    /// texel covers, on each side
+    pub fn reach(&self) -> u32 {
+        (2.0 * self.radius() - 1.0).ceil() as u32
+    }
+
+    /// Weight of a source texel `t` destination texels away from the center
+    fn weight(&self, t: f32) -> f32 {
+        match self {
+            MipFilter::Box => if t.abs() <= 0.5 { 1.0 } else { 0.0 },
+            MipFilter::Kaiser => {
+                const BETA: f32 = 4.0;
+                let x = t / self.radius();
+                if x.abs() >= 1.0 {
+                    return 0.0;
+                }
+                sinc(t) * bessel_i0(BETA * (1.0 - x * x).sqrt()) / bessel_i0(BETA)
+            },
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Modified Bessel function of the first kind of order zero, as a power series
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_sq = x * x / 4.0;
+    for k in 1..32 {
+        term *= half_sq / (k * k) as f32;
+        sum += term;
+        if term < sum * 1e-8 {
+            break;
+        }
+    }
+    sum
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let c = if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+    (c * 255.0).round() as u8
+}
+
+/// Linear, alpha premultiplied pixels so transparent texels don't bleed
+/// their color into their neighbors when averaged
+struct LinearImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl LinearImage {
+    fn from_rgba(image: &RgbaImage) -> Self {
+        let pixels = image.pixels().map(|Rgba([r, g, b, a])| {
+            let a = *a as f32 / 255.0;
+            [srgb_to_linear(*r) * a, srgb_to_linear(*g) * a, srgb_to_linear(*b) * a, a]
+        }).collect();
+
+        Self { width: image.width(), height: image.height(), pixels }
+    }
+
+    fn to_rgba(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let [r, g, b, a] = self.pixels[(y * self.width + x) as usize];
+            let a = a.clamp(0.0, 1.0);
+            if a <= 0.0 {
+                return Rgba([0, 0, 0, 0]);
+            }
+            Rgba([linear_to_srgb(r / a), linear_to_srgb(g / a), linear_to_srgb(b / a), (a * 255.0).round() as u8])
+        })
+    }
+
+    /// Halves both dimensions, one axis at a time
+    fn downsampled(&self, filter: MipFilter) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+
+        let rows = resample(&self.pixels, self.width, self.height, width, filter);
+        // Columns are resampled as the rows of the transposed image
+        let columns = resample(&transpose(&rows, width, self.height), self.height, width, height, filter);
+
+        Self { width, height, pixels: transpose(&columns, height, width) }
+    }
+}
+
+/// Resamples every row of `src` from `src_len` to `dst_len` texels
+fn resample(src: &[[f32; 4]], src_len: u32, rows: u32, dst_len: u32, filter: MipFilter) -> Vec<[f32; 4]> {
+    let scale = src_len as f32 / dst_len as f32;
+    let radius = filter.radius() * scale;
+
+    // The weights are the same for every row
+    let taps: Vec<Vec<(u32, f32)>> = (0..dst_len).map(|i| {
+        let center = (i as f32 + 0.5) * scale;
+        let first = (center - radius).floor() as i32;
+        let last = (center + radius).ceil() as i32;
+        let mut taps: Vec<_> = (first..=last).filter_map(|j| {
+            let weight = filter.weight((j as f32 + 0.5 - center) / scale);
+            // Edge extension outside of the image
+            (weight != 0.0).then(|| (j.clamp(0, src_len as i32 - 1) as u32, weight))
+        }).collect();
+
+        let total: f32 = taps.iter().map(|(_, w)| w).sum();
+        for (_, w) in &mut taps {
+            *w /= total;
+        }
+        taps
+    }).collect();
+
+    let mut ret = Vec::with_capacity((dst_len * rows) as usize);
+    for row in src.chunks(src_len as usize) {
+        for taps in &taps {
+            let mut sum = [0.0; 4];
+            for (j, w) in taps {
+                for (c, value) in row[*j as usize].iter().enumerate() {
+                    sum[c] += value * w;
+                }
+            }
+            ret.push(sum);
+        }
+    }
+    ret
+}
+
+/// Swaps rows and columns of an image `width` texels wide
+fn transpose(src: &[[f32; 4]], width: u32, height: u32) -> Vec<[f32; 4]> {
+    let mut ret = vec![[0.0; 4]; src.len()];
+    for y in 0..height {
+        for x in 0..width {
+            ret[(x * height + y) as usize] = src[(y * width + x) as usize];
+        }
+    }
+    ret
+}
+
+/// Number of levels of a full mip chain down to 1x1
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Every mip level of `base`, starting with `base` itself and ending at 1x1.
+/// Colors are averaged in linear space and weighted by alpha.
+pub fn generate_mip_chain(base: &RgbaImage, filter: MipFilter) -> Vec<RgbaImage> {
+    let mut ret = vec![base.clone()];
+    let mut level = LinearImage::from_rgba(base);
+    while level.width > 1 || level.height > 1 {
+        level = level.downsampled(filter);
+        ret.push(level.to_rgba());
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+    #[test]
+    fn chain_sizes() {
+        let chain = generate_mip_chain(&RgbaImage::new(16, 4), MipFilter::Box);
+        let sizes: Vec<_> = chain.iter().map(|level| level.dimensions()).collect();
+        assert_eq!(sizes, vec![(16, 4), (8, 2), (4, 1), (2, 1), (1, 1)]);
+        assert_eq!(mip_level_count(16, 4), 5);
+        assert_eq!(mip_level_count(1024, 1024), 11);
+    }
+
+    #[test]
+    fn uniform_color_is_preserved() {
+        let image = RgbaImage::from_pixel(16, 16, Rgba([200, 100, 50, 255]));
+        for filter in [MipFilter::Box, MipFilter::Kaiser] {
+            for level in generate_mip_chain(&image, filter) {
+                assert!(level.pixels().all(|p| *p == Rgba([200, 100, 50, 255])), "{:?}", filter);
+            }
+        }
+    }
+
+    #[test]
+    fn box_averages_in_linear_space() {
+        let image = RgbaImage::from_fn(2, 2, |x, y| {
+            if (x + y) % 2 == 0 { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 255]) }
+        });
+        let chain = generate_mip_chain(&image, MipFilter::Box);
+        // Half of the light, not half of the sRGB value
+        assert_eq!(*chain[1].get_pixel(0, 0), Rgba([188, 188, 188, 255]));
+    }
+
+    #[test]
+    fn transparent_texels_dont_bleed() {
+        // Cutout texture, opaque red on the left and transparent green on the right
+        let image = RgbaImage::from_fn(8, 8, |x, _| {
+            if x < 4 { RED } else { Rgba([0, 255, 0, 0]) }
+        });
+
+        for filter in [MipFilter::Box, MipFilter::Kaiser] {
+            let chain = generate_mip_chain(&image, filter);
+            for level in &chain[1..] {
+                for pixel in level.pixels().filter(|p| p[3] > 0) {
+                    assert_eq!(pixel[1], 0, "{:?}", filter);
+                    assert_eq!(pixel[0], 255, "{:?}", filter);
+                }
+            }
+            let last = chain.last().unwrap().get_pixel(0, 0);
+            assert!((last[3] as i32 - 128).abs() <= 1, "{:?}", last);
+        }
+    }
+
+    #[test]
+    fn finest_detail_averages_to_gray() {
+        // Alternating columns can't be represented at half the resolution
+        let image = RgbaImage::from_fn(16, 16, |x, _| {
+            if x % 2 == 0 { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 255]) }
+        });
+
+        for filter in [MipFilter::Box, MipFilter::Kaiser] {
+            let level = &generate_mip_chain(&image, filter)[1];
+            // Away from the edges, where the image is extended
+            for x in 2..6 {
+                assert_eq!(*level.get_pixel(x, 4), Rgba([188, 188, 188, 255]), "{:?}", filter);
+            }
+        }
+    }
+}
@@ -3,4 +3,6 @@ pub mod cube_model;
 pub mod texture;
 pub mod math;
 pub mod shapes;
-pub mod texture_atlas;
\ No newline at end of file
+pub mod texture_atlas;
+pub mod mipmap;
+pub mod animation;
\ No newline at end of file
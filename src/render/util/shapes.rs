@@ -2,6 +2,7 @@ use nalgebra::{Vector3, Vector2};
 
 use super::vertex::Vertex;
 
+#[derive(Debug, Clone)]
 pub struct Triangle {
     pub a: Vector3<f32>,
     pub b: Vector3<f32>,
@@ -22,6 +23,7 @@ impl Triangle {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct Quad {
     vertices: [Vector3<f32>; 4],
 }
@@ -91,15 +93,42 @@ impl Quad {
         &self.vertices
     }
 
-    pub fn get_vertices(&self) -> [Vertex; 6] {
-        let normal = self.get_triangles().0.normal();
+    pub fn translated(&self, offset: Vector3<f32>) -> Self {
+        Self::new_unchecked(self.vertices.map(|v| v + offset))
+    }
+
+    /// Unit normal of the front face
+    pub fn normal(&self) -> Vector3<f32> {
+        self.get_triangles().0.normal().normalize()
+    }
 
-        let a = Vertex::new(self.vertices[0], Vector2::new(0, 0), normal);
-        let b = Vertex::new(self.vertices[1], Vector2::new(1, 0), normal);
-        let c = Vertex::new(self.vertices[2], Vector2::new(1, 1), normal);
-        let d = Vertex::new(self.vertices[3], Vector2::new(0, 1), normal);
+    /// The four corners, with texture coordinates repeating once per unit of length
+    pub fn get_corners(&self) -> [Vertex; 4] {
+        let normal = self.normal();
+        let width = (self.vertices[1] - self.vertices[0]).norm();
+        let height = (self.vertices[2] - self.vertices[1]).norm();
+
+        [
+            Vertex::new(self.vertices[0], Vector2::new(0.0, 0.0), normal),
+            Vertex::new(self.vertices[1], Vector2::new(width, 0.0), normal),
+            Vertex::new(self.vertices[2], Vector2::new(width, height), normal),
+            Vertex::new(self.vertices[3], Vector2::new(0.0, height), normal),
+        ]
+    }
 
-        [a, b, c, a, c, d]
+    pub fn get_vertices(&self) -> [Vertex; 6] {
+        Self::triangulate(self.get_corners(), false)
+    }
+
+    /// Splits corners into two triangles along the diagonal from the first to
+    /// the third corner, or from the second to the fourth if `flipped`
+    pub fn triangulate(corners: [Vertex; 4], flipped: bool) -> [Vertex; 6] {
+        let [a, b, c, d] = corners;
+        if flipped {
+            [b, c, d, b, d, a]
+        } else {
+            [a, b, c, a, c, d]
+        }
     }
 
     pub fn get_triangles(&self) -> (Triangle, Triangle) {
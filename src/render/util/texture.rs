@@ -1,4 +1,4 @@
-use std::{num::NonZeroU32, fs::File, io::Read};
+use std::{num::NonZeroU32, fs};
 
 use image::DynamicImage;
 use wgpu::{Extent3d, Device};
@@ -83,11 +83,8 @@ impl TextureArray {
         path: &str,
     ) {
         let path = format!("assets/{}", path);
-        let file = File::open(path).unwrap();
-        let image = image::load_from_memory(
-            file.bytes().map(|b| { b.unwrap() })
-            .collect::<Vec<_>>().as_slice()
-        ).unwrap();
+        let bytes = fs::read(path).unwrap();
+        let image = image::load_from_memory(&bytes).unwrap();
 
         self.push_image(queue, image);
     }
@@ -138,4 +135,43 @@ impl TextureArray {
 
         (layout, bind_group)
     }
-}
\ No newline at end of file
+}
+
+/// Depth attachment matching the size of the surface, has to be recreated
+/// whenever the surface is resized
+pub struct DepthTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+}
+
+impl DepthTexture {
+    pub fn new(device: &Device, config: &wgpu::SurfaceConfiguration, format: wgpu::TextureFormat) -> Self {
+        assert!(
+            format.describe().sample_type == wgpu::TextureSampleType::Depth,
+            "{:?} is not a depth format", format
+        );
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            label: Some("depth_texture"),
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            texture,
+            view,
+            format,
+        }
+    }
+}
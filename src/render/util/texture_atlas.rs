@@ -1,30 +1,104 @@
-use guillotiere::{AtlasAllocator, size2, euclid::{Size2D, UnknownUnit}, Allocation};
-use image::{DynamicImage, Rgba32FImage, ImageBuffer, Rgba, RgbaImage};
-use wgpu::{Device, Extent3d, Texture, Queue};
+use guillotiere::{AtlasAllocator, size2, Allocation};
+use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+use wgpu::{Device, Extent3d, Queue, util::DeviceExt};
+
+use super::{animation::AnimatedTexture, mipmap::{MipFilter, generate_mip_chain, mip_level_count}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasOptions {
+    /// Pixels around every texture filled by extending its edges, so the
+    /// first mip levels don't mix in neighboring textures. Wider filters
+    /// need wider gutters for the same number of levels.
+    pub gutter: u32,
+    pub mip_filter: MipFilter,
+}
+
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        Self {
+            gutter: 4,
+            mip_filter: MipFilter::Box,
+        }
+    }
+}
 
 pub struct TextureAtlas {
     allocator: AtlasAllocator,
+    options: AtlasOptions,
     textures: Vec<AllocatedTexture>,
-    device_texture: Option<wgpu::Texture>
+    animations: Vec<AtlasAnimation>,
+    /// The uploaded atlas, kept to regenerate the mips around animated textures
+    image: Option<RgbaImage>,
+    device_texture: Option<wgpu::Texture>,
+    pointer_buffer: Option<wgpu::Buffer>,
 }
 
 impl TextureAtlas {
     pub fn new() -> Self {
+        Self::with_options(AtlasOptions::default())
+    }
+
+    pub fn with_options(options: AtlasOptions) -> Self {
         Self {
             allocator: AtlasAllocator::new(size2(1024, 1024)),
+            options,
             textures: Vec::new(),
+            animations: Vec::new(),
+            image: None,
             device_texture: None,
+            pointer_buffer: None,
         }
     }
 
-    pub fn add_texture(&mut self, texture: DynamicImage) {
-        let tex_size = size2(texture.width() as i32, texture.height() as i32);
+    /// Returns the index of the texture's atlas pointer
+    pub fn add_texture(&mut self, texture: DynamicImage) -> u32 {
+        let padding = 2 * self.options.gutter as i32;
+        let tex_size = size2(texture.width() as i32 + padding, texture.height() as i32 + padding);
         let mut allocation = self.allocator.allocate(tex_size);
         while allocation.is_none() {
             self.allocator.grow(self.allocator.size() * 2);
             allocation = self.allocator.allocate(tex_size);
         }
-        self.textures.push(AllocatedTexture::new(allocation.unwrap(), texture));
+        self.textures.push(AllocatedTexture::new(allocation.unwrap(), self.options.gutter, texture));
+        (self.textures.len() - 1) as u32
+    }
+
+    /// Adds an animation showing its first frame, returns the index of the
+    /// texture's atlas pointer
+    pub fn add_animated_texture(&mut self, texture: AnimatedTexture) -> u32 {
+        let index = self.add_texture(DynamicImage::ImageRgba8(texture.image_at(0)));
+        self.animations.push(AtlasAnimation {
+            index: index as usize,
+            shown: texture.animation.key_at(0),
+            texture,
+        });
+        index
+    }
+
+    /// Mip levels that don't sample outside of the gutters, the levels after
+    /// that are still uploaded but never sampled
+    pub fn usable_mip_levels(&self) -> u32 {
+        let size = self.allocator.size();
+        let full = mip_level_count(size.width as u32, size.height as u32);
+        // Every level halves the gutter, minus what the filter reads past it
+        let reach = self.options.mip_filter.reach();
+        let mut gutter = self.options.gutter;
+        let mut levels = 1;
+        loop {
+            gutter = gutter.saturating_sub(reach) / 2;
+            if gutter == 0 {
+                break levels.min(full);
+            }
+            levels += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
     }
 
     pub fn build_atlas(&self) -> RgbaImage {
@@ -35,21 +109,80 @@ impl TextureAtlas {
         img
     }
 
+    /// The atlas followed by every mip level down to 1x1
+    pub fn build_mip_chain(&self) -> Vec<RgbaImage> {
+        generate_mip_chain(&self.build_atlas(), self.options.mip_filter)
+    }
+
     /// Should be called AFTER `get_bind_group_and_layout`
-    pub fn write_buffer(&self, queue: &Queue) {
+    pub fn write_buffer(&mut self, queue: &Queue) {
         if self.device_texture.is_none() { return }
 
-        let img = self.build_atlas();
+        let chain = self.build_mip_chain();
+        for (level, img) in chain.iter().enumerate() {
+            self.write_region(queue, level as u32, 0, 0, img);
+        }
+        self.image = chain.into_iter().next();
+    }
+
+    /// Switches every animation to its frame at `tick`. Returns the indices
+    /// of the textures that changed.
+    fn update_animations(&mut self, tick: u64) -> Vec<usize> {
+        let mut changed = Vec::new();
+        for animation in &mut self.animations {
+            let key = animation.texture.animation.key_at(tick);
+            if key != animation.shown {
+                animation.shown = key;
+                self.textures[animation.index].texture = DynamicImage::ImageRgba8(animation.texture.image_at(tick));
+                changed.push(animation.index);
+            }
+        }
+        changed
+    }
+
+    /// Uploads the animation frames of `tick` that aren't shown yet. Only the
+    /// areas around the changed textures are written.
+    pub fn animate(&mut self, queue: &Queue, tick: u64) {
+        let Some(mut image) = self.image.take() else { return };
+
+        for index in self.update_animations(tick) {
+            self.textures[index].write_to_image(&mut image);
+            let (x, y, levels) = self.region_mip_chain(&image, index);
+            for (level, img) in levels.iter().enumerate() {
+                self.write_region(queue, level as u32, x >> level, y >> level, img);
+            }
+        }
+        self.image = Some(image);
+    }
+
+    /// Origin and usable mip levels of the area around a texture. The area is
+    /// aligned to the smallest usable level, so its levels match the ones
+    /// of the whole atlas.
+    fn region_mip_chain(&self, atlas: &RgbaImage, index: usize) -> (u32, u32, Vec<RgbaImage>) {
+        let levels = self.usable_mip_levels();
+        let align = 1 << (levels - 1);
+        let rect = self.textures[index].alloc.rectangle;
+        let (x, y) = (rect.min.x as u32 / align * align, rect.min.y as u32 / align * align);
+        let max_x = (rect.max.x as u32).next_multiple_of(align).min(atlas.width());
+        let max_y = (rect.max.y as u32).next_multiple_of(align).min(atlas.height());
+
+        let region = image::imageops::crop_imm(atlas, x, y, max_x - x, max_y - y).to_image();
+        let mut chain = generate_mip_chain(&region, self.options.mip_filter);
+        chain.truncate(levels as usize);
+        (x, y, chain)
+    }
+
+    fn write_region(&self, queue: &Queue, level: u32, x: u32, y: u32, img: &RgbaImage) {
         let size = Extent3d { width: img.width(), height: img.height(), depth_or_array_layers: 1 };
 
         queue.write_texture(
             wgpu::ImageCopyTexture {
                 texture: self.device_texture.as_ref().unwrap(),
-                mip_level: 0,
-                origin: wgpu::Origin3d::default(),
+                mip_level: level,
+                origin: wgpu::Origin3d { x, y, z: 0 },
                 aspect: wgpu::TextureAspect::All,
             }, 
-            &img, 
+            img, 
             wgpu::ImageDataLayout {
                 offset: 0,
                 bytes_per_row: std::num::NonZeroU32::new(4 * size.width),
@@ -60,11 +193,7 @@ impl TextureAtlas {
     }
 
     pub fn get_atlas_pointers(&self) -> Vec<RawAtlasPointer> {
-        let mut ret = Vec::new();
-        for texture in &self.textures {
-            ret.push(texture.alloc.into());
-        }
-        ret
+        self.textures.iter().map(AllocatedTexture::pointer).collect()
     }
 
     pub fn get_bind_group_layout(device: &Device) -> wgpu::BindGroupLayout {
@@ -86,13 +215,24 @@ impl TextureAtlas {
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
             label: Some("texture_bind_group_layout"),
         })
     }
 
     /// Creates a bind group for the texture and returns both the layout and the bind group itself.
-    pub fn get_bind_group_and_layout(&mut self, device: &Device) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
+    /// No textures can be added afterwards.
+    pub fn get_bind_group_and_layout(&mut self, device: &Device, filter: wgpu::FilterMode) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
         let layout = Self::get_bind_group_layout(device);
 
         self.device_texture = Some(device.create_texture(&wgpu::TextureDescriptor {
@@ -101,7 +241,7 @@ impl TextureAtlas {
                 height: self.allocator.size().height as u32, 
                 depth_or_array_layers: 1 
             },
-            mip_level_count: 1,
+            mip_level_count: mip_level_count(self.allocator.size().width as u32, self.allocator.size().height as u32),
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
@@ -113,7 +253,7 @@ impl TextureAtlas {
             &wgpu::TextureViewDescriptor {
                 label: Some("Texture Atlas View"),
                 format: Some(wgpu::TextureFormat::Rgba8UnormSrgb),
-                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                dimension: Some(wgpu::TextureViewDimension::D2),
                 aspect: wgpu::TextureAspect::All,
                 base_mip_level: 0,
                 mip_level_count: None,
@@ -126,12 +266,24 @@ impl TextureAtlas {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: filter,
+            lod_max_clamp: (self.usable_mip_levels() - 1) as f32,
             ..Default::default()
         });
 
+        // Storage buffers can't be empty
+        let mut pointers = self.get_atlas_pointers();
+        if pointers.is_empty() {
+            pointers.push(RawAtlasPointer::default());
+        }
+        self.pointer_buffer = Some(device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Atlas Pointer Buffer"),
+            contents: bytemuck::cast_slice(&pointers),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        }));
+
         let bind_group = device.create_bind_group(
             &wgpu::BindGroupDescriptor {
                 layout: &layout,
@@ -143,7 +295,11 @@ impl TextureAtlas {
                     wgpu::BindGroupEntry {
                         binding: 1,
                         resource: wgpu::BindingResource::Sampler(&sampler),
-                    }
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.pointer_buffer.as_ref().unwrap().as_entire_binding(),
+                    },
                 ],
                 label: Some("texture_bind_group"),
             }
@@ -153,8 +309,15 @@ impl TextureAtlas {
     }
 }
 
+impl Default for TextureAtlas {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pixel rectangle of a texture in the atlas, `max` is exclusive
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct RawAtlasPointer {
     pub min: [i32; 2],
     pub max: [i32; 2],
@@ -169,36 +332,163 @@ impl From<Allocation> for RawAtlasPointer {
     }
 }
 
+/// An animated texture and the frame currently in the atlas
+struct AtlasAnimation {
+    index: usize,
+    texture: AnimatedTexture,
+    shown: (usize, u32),
+}
+
 #[derive(Debug, Clone)]
 pub struct AllocatedTexture {
     pub alloc: Allocation,
+    /// Padding between the edges of the allocation and the texture
+    pub gutter: u32,
     pub texture: DynamicImage,
 }
 
 impl AllocatedTexture {
-    pub fn new(alloc: Allocation, texture: DynamicImage) -> Self {
+    pub fn new(alloc: Allocation, gutter: u32, texture: DynamicImage) -> Self {
         Self {
             alloc,
+            gutter,
             texture,
         }
     }
 
+    /// The texture without its gutter
+    pub fn pointer(&self) -> RawAtlasPointer {
+        let min = self.alloc.rectangle.min;
+        let (x, y) = (min.x + self.gutter as i32, min.y + self.gutter as i32);
+        RawAtlasPointer {
+            min: [x, y],
+            max: [x + self.texture.width() as i32, y + self.texture.height() as i32],
+        }
+    }
+
+    /// Writes the texture and fills the gutter with copies of its edges
     fn write_to_image(&self, img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>) {
         let rect = self.alloc.rectangle;
+        let tex = self.texture.to_rgba8();
+        let gutter = self.gutter as i32;
+
+        for x in 0..rect.width() {
+            for y in 0..rect.height() {
+                let tex_x = (x - gutter).clamp(0, tex.width() as i32 - 1);
+                let tex_y = (y - gutter).clamp(0, tex.height() as i32 - 1);
+                let pixel = *tex.get_pixel(tex_x as u32, tex_y as u32);
+                img.put_pixel((rect.min.x + x) as u32, (rect.min.y + y) as u32, pixel);
+            }
+        }
+    }
+}
 
-        let x_range = 0u32..rect.width() as u32;
-        let y_range = 0u32..rect.height() as u32;
+#[cfg(test)]
+mod tests {
+    use crate::render::util::animation::Animation;
 
-        let x_off = rect.min.x as u32;
-        let y_off = rect.min.y as u32;
+    use super::*;
 
-        let tex = self.texture.to_rgba8();
+    #[test]
+    fn pointers_match_added_textures() {
+        let mut atlas = TextureAtlas::new();
+        let sizes = [(16, 16), (32, 16), (16, 16), (700, 700)];
+        for (i, (w, h)) in sizes.into_iter().enumerate() {
+            let image = RgbaImage::from_pixel(w, h, Rgba([i as u8, 0, 0, 255]));
+            assert_eq!(atlas.add_texture(DynamicImage::ImageRgba8(image)), i as u32);
+        }
 
-        for x in x_range {
-            for y in y_range.clone() {
-                let pixel = tex.get_pixel(x, y).clone();
-                img.put_pixel(x_off + x, y_off + y, pixel);
+        let pointers = atlas.get_atlas_pointers();
+        let img = atlas.build_atlas();
+        for (i, (pointer, (w, h))) in pointers.iter().zip(sizes).enumerate() {
+            assert_eq!(pointer.max[0] - pointer.min[0], w as i32);
+            assert_eq!(pointer.max[1] - pointer.min[1], h as i32);
+            assert_eq!(img.get_pixel(pointer.min[0] as u32, pointer.min[1] as u32)[0], i as u8);
+            assert_eq!(img.get_pixel(pointer.max[0] as u32 - 1, pointer.max[1] as u32 - 1)[0], i as u8);
+        }
+    }
+
+    #[test]
+    fn gutters_extend_edges() {
+        let mut atlas = TextureAtlas::with_options(AtlasOptions { gutter: 2, ..Default::default() });
+        let image = RgbaImage::from_fn(4, 4, |x, y| Rgba([x as u8 * 10, y as u8 * 10, 0, 255]));
+        atlas.add_texture(DynamicImage::ImageRgba8(image.clone()));
+
+        let pointer = atlas.get_atlas_pointers()[0];
+        assert_eq!(pointer.max[0] - pointer.min[0], 4);
+        let img = atlas.build_atlas();
+        let at = |x: i32, y: i32| *img.get_pixel((pointer.min[0] + x) as u32, (pointer.min[1] + y) as u32);
+
+        assert_eq!(at(0, 0), *image.get_pixel(0, 0));
+        assert_eq!(at(3, 2), *image.get_pixel(3, 2));
+        assert_eq!(at(-2, -2), *image.get_pixel(0, 0));
+        assert_eq!(at(-1, 2), *image.get_pixel(0, 2));
+        assert_eq!(at(5, 1), *image.get_pixel(3, 1));
+        assert_eq!(at(4, 5), *image.get_pixel(3, 3));
+    }
+
+    fn assert_usable_mips_dont_bleed(options: AtlasOptions, usable: u32) {
+        let colors = [Rgba([255, 0, 0, 255]), Rgba([0, 255, 0, 255]), Rgba([0, 0, 255, 255])];
+        let mut atlas = TextureAtlas::with_options(options);
+        for _ in 0..20 {
+            for color in colors {
+                atlas.add_texture(DynamicImage::ImageRgba8(RgbaImage::from_pixel(16, 16, color)));
+            }
+        }
+        assert_eq!(atlas.usable_mip_levels(), usable);
+
+        let chain = atlas.build_mip_chain();
+        for (i, pointer) in atlas.get_atlas_pointers().iter().enumerate() {
+            for (level, img) in chain.iter().enumerate().take(atlas.usable_mip_levels() as usize) {
+                let scale = 1 << level;
+                for x in pointer.min[0] / scale..pointer.max[0] / scale {
+                    for y in pointer.min[1] / scale..pointer.max[1] / scale {
+                        assert_eq!(*img.get_pixel(x as u32, y as u32), colors[i % 3], "level {}", level);
+                    }
+                }
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn usable_mips_dont_bleed() {
+        assert_usable_mips_dont_bleed(AtlasOptions { gutter: 4, mip_filter: MipFilter::Box }, 3);
+    }
+
+    #[test]
+    fn usable_kaiser_mips_dont_bleed() {
+        // The kernel reaches 3 texels past each 2x2 block, so 4 texels of
+        // gutter are only enough for the first level
+        assert_usable_mips_dont_bleed(AtlasOptions { gutter: 4, mip_filter: MipFilter::Kaiser }, 1);
+        assert_usable_mips_dont_bleed(AtlasOptions { gutter: 16, mip_filter: MipFilter::Kaiser }, 3);
+    }
+
+    #[test]
+    fn animated_regions_match_whole_atlas() {
+        let mut atlas = TextureAtlas::new();
+        for i in 0..5 {
+            atlas.add_texture(DynamicImage::ImageRgba8(RgbaImage::from_pixel(16, 16, Rgba([i * 40, 0, 0, 255]))));
+        }
+        let strip = RgbaImage::from_fn(16, 32, |x, y| {
+            if y < 16 { Rgba([0, 255, 0, 255]) } else { Rgba([0, 0, x as u8 * 16, 255]) }
+        });
+        let animation = Animation::parse(r#"{ "frametime": 2 }"#, 2).unwrap();
+        let index = atlas.add_animated_texture(AnimatedTexture::new(&DynamicImage::ImageRgba8(strip), animation)) as usize;
+
+        assert!(atlas.update_animations(1).is_empty());
+        assert_eq!(atlas.update_animations(2), vec![index]);
+        assert!(atlas.update_animations(3).is_empty());
+
+        let chain = atlas.build_mip_chain();
+        let pointer = atlas.get_atlas_pointers()[index];
+        assert_eq!(*chain[0].get_pixel(pointer.min[0] as u32 + 1, pointer.min[1] as u32), Rgba([0, 0, 16, 255]));
+
+        let (x, y, levels) = atlas.region_mip_chain(&chain[0], index);
+        assert_eq!(levels.len(), atlas.usable_mip_levels() as usize);
+        for (level, img) in levels.iter().enumerate() {
+            for (px, py, pixel) in img.enumerate_pixels() {
+                assert_eq!(pixel, chain[level].get_pixel((x >> level) + px, (y >> level) + py), "level {}", level);
+            }
+        }
+    }
+}
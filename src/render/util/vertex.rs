@@ -1,23 +1,31 @@
 use nalgebra::{Vector3, Vector2};
-use wgpu::VertexFormat;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct VertexRaw {
     pub position: [f32; 3],
-    pub tex_coord: [i32; 2],
+    pub tex_coord: [f32; 2],
     pub normal: [f32; 3],
+    /// Number of blocks occluding the vertex, from 0 to 3
+    pub ao: u32,
+    /// Index into the atlas pointers
+    pub texture: u32,
+    /// Linear color the texture is multiplied with
+    pub tint: [f32; 3],
 }
 
 unsafe impl bytemuck::Pod for VertexRaw {}
 unsafe impl bytemuck::Zeroable for VertexRaw {}
 
 impl VertexRaw {
-    pub const ATTRIBS: [wgpu::VertexAttribute; 3] =
+    pub const ATTRIBS: [wgpu::VertexAttribute; 6] =
         wgpu::vertex_attr_array![
             0 => Float32x3,
-            1 => Sint32x3,
+            1 => Float32x2,
             2 => Float32x3,
+            3 => Uint32,
+            4 => Uint32,
+            5 => Float32x3,
         ];
 
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
@@ -34,16 +42,22 @@ impl VertexRaw {
 #[derive(Copy, Clone, Debug)]
 pub struct Vertex {
     pub pos: Vector3<f32>,
-    pub tex_coord: Vector2<i32>,
+    pub tex_coord: Vector2<f32>,
     pub normal: Vector3<f32>,
+    pub ao: u32,
+    pub texture: u32,
+    pub tint: [f32; 3],
 }
 
 impl Vertex {
-    pub fn new(pos: Vector3<f32>, tex_coord: Vector2<i32>, normal: Vector3<f32>) -> Self {
+    pub fn new(pos: Vector3<f32>, tex_coord: Vector2<f32>, normal: Vector3<f32>) -> Self {
         Self {
             pos,
             tex_coord,
             normal,
+            ao: 0,
+            texture: 0,
+            tint: [1.0; 3],
         }
     }
 
@@ -63,6 +77,9 @@ impl Vertex {
                 self.normal.y,
                 self.normal.z,
             ],
+            ao: self.ao,
+            texture: self.texture,
+            tint: self.tint,
         }
     }
 }
@@ -84,6 +101,9 @@ impl From<Vertex> for VertexRaw {
                 v.tex_coord.x,
                 v.tex_coord.y,
             ],
+            ao: v.ao,
+            texture: v.texture,
+            tint: v.tint,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,117 @@
+use std::{fmt, path::PathBuf};
+
+use serde::de::DeserializeOwned;
+
+use super::manager::ResourceManager;
+
+/// Every error found while loading one kind of resource
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadErrors<E>(pub Vec<E>);
+
+impl<E> LoadErrors<E> {
+    /// `value` if there are no errors
+    pub fn check<T>(errors: Vec<E>, value: T) -> Result<T, Self> {
+        if errors.is_empty() {
+            Ok(value)
+        } else {
+            Err(Self(errors))
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for LoadErrors<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for LoadErrors<E> {}
+
+/// A problem with one field of a file in a resource pack
+pub trait FileError {
+    fn new(file: PathBuf, field: &str, reason: String) -> Self;
+
+    /// Parsers don't know where the file came from, the loader sets it
+    fn set_file(&mut self, file: PathBuf);
+}
+
+/// Reads every `.json` file in `dir` of every resource pack in name order
+/// and parses it with `parse`, which gets the file name without the
+/// extension and the contents. Every parsed file comes with where it was
+/// read from, the files that failed are left out and their errors returned.
+pub fn load_json_dir<T, E: FileError>(
+    resources: &ResourceManager,
+    dir: &str,
+    mut parse: impl FnMut(&str, &str) -> Result<T, E>,
+) -> (Vec<(PathBuf, T)>, Vec<E>) {
+    let names = match resources.list(dir) {
+        Ok(names) => names,
+        Err(e) => return (Vec::new(), vec![E::new(PathBuf::from(dir), "", format!("failed to read directory: {}", e))]),
+    };
+
+    let mut parsed = Vec::new();
+    let mut errors = Vec::new();
+    for file_name in names.iter().filter(|name| name.ends_with(".json")) {
+        let path = format!("{}/{}", dir, file_name);
+        let file = resources.origin(&path);
+        let result = resources.read_to_string(&path)
+            .map_err(|e| E::new(file.clone(), "", format!("failed to read file: {}", e)))
+            .and_then(|json| parse(file_name.trim_end_matches(".json"), &json).map_err(|mut e| {
+                e.set_file(file.clone());
+                e
+            }));
+        match result {
+            Ok(value) => parsed.push((file, value)),
+            Err(e) => errors.push(e),
+        }
+    }
+    (parsed, errors)
+}
+
+/// Deserializes `json`, failing with the path of the offending field and
+/// the reason
+pub fn parse_json<T: DeserializeOwned>(json: &str) -> Result<T, (String, String)> {
+    let deserializer = &mut serde_json::Deserializer::from_str(json);
+    serde_path_to_error::deserialize(deserializer).map_err(field_error)
+}
+
+/// Path of the offending field, empty for the whole document, and the reason
+/// of a deserialization error
+pub fn field_error<E: fmt::Display>(error: serde_path_to_error::Error<E>) -> (String, String) {
+    let field = error.path().to_string();
+    let field = if field == "." { String::new() } else { field };
+    (field, error.into_inner().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Json {
+        #[allow(dead_code)]
+        values: Vec<u32>,
+    }
+
+    #[test]
+    fn parse_errors_name_the_field() {
+        assert_eq!(parse_json::<Json>(r#"{ "values": [1, -2] }"#).unwrap_err().0, "values[1]");
+        assert_eq!(parse_json::<Json>("3").unwrap_err().0, "");
+    }
+
+    #[test]
+    fn errors_are_shown_one_per_line() {
+        assert_eq!(LoadErrors::check(Vec::<String>::new(), 3), Ok(3));
+        let errors = LoadErrors::check(vec!["a".to_string(), "b".to_string()], 3).unwrap_err();
+        assert_eq!(errors.to_string(), "a\nb");
+    }
+}
@@ -0,0 +1,209 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+use image::DynamicImage;
+use rustc_hash::FxHashMap;
+
+use crate::game::lang::LangJson;
+
+use super::pack::{PackError, ResourcePack};
+
+/// Directory of the language files inside of a pack
+pub const LANG_DIR: &str = "lang";
+
+/// A stack of resource packs. Files are looked up from the last pack to the
+/// first, so later packs override earlier ones.
+pub struct ResourceManager {
+    packs: Vec<ResourcePack>,
+}
+
+impl ResourceManager {
+    /// Packs in priority order, lowest first
+    pub fn new(packs: Vec<ResourcePack>) -> Self {
+        Self { packs }
+    }
+
+    pub fn open<P: AsRef<Path>>(paths: &[P]) -> Result<Self, PackError> {
+        let packs = paths.iter().map(|path| ResourcePack::open(path.as_ref())).collect::<Result<_, _>>()?;
+        Ok(Self::new(packs))
+    }
+
+    /// The `base` pack followed by every directory and `.zip` in `packs_dir`
+    /// in name order, if it exists
+    pub fn discover(base: &Path, packs_dir: &Path) -> Result<Self, PackError> {
+        let mut paths = vec![base.to_path_buf()];
+        match fs::read_dir(packs_dir) {
+            Ok(entries) => {
+                let mut found: Vec<_> = entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.is_dir() || path.extension().is_some_and(|ext| ext == "zip"))
+                    .collect();
+                found.sort();
+                paths.extend(found);
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(source) => return Err(PackError::Io { path: packs_dir.to_path_buf(), source }),
+        }
+        Self::open(&paths)
+    }
+
+    /// Reopens every pack from disk. The current packs are kept if any of them fails.
+    pub fn reload(&mut self) -> Result<(), PackError> {
+        *self = self.reopen()?;
+        Ok(())
+    }
+
+    /// The same packs read again from disk, leaving these untouched
+    pub fn reopen(&self) -> Result<Self, PackError> {
+        let paths: Vec<_> = self.packs.iter().map(|pack| pack.path().to_path_buf()).collect();
+        Self::open(&paths)
+    }
+
+    pub fn packs(&self) -> &[ResourcePack] {
+        &self.packs
+    }
+
+    /// The highest priority pack containing `file`
+    fn find(&self, file: &str) -> Option<&ResourcePack> {
+        self.packs.iter().rev().find(|pack| pack.contains(file))
+    }
+
+    pub fn read(&self, file: &str) -> Result<Vec<u8>, PackError> {
+        for pack in self.packs.iter().rev() {
+            if let Some(data) = pack.read(file)? {
+                return Ok(data);
+            }
+        }
+        Err(PackError::NotFound(file.to_string()))
+    }
+
+    pub fn read_to_string(&self, file: &str) -> Result<String, PackError> {
+        let data = self.read(file)?;
+        String::from_utf8(data).map_err(|e| PackError::Io {
+            path: self.origin(file),
+            source: io::Error::new(io::ErrorKind::InvalidData, e),
+        })
+    }
+
+    /// Reads and decodes an image, returning the reason on failure
+    pub fn load_image(&self, file: &str) -> Result<DynamicImage, String> {
+        let data = self.read(file).map_err(|e| e.to_string())?;
+        image::load_from_memory(&data).map_err(|e| format!("failed to decode `{}`: {}", self.origin(file).display(), e))
+    }
+
+    /// Where `file` is read from, for error messages. Files that don't exist
+    /// are shown relative to the last pack.
+    pub fn origin(&self, file: &str) -> PathBuf {
+        match self.find(file).or(self.packs.last()) {
+            Some(pack) => pack.path().join(file),
+            None => PathBuf::from(file),
+        }
+    }
+
+    /// Names of the files directly inside of `dir` in any pack, sorted
+    pub fn list(&self, dir: &str) -> Result<Vec<String>, PackError> {
+        let mut ret = Vec::new();
+        for pack in &self.packs {
+            ret.extend(pack.list(dir)?);
+        }
+        ret.sort();
+        ret.dedup();
+        Ok(ret)
+    }
+
+    /// Codes of every language with a file in any pack, sorted
+    pub fn languages(&self) -> Result<Vec<String>, PackError> {
+        Ok(self.list(LANG_DIR)?
+            .into_iter()
+            .filter_map(|file| file.strip_suffix(".json").map(str::to_string))
+            .collect())
+    }
+
+    /// Entries of `lang/<code>.json` merged over every pack, so packs can
+    /// override single entries
+    pub fn lang(&self, code: &str) -> Result<FxHashMap<String, String>, PackError> {
+        let file = format!("{}/{}.json", LANG_DIR, code);
+        let mut ret = FxHashMap::default();
+        for pack in &self.packs {
+            let Some(data) = pack.read(&file)? else { continue };
+            let lang: LangJson = serde_json::from_slice(&data).map_err(|e| PackError::Io {
+                path: pack.path().join(&file),
+                source: io::Error::new(io::ErrorKind::InvalidData, e),
+            })?;
+            ret.extend(lang.data);
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{resources::pack::{MANIFEST, PACK_FORMAT}, util::test_dir::TestDir};
+
+    use super::*;
+
+    fn pack(root: &Path, name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(MANIFEST), format!(r#"{{ "name": "{}", "format": {} }}"#, name, PACK_FORMAT)).unwrap();
+        for (file, contents) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn later_packs_override() {
+        let root = TestDir::new("resources-override");
+        let base = pack(&root, "base", &[
+            ("models/a.json", "base a"),
+            ("models/b.json", "base b"),
+            ("lang/en_us.json", r#"{ "block.stone": "Stone", "block.dirt": "Dirt" }"#),
+        ]);
+        let over = pack(&root, "over", &[
+            ("models/b.json", "over b"),
+            ("models/c.json", "over c"),
+            ("lang/en_us.json", r#"{ "block.dirt": "Soil" }"#),
+        ]);
+
+        let resources = ResourceManager::open(&[&base, &over]).unwrap();
+        assert_eq!(resources.read_to_string("models/a.json").unwrap(), "base a");
+        assert_eq!(resources.read_to_string("models/b.json").unwrap(), "over b");
+        assert_eq!(resources.origin("models/b.json"), over.join("models/b.json"));
+        assert_eq!(resources.list("models").unwrap(), vec!["a.json", "b.json", "c.json"]);
+        assert!(matches!(resources.read("models/d.json"), Err(PackError::NotFound(_))));
+
+        let lang = resources.lang("en_us").unwrap();
+        assert_eq!(lang["block.stone"], "Stone");
+        assert_eq!(lang["block.dirt"], "Soil");
+        assert!(resources.lang("de_de").unwrap().is_empty());
+        assert_eq!(resources.languages().unwrap(), vec!["en_us"]);
+    }
+
+    #[test]
+    fn discover_and_reload() {
+        let root = TestDir::new("resources-discover");
+        let base = pack(&root, "base", &[("models/a.json", "base")]);
+        let packs_dir = root.join("packs");
+        pack(&packs_dir, "2_second", &[("models/a.json", "second")]);
+        pack(&packs_dir, "1_first", &[("models/a.json", "first")]);
+        fs::write(packs_dir.join("readme.txt"), "not a pack").unwrap();
+
+        let mut resources = ResourceManager::discover(&base, &packs_dir).unwrap();
+        let names: Vec<_> = resources.packs().iter().map(|pack| pack.manifest().name.as_str()).collect();
+        assert_eq!(names, vec!["base", "1_first", "2_second"]);
+        assert_eq!(resources.read_to_string("models/a.json").unwrap(), "second");
+
+        fs::write(packs_dir.join("2_second/models/a.json"), "changed").unwrap();
+        resources.reload().unwrap();
+        assert_eq!(resources.read_to_string("models/a.json").unwrap(), "changed");
+
+        // A broken pack keeps the previous state
+        fs::remove_file(packs_dir.join("1_first").join(MANIFEST)).unwrap();
+        assert!(resources.reload().is_err());
+        assert_eq!(resources.packs().len(), 3);
+
+        assert_eq!(ResourceManager::discover(&base, &root.join("nothing")).unwrap().packs().len(), 1);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod loader;
+pub mod manager;
+pub mod pack;
+pub mod watcher;
@@ -0,0 +1,237 @@
+use std::{fs, io::{self, Read}, path::{Path, PathBuf}};
+
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+use thiserror::Error;
+use zip::{ZipArchive, result::ZipError};
+
+/// Version of the pack layout this build understands
+pub const PACK_FORMAT: u32 = 1;
+
+/// Manifest at the root of every pack
+pub const MANIFEST: &str = "pack.json";
+
+#[derive(Debug, Error)]
+pub enum PackError {
+    #[error("{}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("{}: invalid zip archive: {source}", path.display())]
+    Zip { path: PathBuf, source: ZipError },
+    #[error("{}: `{MANIFEST}`: {reason}", path.display())]
+    Manifest { path: PathBuf, reason: String },
+    #[error("{}: pack format {format} is not supported, expected {PACK_FORMAT}", path.display())]
+    UnsupportedFormat { path: PathBuf, format: u32 },
+    #[error("invalid resource path `{0}`")]
+    InvalidPath(String),
+    #[error("`{0}` is not in any resource pack")]
+    NotFound(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PackManifest {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Must equal `PACK_FORMAT`
+    pub format: u32,
+}
+
+enum PackSource {
+    Dir,
+    /// Every file of the archive by path, read when the pack is opened
+    Zip(FxHashMap<String, Vec<u8>>),
+}
+
+/// A directory or zip archive of assets with a `pack.json` at its root.
+/// Files are addressed by `/` separated paths relative to the root.
+pub struct ResourcePack {
+    path: PathBuf,
+    manifest: PackManifest,
+    source: PackSource,
+}
+
+impl ResourcePack {
+    /// Opens a directory, or a zip archive if `path` is a file
+    pub fn open(path: &Path) -> Result<Self, PackError> {
+        let io_error = |source| PackError::Io { path: path.to_path_buf(), source };
+
+        let source = if fs::metadata(path).map_err(io_error)?.is_dir() {
+            PackSource::Dir
+        } else {
+            let zip_error = |source| PackError::Zip { path: path.to_path_buf(), source };
+            let mut archive = ZipArchive::new(fs::File::open(path).map_err(io_error)?).map_err(zip_error)?;
+            let mut files = FxHashMap::default();
+            for i in 0..archive.len() {
+                let mut file = archive.by_index(i).map_err(zip_error)?;
+                if file.is_dir() {
+                    continue;
+                }
+                let mut data = Vec::with_capacity(file.size() as usize);
+                file.read_to_end(&mut data).map_err(io_error)?;
+                files.insert(file.name().trim_start_matches("./").to_string(), data);
+            }
+            PackSource::Zip(files)
+        };
+
+        let mut ret = Self {
+            path: path.to_path_buf(),
+            manifest: PackManifest { name: String::new(), description: String::new(), format: 0 },
+            source,
+        };
+
+        let manifest_error = |reason: String| PackError::Manifest { path: path.to_path_buf(), reason };
+        let data = ret.read(MANIFEST)?.ok_or_else(|| manifest_error("missing".to_string()))?;
+        ret.manifest = serde_json::from_slice(&data).map_err(|e| manifest_error(e.to_string()))?;
+        if ret.manifest.format != PACK_FORMAT {
+            return Err(PackError::UnsupportedFormat { path: path.to_path_buf(), format: ret.manifest.format });
+        }
+
+        Ok(ret)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn manifest(&self) -> &PackManifest {
+        &self.manifest
+    }
+
+    /// Contents of a file, `None` if the pack doesn't have it
+    pub fn read(&self, file: &str) -> Result<Option<Vec<u8>>, PackError> {
+        check_path(file)?;
+        match &self.source {
+            PackSource::Dir => match fs::read(self.path.join(file)) {
+                Ok(data) => Ok(Some(data)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(source) => Err(PackError::Io { path: self.path.join(file), source }),
+            },
+            PackSource::Zip(files) => Ok(files.get(file).cloned()),
+        }
+    }
+
+    pub fn contains(&self, file: &str) -> bool {
+        check_path(file).is_ok() && match &self.source {
+            PackSource::Dir => self.path.join(file).is_file(),
+            PackSource::Zip(files) => files.contains_key(file),
+        }
+    }
+
+    /// Names of the files directly inside of `dir`, unsorted
+    pub fn list(&self, dir: &str) -> Result<Vec<String>, PackError> {
+        check_path(dir)?;
+        match &self.source {
+            PackSource::Dir => {
+                let path = self.path.join(dir);
+                let entries = match fs::read_dir(&path) {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+                    Err(source) => return Err(PackError::Io { path, source }),
+                };
+                Ok(entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.path().is_file())
+                    .map(|entry| entry.file_name().to_string_lossy().to_string())
+                    .collect())
+            },
+            PackSource::Zip(files) => {
+                let prefix = format!("{}/", dir.trim_end_matches('/'));
+                Ok(files.keys()
+                    .filter_map(|file| file.strip_prefix(&prefix))
+                    .filter(|name| !name.contains('/'))
+                    .map(str::to_string)
+                    .collect())
+            },
+        }
+    }
+}
+
+/// Paths stay inside of the pack, whatever it is stored as
+fn check_path(file: &str) -> Result<(), PackError> {
+    let valid = !file.is_empty()
+        && !file.contains('\\')
+        && file.split('/').all(|part| !part.is_empty() && part != "." && part != "..");
+    if valid {
+        Ok(())
+    } else {
+        Err(PackError::InvalidPath(file.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{ZipWriter, write::FileOptions};
+
+    use crate::util::test_dir::TestDir;
+
+    use super::*;
+
+    fn manifest(format: u32) -> String {
+        format!(r#"{{ "name": "Test", "format": {} }}"#, format)
+    }
+
+    fn write_zip(path: &Path, files: &[(&str, &str)]) {
+        let mut zip = ZipWriter::new(fs::File::create(path).unwrap());
+        for (name, contents) in files {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn directory_pack() {
+        let dir = TestDir::new("pack-dir");
+        fs::write(dir.join(MANIFEST), manifest(PACK_FORMAT)).unwrap();
+        fs::create_dir_all(dir.join("models/block")).unwrap();
+        fs::write(dir.join("models/a.json"), "a").unwrap();
+        fs::write(dir.join("models/block/b.json"), "b").unwrap();
+
+        let pack = ResourcePack::open(&dir).unwrap();
+        assert_eq!(pack.manifest().name, "Test");
+        assert_eq!(pack.read("models/a.json").unwrap(), Some(b"a".to_vec()));
+        assert_eq!(pack.read("models/c.json").unwrap(), None);
+        assert!(pack.contains("models/block/b.json"));
+        assert_eq!(pack.list("models").unwrap(), vec!["a.json"]);
+        assert!(pack.list("textures").unwrap().is_empty());
+        assert!(matches!(pack.read("../secret"), Err(PackError::InvalidPath(_))));
+        assert!(matches!(pack.read("/etc/passwd"), Err(PackError::InvalidPath(_))));
+    }
+
+    #[test]
+    fn zip_pack() {
+        let dir = TestDir::new("pack-zip");
+        let path = dir.join("pack.zip");
+        let manifest = manifest(PACK_FORMAT);
+        write_zip(&path, &[(MANIFEST, &manifest), ("models/a.json", "a"), ("models/block/b.json", "b")]);
+
+        let pack = ResourcePack::open(&path).unwrap();
+        assert_eq!(pack.read("models/block/b.json").unwrap(), Some(b"b".to_vec()));
+        assert!(!pack.contains("models/b.json"));
+        assert_eq!(pack.list("models").unwrap(), vec!["a.json"]);
+    }
+
+    #[test]
+    fn manifest_errors() {
+        let dir = TestDir::new("pack-manifest");
+        let missing = dir.join("missing.zip");
+        write_zip(&missing, &[("models/a.json", "a")]);
+        assert!(matches!(ResourcePack::open(&missing), Err(PackError::Manifest { .. })));
+
+        let future = dir.join("future.zip");
+        write_zip(&future, &[(MANIFEST, &manifest(PACK_FORMAT + 1))]);
+        let Err(PackError::UnsupportedFormat { format, .. }) = ResourcePack::open(&future) else { panic!("expected a format error") };
+        assert_eq!(format, PACK_FORMAT + 1);
+
+        let typo = dir.join("typo.zip");
+        write_zip(&typo, &[(MANIFEST, r#"{ "name": "Test", "fromat": 1 }"#)]);
+        assert!(matches!(ResourcePack::open(&typo), Err(PackError::Manifest { .. })));
+
+        fs::write(dir.join("broken.zip"), "not a zip").unwrap();
+        assert!(matches!(ResourcePack::open(&dir.join("broken.zip")), Err(PackError::Zip { .. })));
+        assert!(matches!(ResourcePack::open(&dir.join("nothing")), Err(PackError::Io { .. })));
+    }
+}
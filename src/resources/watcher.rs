@@ -0,0 +1,144 @@
+use std::{env, path::{Path, PathBuf}, sync::mpsc::{self, Receiver}, time::{Duration, Instant}};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// How long the files have to stay unchanged before a reload. Editors usually
+/// write a file in several steps.
+pub const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Shader source watched in development builds
+pub const SHADER_DIR: &str = "src/shader";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchTarget {
+    /// Anything read through the resource manager
+    Resources,
+    Shader,
+}
+
+/// What has to be reloaded after the watched files changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Changes {
+    pub resources: bool,
+    pub shader: bool,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        !self.resources && !self.shader
+    }
+}
+
+/// Watches directories for changes during development and reports them once
+/// the files have settled
+pub struct AssetWatcher {
+    _watcher: Option<RecommendedWatcher>,
+    receiver: Receiver<PathBuf>,
+    roots: Vec<(PathBuf, WatchTarget)>,
+    pending: Changes,
+    last_change: Option<Instant>,
+}
+
+impl AssetWatcher {
+    /// Starts watching every root recursively. Roots that don't exist are
+    /// skipped with a warning.
+    pub fn new(roots: &[(&Path, WatchTarget)]) -> notify::Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| match result {
+            Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                for path in event.paths {
+                    let _ = sender.send(path);
+                }
+            },
+            Ok(_) => {},
+            Err(error) => log::warn!("File watcher error: {}", error),
+        })?;
+
+        let mut watched = Vec::new();
+        for &(root, target) in roots {
+            // Events have absolute paths
+            let root = match root.is_absolute() {
+                true => root.to_path_buf(),
+                false => env::current_dir().map_err(notify::Error::io)?.join(root),
+            };
+            match watcher.watch(&root, RecursiveMode::Recursive) {
+                Ok(()) => watched.push((root, target)),
+                Err(error) => log::warn!("Not watching `{}`: {}", root.display(), error),
+            }
+        }
+
+        let mut ret = Self::with_receiver(watched, receiver);
+        ret._watcher = Some(watcher);
+        Ok(ret)
+    }
+
+    fn with_receiver(roots: Vec<(PathBuf, WatchTarget)>, receiver: Receiver<PathBuf>) -> Self {
+        Self {
+            _watcher: None,
+            receiver,
+            roots,
+            pending: Changes::default(),
+            last_change: None,
+        }
+    }
+
+    fn target(&self, path: &Path) -> Option<WatchTarget> {
+        self.roots.iter().find(|(root, _)| path.starts_with(root)).map(|&(_, target)| target)
+    }
+
+    /// Changes since the last call, empty until no file changed for `DEBOUNCE`
+    pub fn poll(&mut self) -> Changes {
+        self.poll_at(Instant::now())
+    }
+
+    fn poll_at(&mut self, now: Instant) -> Changes {
+        while let Ok(path) = self.receiver.try_recv() {
+            match self.target(&path) {
+                Some(WatchTarget::Resources) => self.pending.resources = true,
+                Some(WatchTarget::Shader) => self.pending.shader = true,
+                None => continue,
+            }
+            self.last_change = Some(now);
+        }
+
+        match self.last_change {
+            Some(last) if now.duration_since(last) >= DEBOUNCE => {
+                self.last_change = None;
+                std::mem::take(&mut self.pending)
+            },
+            _ => Changes::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debounced_changes() {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = AssetWatcher::with_receiver(vec![
+            (PathBuf::from("/game/assets"), WatchTarget::Resources),
+            (PathBuf::from("/game/src/shader"), WatchTarget::Shader),
+        ], receiver);
+        let start = Instant::now();
+
+        sender.send(PathBuf::from("/game/assets/textures/block/stone.png")).unwrap();
+        sender.send(PathBuf::from("/game/target/debug/voxel")).unwrap();
+        assert!(watcher.poll_at(start).is_empty());
+
+        // Another write resets the delay
+        let later = start + DEBOUNCE / 2;
+        sender.send(PathBuf::from("/game/src/shader/shader.wgsl")).unwrap();
+        assert!(watcher.poll_at(later).is_empty());
+        assert!(watcher.poll_at(start + DEBOUNCE).is_empty());
+        assert_eq!(watcher.poll_at(later + DEBOUNCE), Changes { resources: true, shader: true });
+        assert!(watcher.poll_at(later + DEBOUNCE * 4).is_empty());
+
+        // Files outside of the roots are ignored
+        sender.send(PathBuf::from("/game/assets_old/stone.png")).unwrap();
+        assert!(watcher.poll_at(later + DEBOUNCE * 8).is_empty());
+        assert!(watcher.poll_at(later + DEBOUNCE * 16).is_empty());
+    }
+}
@@ -0,0 +1,399 @@
+use thiserror::Error;
+
+use crate::game::{biome::BiomeRegistry, chunk::{Chunk, CHUNK_AREA, CHUNK_VOLUME}, palette::{PackedArray, PalettedContainer}, pos::ChunkPos, registry::BlockRegistry};
+
+use super::version::Migrations;
+
+/// Version of the chunk data written by this build
+pub const CHUNK_FORMAT: u32 = 3;
+
+/// Every state by ID of the builds that wrote formats 1 and 2, which saved
+/// IDs instead of names. Never change it.
+const LEGACY_STATES: [&str; 13] = [
+    "air", "coal_ore", "cobblestone", "dirt", "grass_block", "iron_ore", "oak_leaves",
+    "oak_log", "sand", "spruce_leaves", "spruce_log", "stone", "water",
+];
+
+/// Every biome by ID of the builds that wrote formats 1 and 2
+const LEGACY_BIOMES: [&str; 4] = ["desert", "forest", "plains", "tundra"];
+
+/// The names of the IDs formats 1 and 2 saved
+#[derive(Clone, Copy)]
+pub struct LegacyIds {
+    pub states: fn(u32) -> Option<&'static str>,
+    pub biomes: fn(u16) -> Option<&'static str>,
+}
+
+impl LegacyIds {
+    /// The IDs of the builds that wrote formats 1 and 2
+    pub const SHIPPED: LegacyIds = LegacyIds {
+        states: |id| LEGACY_STATES.get(id as usize).copied(),
+        biomes: |id| LEGACY_BIOMES.get(id as usize).copied(),
+    };
+}
+
+/// Why stored chunk data can't be turned back into a chunk
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{0}")]
+pub struct DecodeError(pub String);
+
+/// The registries that turn the IDs of this run into the names chunks are
+/// saved with and back
+#[derive(Clone, Copy)]
+pub struct Registries<'a> {
+    pub blocks: &'a BlockRegistry,
+    pub biomes: &'a BiomeRegistry,
+    /// Names of the IDs in chunks of formats 1 and 2
+    pub legacy: LegacyIds,
+}
+
+impl<'a> Registries<'a> {
+    pub fn new(blocks: &'a BlockRegistry, biomes: &'a BiomeRegistry) -> Self {
+        Self { blocks, biomes, legacy: LegacyIds::SHIPPED }
+    }
+}
+
+/// Upgrades the bytes after the version of chunks saved by older builds
+pub fn chunk_migrations(legacy: LegacyIds) -> Migrations<Vec<u8>> {
+    Migrations::new(1)
+        .with_step(1, narrow_biome_palette)
+        .with_step(2, move |data| name_palettes(data, legacy))
+}
+
+/// Serializes a chunk as the format, its position and the palettes and
+/// packed indices of its blocks and biomes, all little endian and
+/// uncompressed. The palettes hold state strings and biome names, so saves
+/// keep loading when blocks, states or biomes are added.
+pub fn encode_chunk(chunk: &Chunk, registries: Registries) -> Vec<u8> {
+    let mut out = CHUNK_FORMAT.to_le_bytes().to_vec();
+    for v in [chunk.pos.x, chunk.pos.y, chunk.pos.z] {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    encode_container(&mut out, chunk.blocks(), |out, state| write_str(out, &registries.blocks.format_state(*state)));
+    encode_container(&mut out, chunk.biomes(), |out, biome| {
+        write_str(out, &registries.biomes.get(*biome).expect("chunks only hold registered biomes").name);
+    });
+    out
+}
+
+/// Decodes a chunk of any supported format. Properties missing from a saved
+/// state keep their default value, unknown blocks, properties and biomes are
+/// errors.
+pub fn decode_chunk(data: &[u8], registries: Registries) -> Result<Chunk, DecodeError> {
+    let mut reader = Reader { data };
+    let version = reader.u32()?;
+    let data = chunk_migrations(registries.legacy).migrate(version, reader.data.to_vec())
+        .map_err(|e| DecodeError(e.to_string()))?;
+
+    let mut reader = Reader { data: &data };
+    let pos = ChunkPos::new(reader.i32()?, reader.i32()?, reader.i32()?);
+    let blocks = decode_container(&mut reader, CHUNK_VOLUME, |reader| {
+        let state = reader.str()?;
+        registries.blocks.parse_state(state).map_err(|e| DecodeError(e.to_string()))
+    }).map_err(|e| DecodeError(format!("blocks: {}", e)))?;
+    let biomes = decode_container(&mut reader, CHUNK_AREA, |reader| {
+        let name = reader.str()?;
+        registries.biomes.get_by_name(name).map(|(id, _)| id).ok_or_else(|| DecodeError(format!("unknown biome `{}`", name)))
+    }).map_err(|e| DecodeError(format!("biomes: {}", e)))?;
+    if !reader.data.is_empty() {
+        return Err(DecodeError(format!("{} bytes left over", reader.data.len())));
+    }
+    Ok(Chunk::from_parts(pos, blocks, biomes))
+}
+
+/// Palette length, palette, bits per index and the words of the indices
+fn encode_container<T: Clone + PartialEq>(out: &mut Vec<u8>, container: &PalettedContainer<T>, write: impl Fn(&mut Vec<u8>, &T)) {
+    let palette = container.palette();
+    out.extend_from_slice(&(palette.len() as u32).to_le_bytes());
+    for value in palette {
+        write(out, value);
+    }
+    write_indices(out, container);
+}
+
+fn write_indices<T: Clone + PartialEq>(out: &mut Vec<u8>, container: &PalettedContainer<T>) {
+    out.push(container.bits_per_entry() as u8);
+    for word in container.indices().map_or(&[][..], PackedArray::words) {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+}
+
+/// Length as `u16`, then UTF-8
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    let len = u16::try_from(s.len()).expect("names are shorter than 64 KiB");
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn decode_container<'a, T: Clone + PartialEq>(
+    reader: &mut Reader<'a>,
+    len: usize,
+    read: impl Fn(&mut Reader<'a>) -> Result<T, DecodeError>,
+) -> Result<PalettedContainer<T>, DecodeError> {
+    let palette_len = reader.u32()? as usize;
+    if !(1..=len).contains(&palette_len) {
+        return Err(DecodeError(format!("palette of {} entries", palette_len)));
+    }
+    let palette = (0..palette_len).map(|_| read(reader)).collect::<Result<Vec<_>, _>>()?;
+
+    let bits = reader.u8()? as u32;
+    let indices = match bits {
+        0 => None,
+        1..=32 => {
+            let words = (0..len.div_ceil((64 / bits) as usize)).map(|_| reader.u64()).collect::<Result<_, _>>()?;
+            PackedArray::from_words(bits, len, words)
+        },
+        _ => return Err(DecodeError(format!("{} bits per entry", bits))),
+    };
+    PalettedContainer::from_parts(len, palette, indices)
+        .ok_or_else(|| DecodeError(format!("indices don't match the palette of {} entries", palette_len)))
+}
+
+/// Format 1 stored the biome palette as `u32`, format 2 as `u16` like `BiomeId`
+fn narrow_biome_palette(data: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut reader = Reader { data: &data };
+    reader.take::<12>().map_err(|e| e.to_string())?;
+    skip_container(&mut reader, CHUNK_VOLUME, 4).map_err(|e| format!("blocks: {}", e))?;
+
+    let mut out = data[..data.len() - reader.data.len()].to_vec();
+    let palette_len = reader.u32().map_err(|e| e.to_string())?;
+    out.extend_from_slice(&palette_len.to_le_bytes());
+    for _ in 0..palette_len {
+        let id = reader.u32().map_err(|e| e.to_string())?;
+        let id = u16::try_from(id).map_err(|_| format!("biome ID {} is out of range", id))?;
+        out.extend_from_slice(&id.to_le_bytes());
+    }
+    out.extend_from_slice(reader.data);
+    Ok(out)
+}
+
+/// Formats 1 and 2 stored the IDs of states and biomes, format 3 their names
+fn name_palettes(data: Vec<u8>, legacy: LegacyIds) -> Result<Vec<u8>, String> {
+    let mut reader = Reader { data: &data };
+    let mut out = reader.take::<12>().map_err(|e| e.to_string())?.to_vec();
+    name_palette(&mut reader, &mut out, CHUNK_VOLUME, |reader| reader.u32().map(|id| (id, (legacy.states)(id))))
+        .map_err(|e| format!("blocks: {}", e))?;
+    name_palette(&mut reader, &mut out, CHUNK_AREA, |reader| reader.u16().map(|id| (id.into(), (legacy.biomes)(id))))
+        .map_err(|e| format!("biomes: {}", e))?;
+    out.extend_from_slice(reader.data);
+    Ok(out)
+}
+
+/// Copies a container with every palette ID replaced by its name
+fn name_palette(
+    reader: &mut Reader,
+    out: &mut Vec<u8>,
+    len: usize,
+    read: impl Fn(&mut Reader) -> Result<(u32, Option<&'static str>), DecodeError>,
+) -> Result<(), DecodeError> {
+    let palette_len = reader.u32()?;
+    out.extend_from_slice(&palette_len.to_le_bytes());
+    for _ in 0..palette_len {
+        let (id, name) = read(reader)?;
+        let name = name.ok_or_else(|| DecodeError(format!("unknown ID {}", id)))?;
+        write_str(out, name);
+    }
+    let indices = reader.data;
+    skip_indices(reader, len)?;
+    out.extend_from_slice(&indices[..indices.len() - reader.data.len()]);
+    Ok(())
+}
+
+/// Moves past a container with palette entries of `entry_len` bytes
+fn skip_container(reader: &mut Reader, len: usize, entry_len: usize) -> Result<(), DecodeError> {
+    let palette_len = reader.u32()? as usize;
+    reader.skip(palette_len.saturating_mul(entry_len))?;
+    skip_indices(reader, len)
+}
+
+fn skip_indices(reader: &mut Reader, len: usize) -> Result<(), DecodeError> {
+    let words = match reader.u8()? as usize {
+        0 => 0,
+        bits @ 1..=32 => len.div_ceil(64 / bits),
+        bits => return Err(DecodeError(format!("{} bits per entry", bits))),
+    };
+    reader.skip(words * 8)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn skip(&mut self, len: usize) -> Result<(), DecodeError> {
+        if self.data.len() < len {
+            return Err(DecodeError("unexpected end of data".to_string()));
+        }
+        self.data = &self.data[len..];
+        Ok(())
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let bytes = self.data.get(..N).ok_or_else(|| DecodeError("unexpected end of data".to_string()))?;
+        let ret = bytes.try_into().unwrap();
+        self.data = &self.data[N..];
+        Ok(ret)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        self.take::<1>().map(|[b]| b)
+    }
+
+    fn u16(&mut self) -> Result<u16, DecodeError> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32, DecodeError> {
+        self.take().map(i32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, DecodeError> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn str(&mut self) -> Result<&'a str, DecodeError> {
+        let len = self.u16()? as usize;
+        let data = self.data;
+        self.skip(len)?;
+        std::str::from_utf8(&data[..len]).map_err(|_| DecodeError("name is not UTF-8".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{biome::BiomeId, block::BlockState, pos::LocalPos, registry::{BlockProperties, BlockRegistry}};
+
+    use super::{*, super::{TestRegistries, assert_same, fixture_chunk, legacy_fixture_chunk}};
+
+    fn sample(pos: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::new(pos);
+        for i in (0..CHUNK_VOLUME).step_by(7) {
+            chunk.set(LocalPos::from_index(i), BlockState((i % 8) as u32 + 1));
+        }
+        chunk.set_biome(3, 4, BiomeId(2));
+        chunk
+    }
+
+    fn replace(data: &mut [u8], from: &str, to: &str) {
+        let start = data.windows(from.len()).position(|window| window == from.as_bytes()).unwrap();
+        data[start..start + to.len()].copy_from_slice(to.as_bytes());
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let registries = TestRegistries::new();
+        let mut single = Chunk::filled(ChunkPos::new(0, 0, 0), BlockState(3));
+        single.compact();
+        for chunk in [single, sample(ChunkPos::new(-5, 2, 1_000_000))] {
+            let decoded = decode_chunk(&encode_chunk(&chunk, registries.get()), registries.get()).unwrap();
+            assert_same(&decoded, &chunk);
+        }
+    }
+
+    #[test]
+    fn invalid_data_is_rejected() {
+        let registries = TestRegistries::new();
+        let data = encode_chunk(&sample(ChunkPos::new(1, 2, 3)), registries.get());
+        assert!(decode_chunk(&data[..data.len() - 1], registries.get()).is_err());
+        assert!(decode_chunk(&[data.clone(), vec![0]].concat(), registries.get()).is_err());
+
+        // A palette index past the end of the palette
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        chunk.set(LocalPos::new(0, 0, 0), registries.state("stone"));
+        chunk.set(LocalPos::new(1, 0, 0), registries.state("sand"));
+        let mut data = encode_chunk(&chunk, registries.get());
+        // Format, position, palette length and three names, bits, then the first index word
+        let bits = 4 + 12 + 4 + ["air", "stone", "sand"].iter().map(|name| 2 + name.len()).sum::<usize>();
+        assert_eq!(data[bits], 2);
+        data[bits + 1] = 0b11;
+        assert_eq!(decode_chunk(&data, registries.get()).unwrap_err().0, "blocks: indices don't match the palette of 3 entries");
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        let registries = TestRegistries::new();
+        let data = encode_chunk(&fixture_chunk(ChunkPos::new(0, 0, 0), &registries), registries.get());
+        let decode = |from: &str, to: &str| {
+            let mut data = data.clone();
+            replace(&mut data, from, to);
+            decode_chunk(&data, registries.get()).unwrap_err().0
+        };
+        assert_eq!(decode("stone", "stane"), "blocks: unknown block `stane`");
+        assert_eq!(decode("axis=x", "axle=x"), "blocks: block `oak_log` has no property `axle`");
+        assert_eq!(decode("axis=x", "axis=w"), "blocks: `w` is not a valid value for property `axis` of block `oak_log`");
+        assert_eq!(decode("tundra", "tundro"), "biomes: unknown biome `tundro`");
+    }
+
+    #[test]
+    fn every_format_still_loads() {
+        assert_eq!(chunk_migrations(LegacyIds::SHIPPED).current(), CHUNK_FORMAT);
+        let registries = TestRegistries::new();
+        let expected = fixture_chunk(ChunkPos::new(-1, 2, 3), &registries);
+        let legacy = legacy_fixture_chunk(ChunkPos::new(-1, 2, 3), &registries);
+
+        // Format 1 was written without the version in front
+        let v1 = [&1u32.to_le_bytes()[..], include_bytes!("fixtures/chunk_v1.bin")].concat();
+        assert_same(&decode_chunk(&v1, registries.get()).unwrap(), &legacy);
+        assert_same(&decode_chunk(include_bytes!("fixtures/chunk_v2.bin"), registries.get()).unwrap(), &legacy);
+        assert_same(&decode_chunk(include_bytes!("fixtures/chunk_v3.bin"), registries.get()).unwrap(), &expected);
+        assert_eq!(&encode_chunk(&expected, registries.get())[..], include_bytes!("fixtures/chunk_v3.bin"));
+    }
+
+    #[test]
+    fn blocks_that_gained_properties_load_with_their_defaults() {
+        let registries = TestRegistries::new();
+        let log = LocalPos::new(0, 8, 0);
+        let log_state = |data: &[u8]| registries.blocks.format_state(decode_chunk(data, registries.get()).unwrap().get(log));
+
+        // Saved as IDs before `oak_log` had an axis
+        assert_eq!(log_state(include_bytes!("fixtures/chunk_v2.bin")), "oak_log[axis=x]");
+
+        // Saved by name before `oak_log` had an axis
+        let mut before = BlockRegistry::new();
+        for name in ["stone", "sand", "grass_block", "oak_log", "dirt", "coal_ore"] {
+            before.register(name, name, BlockProperties::default());
+        }
+        let old = Registries { blocks: &before, ..registries.get() };
+        let saved = include_bytes!("fixtures/chunk_v3_log_without_axis.bin");
+        assert_eq!(before.format_state(decode_chunk(saved, old).unwrap().get(log)), "oak_log");
+        assert_eq!(log_state(saved), "oak_log[axis=x]");
+        assert_same(&decode_chunk(saved, registries.get()).unwrap(), &fixture_chunk(ChunkPos::new(-1, 2, 3), &registries));
+    }
+
+    #[test]
+    fn unknown_formats_are_rejected() {
+        let registries = TestRegistries::new();
+        let mut data = encode_chunk(&sample(ChunkPos::new(0, 0, 0)), registries.get());
+        data[..4].copy_from_slice(&(CHUNK_FORMAT + 1).to_le_bytes());
+        assert_eq!(
+            decode_chunk(&data, registries.get()).unwrap_err().0,
+            format!("format {} is newer than {}, the latest this build understands", CHUNK_FORMAT + 1, CHUNK_FORMAT),
+        );
+        data[..4].copy_from_slice(&0u32.to_le_bytes());
+        assert!(decode_chunk(&data, registries.get()).is_err());
+
+        // Biome IDs past the range of the new palette
+        let mut v1 = include_bytes!("fixtures/chunk_v1.bin").to_vec();
+        let mut reader = Reader { data: &v1[12..] };
+        skip_container(&mut reader, CHUNK_VOLUME, 4).unwrap();
+        let biomes = v1.len() - reader.data.len() + 4;
+        assert_eq!(u32::from_le_bytes(v1[biomes - 4..biomes].try_into().unwrap()), 3);
+        v1[biomes + 2] = 1;
+        assert_eq!(narrow_biome_palette(v1).unwrap_err(), "biome ID 65538 is out of range");
+
+        // IDs that no build ever saved
+        let mut v2 = include_bytes!("fixtures/chunk_v2.bin").to_vec();
+        v2[4 + 12 + 4..4 + 12 + 8].copy_from_slice(&70_001u32.to_le_bytes());
+        assert_eq!(
+            decode_chunk(&v2, registries.get()).unwrap_err().0,
+            "upgrading format 2 to 3: blocks: unknown ID 70001",
+        );
+        // The shipped builds never had a state 70000
+        let shipped = Registries { legacy: LegacyIds::SHIPPED, ..registries.get() };
+        assert!(decode_chunk(include_bytes!("fixtures/chunk_v2.bin"), shipped).is_err());
+    }
+}
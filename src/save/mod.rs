@@ -0,0 +1,128 @@
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+use crate::game::{biome::{Biome, BiomeRegistry, Color}, block::BlockState, block_state::Property, chunk::Chunk, pos::{ChunkPos, LocalPos}, registry::{BlockProperties, BlockRegistry}};
+#[cfg(test)]
+use codec::{LegacyIds, Registries};
+
+pub mod codec;
+pub mod region;
+pub mod version;
+pub mod world;
+
+/// `r.0.0.0.region` is written as `r.0.0.0.region.tmp` first
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// The names of the IDs the fixtures of formats 1 and 2 were saved with
+#[cfg(test)]
+const FIXTURE_IDS: LegacyIds = LegacyIds {
+    states: |id| match id {
+        0 => Some("air"),
+        1 => Some("stone"),
+        2 => Some("dirt"),
+        3 => Some("grass_block"),
+        4 => Some("sand"),
+        9 => Some("coal_ore"),
+        70_000 => Some("oak_log"),
+        _ => None,
+    },
+    biomes: |id| Some(["plains", "tundra", "desert", "forest"][id as usize % 4]),
+};
+
+/// The blocks and biomes of the fixtures, registered in another order than
+/// the IDs the old formats saved. `oak_log` gained its `axis` since then.
+#[cfg(test)]
+struct TestRegistries {
+    blocks: BlockRegistry,
+    biomes: BiomeRegistry,
+}
+
+#[cfg(test)]
+impl TestRegistries {
+    fn new() -> Self {
+        let mut blocks = BlockRegistry::new();
+        for name in ["stone", "sand", "grass_block"] {
+            blocks.register(name, name, BlockProperties::default());
+        }
+        blocks.register_with_states("oak_log", "oak_log", BlockProperties::default(), vec![Property::axis()]);
+        for name in ["dirt", "coal_ore"] {
+            blocks.register(name, name, BlockProperties::default());
+        }
+
+        let biome = |name: &str| Biome {
+            name: name.to_string(),
+            display_name: name.to_string(),
+            temperature: 0.0,
+            humidity: 0.0,
+            surface: BlockState::AIR,
+            underwater: BlockState::AIR,
+            filler: BlockState::AIR,
+            filler_depth: 0,
+            height_offset: 0.0,
+            height_scale: 1.0,
+            tree_density: 0.0,
+            grass_color: Color::WHITE,
+            foliage_color: Color::WHITE,
+        };
+        let biomes = BiomeRegistry::new(["plains", "tundra", "desert", "forest"].into_iter().map(biome).collect());
+        Self { blocks, biomes }
+    }
+
+    fn get(&self) -> Registries<'_> {
+        Registries { blocks: &self.blocks, biomes: &self.biomes, legacy: FIXTURE_IDS }
+    }
+
+    fn state(&self, state: &str) -> BlockState {
+        self.blocks.parse_state(state).unwrap()
+    }
+}
+
+/// The chunk the files of format 3 in `fixtures` were written from, never change it
+#[cfg(test)]
+fn fixture_chunk(pos: ChunkPos, registries: &TestRegistries) -> Chunk {
+    let mut chunk = Chunk::new(pos);
+    for (local, _) in Chunk::new(pos).iter() {
+        let state = match local.y {
+            0..=5 => "stone",
+            6..=7 => ["dirt", "grass_block", "sand"][(local.x + local.z) as usize % 3],
+            _ if local.x == local.z && local.y < 12 => "oak_log",
+            _ => "air",
+        };
+        chunk.set(local, registries.state(state));
+    }
+    for z in 0..16 {
+        for x in 0..16 {
+            let name = ["desert", "forest", "plains", "tundra"][(x / 4 + z / 4 + pos.y.unsigned_abs() as usize) % 4];
+            chunk.set_biome(x, z, registries.biomes.get_by_name(name).unwrap().0);
+        }
+    }
+    chunk.set(LocalPos::new(15, 15, 15), registries.state("coal_ore"));
+    chunk.compact();
+    chunk
+}
+
+/// The chunk the files of formats 1 and 2 in `fixtures` were written from,
+/// which only numbered its biomes differently. Never change it.
+#[cfg(test)]
+fn legacy_fixture_chunk(pos: ChunkPos, registries: &TestRegistries) -> Chunk {
+    let mut chunk = fixture_chunk(pos, registries);
+    for z in 0..16 {
+        for x in 0..16 {
+            let name = (FIXTURE_IDS.biomes)(((x / 4 + z / 4) % 3) as u16 + pos.y.unsigned_abs() as u16).unwrap();
+            chunk.set_biome(x, z, registries.biomes.get_by_name(name).unwrap().0);
+        }
+    }
+    chunk.compact();
+    chunk
+}
+
+#[cfg(test)]
+fn assert_same(a: &Chunk, b: &Chunk) {
+    assert_eq!(a.pos, b.pos);
+    assert!(a.iter().eq(b.iter()), "blocks of {:?} differ", a.pos);
+    assert!(a.biomes().iter().eq(b.biomes().iter()), "biomes of {:?} differ", a.pos);
+}
@@ -0,0 +1,605 @@
+use std::{collections::BTreeMap, fs::{self, File}, io::{self, BufWriter, Read, Seek, SeekFrom, Write}, path::{Path, PathBuf}};
+
+use flate2::{Compression, read::DeflateDecoder, write::DeflateEncoder};
+use rustc_hash::FxHashMap;
+use thiserror::Error;
+
+use crate::game::{chunk::Chunk, pos::{ChunkPos, RegionPos, REGION_SIZE}};
+
+use super::{codec::{Registries, decode_chunk, encode_chunk}, temp_path, version::{MigrationError, Migrations}};
+
+/// Version of the region layout this build writes
+pub const REGION_FORMAT: u32 = 2;
+
+/// Number of chunks a region file can hold
+pub const REGION_CHUNKS: usize = REGION_SIZE * REGION_SIZE * REGION_SIZE;
+
+/// Largest chunk accepted when decompressing, far above what a chunk encodes to
+pub const MAX_CHUNK_BYTES: u64 = 1 << 20;
+
+const MAGIC: &[u8; 4] = b"VXRG";
+
+/// Magic, format and number of entries
+const HEADER_LEN: u64 = 12;
+
+/// Index, offset, length and checksum of a chunk
+const ENTRY_LEN: u64 = 20;
+
+#[derive(Debug, Error)]
+pub enum RegionError {
+    #[error("{}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("{}: region {source}", path.display())]
+    UnsupportedFormat { path: PathBuf, source: MigrationError },
+    #[error("{}: corrupt header: {reason}", path.display())]
+    CorruptHeader { path: PathBuf, reason: String },
+    #[error("{}: chunk {}, {}, {}: {reason}", path.display(), pos.x, pos.y, pos.z)]
+    CorruptChunk { path: PathBuf, pos: ChunkPos, reason: String },
+}
+
+/// Upgrades the decompressed chunks of region files written by older builds
+pub fn region_migrations() -> Migrations<Vec<u8>> {
+    Migrations::new(1)
+        .with_step(1, |data: Vec<u8>| {
+            // Format 1 held chunks of format 1 without the version in front
+            Ok([&1u32.to_le_bytes()[..], &data].concat())
+        })
+}
+
+/// Where a chunk is stored in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    offset: u64,
+    len: u32,
+    /// CRC-32 of the compressed bytes
+    checksum: u32,
+}
+
+/// The chunks of one region, stored as a table of where every chunk is
+/// followed by the chunks, each compressed on its own. Only the table is read
+/// when the file is opened, chunks are read when asked for. Saved chunks are
+/// kept in memory until `flush` rewrites the file, which also upgrades
+/// files of older formats.
+///
+/// The layout is the magic `VXRG`, the format, the number of chunks, one
+/// entry of index in the region, offset, length and checksum per chunk and
+/// the checksum of everything before it, all little endian.
+pub struct RegionFile {
+    path: PathBuf,
+    pos: RegionPos,
+    /// Format of the file on disk
+    format: u32,
+    /// Stored chunks by index in the region
+    entries: BTreeMap<usize, Entry>,
+    /// Compressed chunks saved since the file was last written
+    pending: BTreeMap<usize, Vec<u8>>,
+}
+
+impl RegionFile {
+    /// Reads the table of a region file, a missing file is an empty region
+    pub fn open(path: &Path, pos: RegionPos) -> Result<Self, RegionError> {
+        let mut ret = Self {
+            path: path.to_path_buf(),
+            pos,
+            format: REGION_FORMAT,
+            entries: BTreeMap::new(),
+            pending: BTreeMap::new(),
+        };
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ret),
+            Err(e) => return Err(ret.io_error(e)),
+        };
+        let file_len = file.metadata().map_err(|e| ret.io_error(e))?.len();
+        let corrupt = |reason: String| RegionError::CorruptHeader { path: path.to_path_buf(), reason };
+
+        let mut header = [0; HEADER_LEN as usize];
+        file.read_exact(&mut header).map_err(|e| ret.read_error(e, "header"))?;
+        if &header[0..4] != MAGIC {
+            return Err(corrupt("not a region file".to_string()));
+        }
+        ret.format = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let migrations = region_migrations();
+        if !(migrations.oldest()..=migrations.current()).contains(&ret.format) {
+            let source = migrations.migrate(ret.format, Vec::new()).unwrap_err();
+            return Err(RegionError::UnsupportedFormat { path: path.to_path_buf(), source });
+        }
+        let count = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+        if count > REGION_CHUNKS {
+            return Err(corrupt(format!("{} chunks in a region of {}", count, REGION_CHUNKS)));
+        }
+
+        let mut table = vec![0; count * ENTRY_LEN as usize + 4];
+        file.read_exact(&mut table).map_err(|e| ret.read_error(e, "table"))?;
+        let (table, checksum) = table.split_at(table.len() - 4);
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&header);
+        hasher.update(table);
+        if hasher.finalize() != u32::from_le_bytes(checksum.try_into().unwrap()) {
+            return Err(corrupt("checksum mismatch".to_string()));
+        }
+
+        let data_start = HEADER_LEN + table.len() as u64 + 4;
+        for entry in table.chunks_exact(ENTRY_LEN as usize) {
+            let field = |i: usize| u32::from_le_bytes(entry[i..i + 4].try_into().unwrap());
+            let index = field(0) as usize;
+            let entry = Entry {
+                offset: u64::from_le_bytes(entry[4..12].try_into().unwrap()),
+                len: field(12),
+                checksum: field(16),
+            };
+            if index >= REGION_CHUNKS {
+                return Err(corrupt(format!("chunk index {} is out of range", index)));
+            }
+            if entry.offset < data_start || entry.offset.checked_add(entry.len as u64).is_none_or(|end| end > file_len) {
+                return Err(corrupt(format!("chunk {} is outside of the file", index)));
+            }
+            if ret.entries.insert(index, entry).is_some() {
+                return Err(corrupt(format!("chunk {} is stored twice", index)));
+            }
+        }
+        Ok(ret)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn pos(&self) -> RegionPos {
+        self.pos
+    }
+
+    /// Format of the file on disk, the current one until it is written
+    pub fn format(&self) -> u32 {
+        self.format
+    }
+
+    /// Whether the chunk is stored or waiting to be written
+    pub fn contains(&self, pos: ChunkPos) -> bool {
+        let index = self.index(pos);
+        self.pending.contains_key(&index) || self.entries.contains_key(&index)
+    }
+
+    /// Number of stored and pending chunks
+    pub fn len(&self) -> usize {
+        self.entries.len() + self.pending.keys().filter(|index| !self.entries.contains_key(index)).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether there are saved chunks that aren't written yet
+    pub fn is_dirty(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Reads, checks and decodes a chunk, `None` if it was never saved
+    pub fn read_chunk(&self, pos: ChunkPos, registries: Registries) -> Result<Option<Chunk>, RegionError> {
+        let index = self.index(pos);
+        let data = match (self.pending.get(&index), self.entries.get(&index)) {
+            (Some(pending), _) => self.decompress(pos, pending)?,
+            (None, Some(entry)) => {
+                let data = self.decompress(pos, &self.read_stored(pos, entry)?)?;
+                region_migrations().migrate(self.format, data).map_err(|e| self.corrupt_chunk(pos, e.to_string()))?
+            },
+            (None, None) => return Ok(None),
+        };
+
+        let chunk = decode_chunk(&data, registries).map_err(|e| self.corrupt_chunk(pos, e.to_string()))?;
+        if chunk.pos != pos {
+            return Err(self.corrupt_chunk(pos, format!("holds chunk {}, {}, {}", chunk.pos.x, chunk.pos.y, chunk.pos.z)));
+        }
+        Ok(Some(chunk))
+    }
+
+    /// Compresses a chunk to be written by the next `flush`
+    pub fn write_chunk(&mut self, chunk: &Chunk, registries: Registries) {
+        let index = self.index(chunk.pos);
+        self.pending.insert(index, compress(&encode_chunk(chunk, registries)));
+    }
+
+    /// The compressed bytes of a stored chunk, checked against the checksum
+    fn read_stored(&self, pos: ChunkPos, entry: &Entry) -> Result<Vec<u8>, RegionError> {
+        let mut file = File::open(&self.path).map_err(|e| self.io_error(e))?;
+        file.seek(SeekFrom::Start(entry.offset)).map_err(|e| self.io_error(e))?;
+        let mut data = vec![0; entry.len as usize];
+        file.read_exact(&mut data).map_err(|e| self.io_error(e))?;
+        if crc32fast::hash(&data) != entry.checksum {
+            return Err(self.corrupt_chunk(pos, "checksum mismatch".to_string()));
+        }
+        Ok(data)
+    }
+
+    fn decompress(&self, pos: ChunkPos, compressed: &[u8]) -> Result<Vec<u8>, RegionError> {
+        let mut data = Vec::new();
+        DeflateDecoder::new(compressed).take(MAX_CHUNK_BYTES + 1).read_to_end(&mut data)
+            .map_err(|e| self.corrupt_chunk(pos, format!("failed to decompress: {}", e)))?;
+        if data.len() as u64 > MAX_CHUNK_BYTES {
+            return Err(self.corrupt_chunk(pos, format!("larger than {} bytes", MAX_CHUNK_BYTES)));
+        }
+        Ok(data)
+    }
+
+    /// Brings every stored chunk that can be read to the current format, the
+    /// others are copied as they are and stay detectable as corrupt
+    fn upgrade(&mut self) -> Result<(), RegionError> {
+        let migrations = region_migrations();
+        let old: Vec<_> = self.entries.iter()
+            .filter(|(index, _)| !self.pending.contains_key(index))
+            .map(|(index, entry)| (*index, *entry))
+            .collect();
+        for (index, entry) in old {
+            let pos = self.chunk_pos(index);
+            let data = match self.read_stored(pos, &entry) {
+                Ok(compressed) => self.decompress(pos, &compressed).ok()
+                    .and_then(|data| migrations.migrate(self.format, data).ok()),
+                Err(RegionError::CorruptChunk { .. }) => None,
+                Err(e) => return Err(e),
+            };
+            match data {
+                Some(data) => {
+                    self.pending.insert(index, compress(&data));
+                },
+                None => log::warn!("Copying the unreadable chunk {}, {}, {} of `{}` as it is", pos.x, pos.y, pos.z, self.path.display()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the whole region to a temporary file next to it and renames it
+    /// over the old one, so a crash leaves either the old or the new file.
+    /// Chunks that weren't saved again are copied as they are.
+    pub fn flush(&mut self) -> Result<(), RegionError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        if self.format != REGION_FORMAT {
+            self.upgrade()?;
+        }
+
+        let temp = temp_path(&self.path);
+        let result = self.write_to(&temp);
+        let entries = match result {
+            Ok(entries) => entries,
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                return Err(e);
+            },
+        };
+        fs::rename(&temp, &self.path).map_err(|e| self.io_error(e))?;
+        self.entries = entries;
+        self.pending.clear();
+        self.format = REGION_FORMAT;
+        Ok(())
+    }
+
+    /// Writes every chunk to `temp`, returning where they ended up
+    fn write_to(&self, temp: &Path) -> Result<BTreeMap<usize, Entry>, RegionError> {
+        let io_error = |source| RegionError::Io { path: temp.to_path_buf(), source };
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| self.io_error(e))?;
+        }
+
+        // Old chunks keep their checksum, so a chunk that was already corrupt stays detectable
+        let mut indices: Vec<_> = self.entries.keys().chain(self.pending.keys()).copied().collect();
+        indices.sort_unstable();
+        indices.dedup();
+        let mut offset = HEADER_LEN + indices.len() as u64 * ENTRY_LEN + 4;
+        let entries: BTreeMap<_, _> = indices.iter().map(|index| {
+            let entry = match self.pending.get(index) {
+                Some(data) => Entry { offset, len: data.len() as u32, checksum: crc32fast::hash(data) },
+                None => Entry { offset, ..self.entries[index] },
+            };
+            offset += entry.len as u64;
+            (*index, entry)
+        }).collect();
+
+        let mut header = Vec::with_capacity((HEADER_LEN + entries.len() as u64 * ENTRY_LEN) as usize);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&REGION_FORMAT.to_le_bytes());
+        header.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        for (index, entry) in &entries {
+            header.extend_from_slice(&(*index as u32).to_le_bytes());
+            header.extend_from_slice(&entry.offset.to_le_bytes());
+            header.extend_from_slice(&entry.len.to_le_bytes());
+            header.extend_from_slice(&entry.checksum.to_le_bytes());
+        }
+        header.extend_from_slice(&crc32fast::hash(&header).to_le_bytes());
+
+        let mut old = if self.entries.keys().any(|index| !self.pending.contains_key(index)) {
+            Some(File::open(&self.path).map_err(|e| self.io_error(e))?)
+        } else {
+            None
+        };
+        let file = File::create(temp).map_err(io_error)?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&header).map_err(io_error)?;
+        for index in entries.keys() {
+            match (self.pending.get(index), old.as_mut()) {
+                (Some(data), _) => writer.write_all(data).map_err(io_error)?,
+                (None, Some(old)) => {
+                    let entry = self.entries[index];
+                    old.seek(SeekFrom::Start(entry.offset)).map_err(|e| self.io_error(e))?;
+                    let copied = io::copy(&mut old.take(entry.len as u64), &mut writer).map_err(io_error)?;
+                    if copied != entry.len as u64 {
+                        return Err(self.io_error(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                },
+                (None, None) => unreachable!("the old file is open when chunks are copied from it"),
+            }
+        }
+        let file = writer.into_inner().map_err(|e| io_error(e.into_error()))?;
+        file.sync_all().map_err(io_error)?;
+        Ok(entries)
+    }
+
+    /// Index of a chunk of this region in the table
+    fn index(&self, pos: ChunkPos) -> usize {
+        assert_eq!(pos.region(), self.pos, "chunk {:?} is not in region {:?}", pos, self.pos);
+        let (x, y, z) = pos.region_local();
+        (y * REGION_SIZE + z) * REGION_SIZE + x
+    }
+
+    /// Position of the chunk at an index of the table
+    fn chunk_pos(&self, index: usize) -> ChunkPos {
+        let (x, y, z) = (index % REGION_SIZE, index / (REGION_SIZE * REGION_SIZE), index / REGION_SIZE % REGION_SIZE);
+        self.pos.origin().offset(x as i32, y as i32, z as i32)
+    }
+
+    fn io_error(&self, source: io::Error) -> RegionError {
+        RegionError::Io { path: self.path.clone(), source }
+    }
+
+    /// A file ending too early is corrupt rather than unreadable
+    fn read_error(&self, source: io::Error, part: &str) -> RegionError {
+        if source.kind() == io::ErrorKind::UnexpectedEof {
+            RegionError::CorruptHeader { path: self.path.clone(), reason: format!("the file ends inside of the {}", part) }
+        } else {
+            self.io_error(source)
+        }
+    }
+
+    fn corrupt_chunk(&self, pos: ChunkPos, reason: String) -> RegionError {
+        RegionError::CorruptChunk { path: self.path.clone(), pos, reason }
+    }
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).expect("writing to a Vec can't fail");
+    encoder.finish().expect("writing to a Vec can't fail")
+}
+
+/// Every region file of a world in one directory, opened the first time one
+/// of their chunks is loaded or saved
+pub struct RegionStorage {
+    dir: PathBuf,
+    regions: FxHashMap<RegionPos, RegionFile>,
+}
+
+impl RegionStorage {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            regions: FxHashMap::default(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// `r.<x>.<y>.<z>.region` in the world directory
+    pub fn region_path(&self, pos: RegionPos) -> PathBuf {
+        self.dir.join(format!("r.{}.{}.{}.region", pos.x, pos.y, pos.z))
+    }
+
+    fn region(&mut self, pos: RegionPos) -> Result<&mut RegionFile, RegionError> {
+        if !self.regions.contains_key(&pos) {
+            let region = RegionFile::open(&self.region_path(pos), pos)?;
+            self.regions.insert(pos, region);
+        }
+        Ok(self.regions.get_mut(&pos).unwrap())
+    }
+
+    /// The saved chunk, `None` if it was never saved
+    pub fn load_chunk(&mut self, pos: ChunkPos, registries: Registries) -> Result<Option<Chunk>, RegionError> {
+        self.region(pos.region())?.read_chunk(pos, registries)
+    }
+
+    /// Keeps the chunk to be written by the next `flush`
+    pub fn save_chunk(&mut self, chunk: &Chunk, registries: Registries) -> Result<(), RegionError> {
+        self.region(chunk.pos.region())?.write_chunk(chunk, registries);
+        Ok(())
+    }
+
+    /// Writes every region with saved chunks, returning the first error after
+    /// trying all of them
+    pub fn flush(&mut self) -> Result<(), RegionError> {
+        let mut ret = Ok(());
+        for region in self.regions.values_mut().filter(|region| region.is_dirty()) {
+            if let Err(e) = region.flush() {
+                log::error!("Failed to save region: {}", e);
+                if ret.is_ok() {
+                    ret = Err(e);
+                }
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{game::{biome::BiomeId, block::BlockState, pos::LocalPos}, util::test_dir::TestDir};
+
+    use super::{*, super::{TestRegistries, assert_same, legacy_fixture_chunk}};
+
+    fn chunk(pos: ChunkPos, seed: u32) -> Chunk {
+        let mut chunk = Chunk::new(pos);
+        for i in 0..200 {
+            let local = LocalPos::from_index((i * 97 + seed as usize * 13) % 4096);
+            chunk.set(local, BlockState(1 + (i as u32 + seed) % 5));
+        }
+        chunk.set_biome(1, 2, BiomeId((seed % 4) as u16));
+        chunk
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let registries = TestRegistries::new();
+        let dir = TestDir::new("region-round-trip");
+        let chunks = [
+            chunk(ChunkPos::new(0, 0, 0), 1),
+            chunk(ChunkPos::new(31, -1, 5), 2),
+            chunk(ChunkPos::new(-33, 64, -1), 3),
+        ];
+        let mut storage = RegionStorage::new(&*dir);
+        for chunk in &chunks {
+            storage.save_chunk(chunk, registries.get()).unwrap();
+        }
+        // Saved chunks can be loaded before they are written
+        assert_same(&storage.load_chunk(chunks[1].pos, registries.get()).unwrap().unwrap(), &chunks[1]);
+        storage.flush().unwrap();
+
+        let mut files: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+        files.sort();
+        assert_eq!(files, ["r.-2.2.-1.region", "r.0.-1.0.region", "r.0.0.0.region"]);
+
+        let mut storage = RegionStorage::new(&*dir);
+        for chunk in &chunks {
+            assert_same(&storage.load_chunk(chunk.pos, registries.get()).unwrap().unwrap(), chunk);
+        }
+        assert!(storage.load_chunk(ChunkPos::new(1, 0, 0), registries.get()).unwrap().is_none());
+        assert!(storage.load_chunk(ChunkPos::new(100, 100, 100), registries.get()).unwrap().is_none());
+    }
+
+    #[test]
+    fn rewrites_keep_the_other_chunks() {
+        let registries = TestRegistries::new();
+        let dir = TestDir::new("region-rewrite");
+        let path = dir.join("r.0.0.0.region");
+        let (a, b) = (ChunkPos::new(1, 2, 3), ChunkPos::new(4, 5, 6));
+        let mut region = RegionFile::open(&path, RegionPos::new(0, 0, 0)).unwrap();
+        region.write_chunk(&chunk(a, 1), registries.get());
+        region.write_chunk(&chunk(b, 2), registries.get());
+        region.flush().unwrap();
+
+        // A crash during an earlier write left a temporary file behind
+        fs::write(temp_path(&path), b"garbage").unwrap();
+
+        let mut region = RegionFile::open(&path, RegionPos::new(0, 0, 0)).unwrap();
+        assert_eq!(region.len(), 2);
+        region.write_chunk(&chunk(a, 7), registries.get());
+        assert!(region.is_dirty());
+        region.flush().unwrap();
+        assert!(!region.is_dirty());
+        assert!(!temp_path(&path).exists());
+
+        let region = RegionFile::open(&path, RegionPos::new(0, 0, 0)).unwrap();
+        assert_eq!(region.len(), 2);
+        assert_same(&region.read_chunk(a, registries.get()).unwrap().unwrap(), &chunk(a, 7));
+        assert_same(&region.read_chunk(b, registries.get()).unwrap().unwrap(), &chunk(b, 2));
+    }
+
+    #[test]
+    fn corrupt_chunks_are_detected() {
+        let registries = TestRegistries::new();
+        let dir = TestDir::new("region-corrupt-chunk");
+        let path = dir.join("r.0.0.0.region");
+        let (a, b) = (ChunkPos::new(0, 0, 0), ChunkPos::new(0, 0, 1));
+        let mut region = RegionFile::open(&path, RegionPos::new(0, 0, 0)).unwrap();
+        region.write_chunk(&chunk(a, 1), registries.get());
+        region.write_chunk(&chunk(b, 2), registries.get());
+        region.flush().unwrap();
+
+        // The last chunk in the file has the highest index
+        let mut data = fs::read(&path).unwrap();
+        *data.last_mut().unwrap() ^= 0x40;
+        fs::write(&path, &data).unwrap();
+
+        let region = RegionFile::open(&path, RegionPos::new(0, 0, 0)).unwrap();
+        assert_same(&region.read_chunk(a, registries.get()).unwrap().unwrap(), &chunk(a, 1));
+        match region.read_chunk(b, registries.get()) {
+            Err(RegionError::CorruptChunk { pos, reason, .. }) => {
+                assert_eq!(pos, b);
+                assert_eq!(reason, "checksum mismatch");
+            },
+            other => panic!("expected a corrupt chunk, got {:?}", other.map(|chunk| chunk.map(|chunk| chunk.pos))),
+        }
+
+        // Copying the region keeps the broken chunk detectable
+        let mut region = region;
+        region.write_chunk(&chunk(a, 3), registries.get());
+        region.flush().unwrap();
+        assert!(matches!(region.read_chunk(b, registries.get()), Err(RegionError::CorruptChunk { .. })));
+    }
+
+    #[test]
+    fn corrupt_headers_are_detected() {
+        let registries = TestRegistries::new();
+        let dir = TestDir::new("region-corrupt-header");
+        let path = dir.join("r.0.0.0.region");
+        let mut region = RegionFile::open(&path, RegionPos::new(0, 0, 0)).unwrap();
+        region.write_chunk(&chunk(ChunkPos::new(3, 3, 3), 1), registries.get());
+        region.flush().unwrap();
+        let data = fs::read(&path).unwrap();
+
+        let open = |data: &[u8]| {
+            fs::write(&path, data).unwrap();
+            RegionFile::open(&path, RegionPos::new(0, 0, 0)).err().map(|e| e.to_string())
+        };
+        assert_eq!(open(&data), None);
+        // A bit flipped in the offset of the chunk
+        let mut flipped = data.clone();
+        flipped[HEADER_LEN as usize + 4] ^= 1;
+        assert!(open(&flipped).unwrap().ends_with("corrupt header: checksum mismatch"));
+        assert!(open(&data[..20]).unwrap().ends_with("the file ends inside of the table"));
+        assert!(open(b"PNG").unwrap().ends_with("the file ends inside of the header"));
+        assert!(open(b"not a region").unwrap().ends_with("not a region file"));
+        let mut future = data.clone();
+        future[4] = 9;
+        assert!(open(&future).unwrap().ends_with("region format 9 is newer than 2, the latest this build understands"));
+        // The table is intact but the file was cut short
+        assert!(open(&data[..data.len() - 1]).unwrap().ends_with("chunk 3171 is outside of the file"));
+        // An offset so large that the end of the chunk overflows, with a valid checksum
+        let mut overflowing = data.clone();
+        let offset = HEADER_LEN as usize + 4;
+        overflowing[offset..offset + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let table_end = HEADER_LEN as usize + ENTRY_LEN as usize;
+        let checksum = crc32fast::hash(&overflowing[..table_end]);
+        overflowing[table_end..table_end + 4].copy_from_slice(&checksum.to_le_bytes());
+        assert!(open(&overflowing).unwrap().ends_with("chunk 3171 is outside of the file"));
+    }
+
+    #[test]
+    fn old_regions_load_and_are_upgraded() {
+        let registries = TestRegistries::new();
+        let dir = TestDir::new("region-old-format");
+        let path = dir.join("r.0.-1.0.region");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, include_bytes!("fixtures/r.0.-1.0.region")).unwrap();
+        let old = [ChunkPos::new(0, -1, 0), ChunkPos::new(5, -32, 31)];
+
+        let mut region = RegionFile::open(&path, RegionPos::new(0, -1, 0)).unwrap();
+        assert_eq!(region.format(), 1);
+        for pos in old {
+            assert_same(&region.read_chunk(pos, registries.get()).unwrap().unwrap(), &legacy_fixture_chunk(pos, &registries));
+        }
+
+        // Writing any chunk rewrites the others in the current format
+        let new = chunk(ChunkPos::new(1, -1, 0), 4);
+        region.write_chunk(&new, registries.get());
+        region.flush().unwrap();
+        assert_eq!(region.format(), REGION_FORMAT);
+        assert_eq!(fs::read(&path).unwrap()[4..8], REGION_FORMAT.to_le_bytes());
+
+        let region = RegionFile::open(&path, RegionPos::new(0, -1, 0)).unwrap();
+        assert_eq!(region.len(), 3);
+        for pos in old {
+            assert_same(&region.read_chunk(pos, registries.get()).unwrap().unwrap(), &legacy_fixture_chunk(pos, &registries));
+        }
+        assert_same(&region.read_chunk(new.pos, registries.get()).unwrap().unwrap(), &new);
+    }
+}
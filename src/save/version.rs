@@ -0,0 +1,100 @@
+use thiserror::Error;
+
+/// Upgrades data of one format version to the next one
+pub type Migration<T> = Box<dyn Fn(T) -> Result<T, String>>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MigrationError {
+    #[error("format {found} is newer than {current}, the latest this build understands")]
+    TooNew { found: u32, current: u32 },
+    #[error("format {found} is older than {oldest}, the oldest that can be upgraded")]
+    TooOld { found: u32, oldest: u32 },
+    #[error("upgrading format {from} to {}: {reason}", from + 1)]
+    Failed { from: u32, reason: String },
+}
+
+/// The steps that bring data saved by older builds up to the current format,
+/// one version at a time. Old steps are never changed or removed, so every
+/// save written since the oldest format keeps loading.
+pub struct Migrations<T> {
+    oldest: u32,
+    /// Step `i` upgrades version `oldest + i`
+    steps: Vec<Migration<T>>,
+}
+
+impl<T> Migrations<T> {
+    /// No steps yet, `oldest` is the current format
+    pub fn new(oldest: u32) -> Self {
+        Self { oldest, steps: Vec::new() }
+    }
+
+    /// Adds the step from `from`, which has to be the current format, to the next one
+    pub fn with_step(mut self, from: u32, step: impl Fn(T) -> Result<T, String> + 'static) -> Self {
+        assert_eq!(from, self.current(), "migrations have to be added in order");
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    pub fn oldest(&self) -> u32 {
+        self.oldest
+    }
+
+    /// The format the data is upgraded to
+    pub fn current(&self) -> u32 {
+        self.oldest + self.steps.len() as u32
+    }
+
+    /// Runs every step from `version` on
+    pub fn migrate(&self, version: u32, mut data: T) -> Result<T, MigrationError> {
+        if version > self.current() {
+            return Err(MigrationError::TooNew { found: version, current: self.current() });
+        }
+        if version < self.oldest {
+            return Err(MigrationError::TooOld { found: version, oldest: self.oldest });
+        }
+        for (from, step) in (version..).zip(&self.steps[(version - self.oldest) as usize..]) {
+            data = step(data).map_err(|reason| MigrationError::Failed { from, reason })?;
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrations() -> Migrations<Vec<&'static str>> {
+        Migrations::new(2)
+            .with_step(2, |mut data: Vec<&str>| {
+                data.push("3");
+                Ok(data)
+            })
+            .with_step(3, |mut data: Vec<&str>| {
+                if data.contains(&"broken") {
+                    return Err("can't be upgraded".to_string());
+                }
+                data.push("4");
+                Ok(data)
+            })
+    }
+
+    #[test]
+    fn steps_run_in_order_from_the_saved_version() {
+        let migrations = migrations();
+        assert_eq!((migrations.oldest(), migrations.current()), (2, 4));
+        assert_eq!(migrations.migrate(2, vec![]).unwrap(), ["3", "4"]);
+        assert_eq!(migrations.migrate(3, vec![]).unwrap(), ["4"]);
+        assert!(migrations.migrate(4, vec![]).unwrap().is_empty());
+
+        assert_eq!(migrations.migrate(5, vec![]), Err(MigrationError::TooNew { found: 5, current: 4 }));
+        assert_eq!(migrations.migrate(1, vec![]), Err(MigrationError::TooOld { found: 1, oldest: 2 }));
+        let error = migrations.migrate(2, vec!["broken"]).unwrap_err();
+        assert_eq!(error.to_string(), "upgrading format 3 to 4: can't be upgraded");
+    }
+
+    #[test]
+    #[should_panic(expected = "migrations have to be added in order")]
+    fn steps_are_added_in_order() {
+        let _ = Migrations::<()>::new(1).with_step(2, Ok);
+    }
+}
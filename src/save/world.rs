@@ -0,0 +1,130 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::resources::loader::field_error;
+
+use super::{temp_path, version::{MigrationError, Migrations}};
+
+/// Version of the world file this build writes
+pub const WORLD_FORMAT: u32 = 1;
+
+/// Name of the world file inside of the world directory
+pub const WORLD_FILE: &str = "world.json";
+
+#[derive(Debug, Error)]
+pub enum WorldInfoError {
+    #[error("{}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("{}: world {source}", path.display())]
+    UnsupportedFormat { path: PathBuf, source: MigrationError },
+    #[error("{}: `{field}`: {reason}", path.display())]
+    Invalid { path: PathBuf, field: String, reason: String },
+}
+
+/// Upgrades world files written by older builds, as JSON so steps can
+/// rename and restructure fields
+pub fn world_migrations() -> Migrations<Value> {
+    Migrations::new(1)
+}
+
+/// What a world needs besides its chunks, stored as `world.json` next to the
+/// region files with its format in the `format` field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorldInfo {
+    pub seed: u64,
+}
+
+impl WorldInfo {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// Reads and upgrades the world file of a world, `None` if there is none
+    pub fn load(dir: &Path) -> Result<Option<Self>, WorldInfoError> {
+        let path = dir.join(WORLD_FILE);
+        match fs::read_to_string(&path) {
+            Ok(json) => Self::parse(&path, &json).map(Some),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(source) => Err(WorldInfoError::Io { path, source }),
+        }
+    }
+
+    /// `path` is only used for errors
+    fn parse(path: &Path, json: &str) -> Result<Self, WorldInfoError> {
+        let invalid = |field: &str, reason: String| WorldInfoError::Invalid {
+            path: path.to_path_buf(),
+            field: field.to_string(),
+            reason,
+        };
+
+        let mut value: Value = serde_json::from_str(json).map_err(|e| invalid("", e.to_string()))?;
+        let format = value.as_object_mut()
+            .and_then(|object| object.remove("format"))
+            .ok_or_else(|| invalid("format", "missing".to_string()))?;
+        let format = format.as_u64()
+            .ok_or_else(|| invalid("format", format!("expected a version, found {}", format)))?;
+        let value = world_migrations().migrate(format.try_into().unwrap_or(u32::MAX), value)
+            .map_err(|source| WorldInfoError::UnsupportedFormat { path: path.to_path_buf(), source })?;
+
+        serde_path_to_error::deserialize(value).map_err(|e| {
+            let (field, reason) = field_error(e);
+            invalid(&field, reason)
+        })
+    }
+
+    /// Writes the world file in the current format, replacing the old one
+    /// only once the new one is complete
+    pub fn save(&self, dir: &Path) -> Result<(), WorldInfoError> {
+        let path = dir.join(WORLD_FILE);
+        let mut value = serde_json::to_value(self).expect("world info is always valid JSON");
+        value.as_object_mut().expect("world info is an object").insert("format".to_string(), WORLD_FORMAT.into());
+        let json = serde_json::to_string_pretty(&value).expect("world info is always valid JSON");
+
+        let temp = temp_path(&path);
+        let io_error = |path: &Path, source| WorldInfoError::Io { path: path.to_path_buf(), source };
+        fs::create_dir_all(dir).map_err(|e| io_error(dir, e))?;
+        fs::write(&temp, json).map_err(|e| io_error(&temp, e))?;
+        fs::rename(&temp, &path).map_err(|e| io_error(&path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::test_dir::TestDir;
+
+    use super::*;
+
+    fn parse(json: &str) -> Result<WorldInfo, String> {
+        WorldInfo::parse(Path::new(WORLD_FILE), json).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn every_format_still_loads() {
+        assert_eq!(parse(include_str!("fixtures/world_v1.json")), Ok(WorldInfo::new(24301)));
+    }
+
+    #[test]
+    fn saved_worlds_load() {
+        let dir = TestDir::new("world-info");
+        assert!(WorldInfo::load(&dir).unwrap().is_none());
+
+        let info = WorldInfo::new(u64::MAX);
+        info.save(&dir).unwrap();
+        assert_eq!(WorldInfo::load(&dir).unwrap(), Some(info));
+        let saved: Value = serde_json::from_str(&fs::read_to_string(dir.join(WORLD_FILE)).unwrap()).unwrap();
+        assert_eq!(saved["format"], WORLD_FORMAT);
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        assert_eq!(parse(r#"{"format": 99, "seed": 1}"#), Err("world.json: world format 99 is newer than 1, the latest this build understands".to_string()));
+        assert_eq!(parse(r#"{"seed": 1}"#), Err("world.json: `format`: missing".to_string()));
+        assert_eq!(parse(r#"{"format": "1", "seed": 1}"#), Err(r#"world.json: `format`: expected a version, found "1""#.to_string()));
+        assert!(parse(r#"{"format": 1, "seed": -1}"#).unwrap_err().starts_with("world.json: `seed`: invalid value"));
+        assert!(parse(r#"{"format": 1, "seed": 1, "name": "a"}"#).unwrap_err().contains("unknown field `name`"));
+    }
+}
@@ -8,14 +8,20 @@ var<uniform> camera: CameraUniform;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
-    @location(1) tex_coords: vec2<i32>,
+    @location(1) tex_coords: vec2<f32>,
     @location(2) normal: vec3<f32>,
+    @location(3) ao: u32,
+    @location(4) texture: u32,
+    @location(5) tint: vec3<f32>,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
-    @location(0) tex_coords: vec2<i32>,
+    @location(0) tex_coords: vec2<f32>,
     @location(1) normal: vec3<f32>,
+    @location(2) ao: f32,
+    @location(3) @interpolate(flat) texture: u32,
+    @location(4) tint: vec3<f32>,
 };
 
 @vertex
@@ -26,6 +32,9 @@ fn vs_main(
     out.tex_coords = model.tex_coords;
     out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
     out.normal = model.normal;
+    out.ao = f32(model.ao);
+    out.texture = model.texture;
+    out.tint = model.tint;
     
     return out;
 }
@@ -37,6 +46,14 @@ var texture_atlas: texture_2d<f32>;
 @group(0) @binding(1)
 var s: sampler;
 
+// Pixel rectangle of a texture in the atlas, max is exclusive
+struct AtlasPointer {
+    min: vec2<i32>,
+    max: vec2<i32>,
+}
+@group(0) @binding(2)
+var<storage, read> atlas_pointers: array<AtlasPointer>;
+
 struct FaceLightingUniform {
     positive: vec3<f32>,
     negative: vec3<f32>,
@@ -53,8 +70,26 @@ fn normal_shading(n: vec3<f32>) -> f32 {
     return ret.x + ret.y + ret.z;
 }
 
+// Samples a texture of the atlas, repeating it once per unit of uv
+fn sample_atlas(pointer: AtlasPointer, uv: vec2<f32>) -> vec4<f32> {
+    let atlas_size = vec2<f32>(textureDimensions(texture_atlas));
+    let min = vec2<f32>(pointer.min);
+    let size = vec2<f32>(pointer.max - pointer.min);
+
+    // Keep half a texel away from the edges so neighboring textures never bleed in
+    let texel = clamp(fract(uv) * size, vec2(0.5), size - 0.5);
+    // Gradients of the unwrapped uv, fract would make them jump at every repeat
+    let scale = size / atlas_size;
+    return textureSampleGrad(texture_atlas, s, (min + texel) / atlas_size, dpdx(uv) * scale, dpdy(uv) * scale);
+}
+
+// How much light each occluding block takes away, a fully occluded corner keeps 40%
+let ao_strength = 0.2;
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let face_brightness = normal_shading(in.normal);
-    return textureLoad(texture_atlas, in.tex_coords, 0) * face_brightness;
+    let ao_brightness = 1.0 - in.ao * ao_strength;
+    let color = sample_atlas(atlas_pointers[in.texture], in.tex_coords);
+    return vec4<f32>(color.rgb * in.tint * face_brightness * ao_brightness, color.a);
 }
\ No newline at end of file
@@ -1 +1,3 @@
-pub mod constants;
\ No newline at end of file
+pub mod constants;
+#[cfg(test)]
+pub mod test_dir;
@@ -0,0 +1,37 @@
+use std::{fs, ops::Deref, path::{Path, PathBuf}, process, sync::atomic::{AtomicUsize, Ordering}};
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty directory for one test, removed with everything in it when
+/// dropped. Names only have to be readable, every directory is unique.
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    pub fn new(name: &str) -> Self {
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("voxel-{}-{}-{}", name, process::id(), n));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
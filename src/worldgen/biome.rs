@@ -0,0 +1,171 @@
+use crate::game::biome::{BiomeId, BiomeRegistry};
+
+use super::noise::{Fbm, derive_seed};
+
+/// Distance between the points biomes are blended from, in blocks
+pub const BLEND_SPACING: i32 = 4;
+
+/// Biomes of the grid points closer than this affect the height of a column
+pub const BLEND_RADIUS: i32 = 12;
+
+/// Picks the biome of every column from temperature and humidity noise
+#[derive(Debug, Clone)]
+pub struct BiomeSource {
+    temperature: Fbm,
+    humidity: Fbm,
+    /// Temperature and humidity of every biome, indexed by `BiomeId`
+    climates: Vec<(f64, f64)>,
+}
+
+impl BiomeSource {
+    pub fn new(seed: u64, biomes: &BiomeRegistry) -> Self {
+        Self {
+            temperature: Fbm::new(derive_seed(seed, "temperature"), 4, 1.0 / 640.0, 0.5),
+            humidity: Fbm::new(derive_seed(seed, "humidity"), 4, 1.0 / 512.0, 0.5),
+            climates: biomes.iter().map(|(_, biome)| (biome.temperature, biome.humidity)).collect(),
+        }
+    }
+
+    /// Temperature and humidity of a column
+    pub fn climate_at(&self, x: i32, z: i32) -> (f64, f64) {
+        let (x, z) = (x as f64, z as f64);
+        (self.temperature.sample2(x, z), self.humidity.sample2(x, z))
+    }
+
+    /// The biome with the closest climate, the first one on ties
+    pub fn biome_at(&self, x: i32, z: i32) -> BiomeId {
+        let (temperature, humidity) = self.climate_at(x, z);
+        let distance = |&(t, h): &(f64, f64)| (t - temperature).powi(2) + (h - humidity).powi(2);
+        let (i, _) = self.climates.iter().enumerate()
+            .fold((0, f64::INFINITY), |best, (i, climate)| match distance(climate) {
+                d if d < best.1 => (i, d),
+                _ => best,
+            });
+        BiomeId(i as u16)
+    }
+
+    /// Biomes of the grid points needed to blend the columns from `min` to
+    /// `max`, both inclusive
+    pub fn grid(&self, min: (i32, i32), max: (i32, i32)) -> BiomeGrid {
+        let low = |v: i32| (v - BLEND_RADIUS).div_euclid(BLEND_SPACING);
+        let high = |v: i32| (v + BLEND_RADIUS).div_euclid(BLEND_SPACING) + 1;
+        let origin = (low(min.0), low(min.1));
+        let (width, depth) = ((high(max.0) - origin.0 + 1) as usize, (high(max.1) - origin.1 + 1) as usize);
+
+        let mut biomes = Vec::with_capacity(width * depth);
+        for gz in 0..depth as i32 {
+            for gx in 0..width as i32 {
+                biomes.push(self.biome_at((origin.0 + gx) * BLEND_SPACING, (origin.1 + gz) * BLEND_SPACING));
+            }
+        }
+        BiomeGrid { origin, width, biomes }
+    }
+}
+
+/// Biomes sampled on a world aligned grid, so every chunk blends the same
+/// points no matter where its neighbors are
+#[derive(Debug, Clone)]
+pub struct BiomeGrid {
+    /// Grid coordinates of the first point
+    origin: (i32, i32),
+    width: usize,
+    biomes: Vec<BiomeId>,
+}
+
+impl BiomeGrid {
+    fn get(&self, gx: i32, gz: i32) -> BiomeId {
+        let (x, z) = ((gx - self.origin.0) as usize, (gz - self.origin.1) as usize);
+        self.biomes[z * self.width + x]
+    }
+
+    /// How much every nearby biome affects a column, the weights add up to 1.
+    /// Weights fall off smoothly so heights don't jump at biome borders.
+    pub fn weights(&self, x: i32, z: i32) -> Vec<(BiomeId, f64)> {
+        let radius = (BLEND_RADIUS * BLEND_RADIUS) as f64;
+        let mut ret: Vec<(BiomeId, f64)> = Vec::new();
+        let mut total = 0.0;
+        for gz in (z - BLEND_RADIUS).div_euclid(BLEND_SPACING)..=(z + BLEND_RADIUS).div_euclid(BLEND_SPACING) + 1 {
+            for gx in (x - BLEND_RADIUS).div_euclid(BLEND_SPACING)..=(x + BLEND_RADIUS).div_euclid(BLEND_SPACING) + 1 {
+                let (dx, dz) = ((gx * BLEND_SPACING - x) as f64, (gz * BLEND_SPACING - z) as f64);
+                let distance = dx * dx + dz * dz;
+                if distance >= radius {
+                    continue;
+                }
+
+                let weight = (1.0 - distance / radius).powi(2);
+                let biome = self.get(gx, gz);
+                match ret.iter_mut().find(|(id, _)| *id == biome) {
+                    Some((_, sum)) => *sum += weight,
+                    None => ret.push((biome, weight)),
+                }
+                total += weight;
+            }
+        }
+
+        for (_, weight) in &mut ret {
+            *weight /= total;
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::biome::{Biome, Color};
+    use crate::game::block::BlockState;
+
+    use super::*;
+
+    fn biome(name: &str, temperature: f64, humidity: f64) -> Biome {
+        Biome {
+            name: name.to_string(),
+            display_name: name.to_string(),
+            temperature,
+            humidity,
+            surface: BlockState(1),
+            underwater: BlockState(1),
+            filler: BlockState(1),
+            filler_depth: 3,
+            height_offset: 0.0,
+            height_scale: 1.0,
+            tree_density: 0.0,
+            grass_color: Color::WHITE,
+            foliage_color: Color::WHITE,
+        }
+    }
+
+    #[test]
+    fn biomes_follow_climate() {
+        let biomes = BiomeRegistry::new(vec![biome("cold", -0.5, 0.0), biome("hot", 0.5, 0.0)]);
+        let source = BiomeSource::new(9, &biomes);
+        let mut seen = [false; 2];
+        for i in 0..2000 {
+            let (x, z) = (i * 97 - 90_000, i * -61);
+            let (temperature, _) = source.climate_at(x, z);
+            let biome = source.biome_at(x, z);
+            assert_eq!(biome, BiomeId((temperature > 0.0) as u16), "temperature {}", temperature);
+            seen[biome.0 as usize] = true;
+        }
+        assert_eq!(seen, [true; 2]);
+    }
+
+    #[test]
+    fn weights_blend_across_borders() {
+        let biomes = BiomeRegistry::new(vec![biome("cold", -0.5, 0.0), biome("hot", 0.5, 0.0)]);
+        let source = BiomeSource::new(4, &biomes);
+        let border = (-5000..5000).step_by(BLEND_SPACING as usize)
+            .find(|&x| source.biome_at(x, 0) != source.biome_at(x + BLEND_SPACING, 0))
+            .expect("a biome border");
+
+        let grid = source.grid((border - 8, -8), (border + 8, 8));
+        for x in border - 8..=border + 8 {
+            for z in -8..=8 {
+                let weights = grid.weights(x, z);
+                assert!((weights.iter().map(|(_, w)| w).sum::<f64>() - 1.0).abs() < 1e-9);
+                // A grid around just this column gives the same weights
+                assert_eq!(source.grid((x, z), (x, z)).weights(x, z), weights);
+            }
+        }
+        assert_eq!(grid.weights(border, 0).len(), 2);
+    }
+}
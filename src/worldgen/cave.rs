@@ -0,0 +1,302 @@
+use std::f64::consts::{PI, TAU};
+
+use crate::game::{block::BlockState, chunk::{Chunk, CHUNK_SIZE}, pos::{ChunkPos, LocalPos}};
+
+use super::{noise::{Fbm, GradientNoise, Rng, derive_seed}, pipeline::{GenerationPass, PassContext}};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaveSettings {
+    /// Cycles per block of the noise carving large open caves
+    pub cheese_frequency: f64,
+    /// Vertical frequency relative to the horizontal one, below 1 for wide flat caves
+    pub cheese_squash: f64,
+    /// Noise above this is carved out, higher is less cave
+    pub cheese_threshold: f64,
+    /// Solid blocks kept between the large caves and the surface
+    pub cheese_roof: i32,
+    /// Chance of a chunk starting a tunnel
+    pub worm_chance: f64,
+    /// Chunks above this height don't start tunnels
+    pub worm_max_y: i32,
+    /// Steps of one block a tunnel takes, the maximum is exclusive
+    pub worm_length: (i32, i32),
+    /// Radius of a tunnel at its ends and at its widest
+    pub worm_radius: (f64, f64),
+    /// Solid blocks kept between the tunnels and the water of columns below the sea level
+    pub underwater_roof: i32,
+    pub sea_level: i32,
+}
+
+impl Default for CaveSettings {
+    fn default() -> Self {
+        Self {
+            cheese_frequency: 1.0 / 64.0,
+            cheese_squash: 0.6,
+            cheese_threshold: 0.3,
+            cheese_roof: 8,
+            worm_chance: 0.06,
+            worm_max_y: 16,
+            worm_length: (24, 96),
+            worm_radius: (1.2, 3.2),
+            underwater_roof: 4,
+            sea_level: 0,
+        }
+    }
+}
+
+/// A sphere of a tunnel, in world coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct WormStep {
+    center: [f64; 3],
+    radius: f64,
+}
+
+/// Carves "cheese" caves where 3D noise is high and tunnels along the paths
+/// of worms steered by noise. Tunnels start in a chunk but can reach into
+/// chunks around it, so each chunk replays every worm that could reach it.
+pub struct CavePass {
+    settings: CaveSettings,
+    water: BlockState,
+    cheese: Fbm,
+    worm_seed: u64,
+}
+
+impl CavePass {
+    /// `water` is never carved
+    pub fn new(seed: u64, water: BlockState) -> Self {
+        Self::with_settings(seed, water, CaveSettings::default())
+    }
+
+    pub fn with_settings(seed: u64, water: BlockState, settings: CaveSettings) -> Self {
+        Self {
+            cheese: Fbm::new(derive_seed(seed, "cheese_caves"), 3, settings.cheese_frequency, 0.5),
+            worm_seed: derive_seed(seed, "worm_caves"),
+            settings,
+            water,
+        }
+    }
+
+    pub fn settings(&self) -> &CaveSettings {
+        &self.settings
+    }
+
+    /// Chunks a tunnel started in can reach, in every direction
+    fn worm_reach(&self) -> i32 {
+        let reach = self.settings.worm_length.1 as f64 + self.settings.worm_radius.1;
+        (reach / CHUNK_SIZE as f64).ceil() as i32
+    }
+
+    /// Path of the tunnel started in `start`, empty if it doesn't start one
+    fn worm(&self, start: ChunkPos) -> Vec<WormStep> {
+        let settings = &self.settings;
+        let mut rng = Rng::for_chunk(self.worm_seed, start);
+        if start.origin().y > settings.worm_max_y || !rng.chance(settings.worm_chance) {
+            return Vec::new();
+        }
+
+        let origin = start.origin();
+        let size = CHUNK_SIZE as i32;
+        let mut pos = [
+            (origin.x + rng.range(0..size)) as f64,
+            (origin.y + rng.range(0..size)) as f64,
+            (origin.z + rng.range(0..size)) as f64,
+        ];
+        let mut yaw = rng.range_f64(0.0, TAU);
+        let mut pitch = rng.range_f64(-0.4, 0.4);
+        let length = rng.range(settings.worm_length.0..settings.worm_length.1);
+        let steering = GradientNoise::new(rng.next_u64());
+        let (thin, wide) = settings.worm_radius;
+
+        (0..length).map(|step| {
+            // Smooth noise instead of random turns keeps the tunnel winding gently
+            let t = step as f64 * 0.07;
+            yaw += steering.sample2(t, 0.5) * 0.35;
+            pitch = (pitch * 0.85 + steering.sample2(t, 17.5) * 0.2).clamp(-1.0, 1.0);
+            pos[0] += yaw.cos() * pitch.cos();
+            pos[1] += pitch.sin();
+            pos[2] += yaw.sin() * pitch.cos();
+
+            // Thickest in the middle so the ends close up
+            let radius = thin + (wide - thin) * (PI * step as f64 / length as f64).sin();
+            WormStep { center: pos, radius }
+        }).collect()
+    }
+
+    /// Whether the block at `y` of a column with its surface at `height` may become air
+    fn carvable(&self, state: BlockState, y: i32, height: i32, roof: i32) -> bool {
+        let roof = if height < self.settings.sea_level { roof.max(self.settings.underwater_roof) } else { roof };
+        !state.is_air() && state != self.water && y <= height.saturating_sub(roof)
+    }
+
+    fn carve_cheese(&self, chunk: &mut Chunk, context: &PassContext) {
+        let origin = chunk.pos.origin();
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let height = context.height(x, z);
+                for y in 0..CHUNK_SIZE {
+                    let local = LocalPos::new(x, y, z);
+                    let world_y = origin.y + y as i32;
+                    if !self.carvable(chunk.get(local), world_y, height, self.settings.cheese_roof) {
+                        continue;
+                    }
+
+                    let value = self.cheese.sample3(
+                        (origin.x + x as i32) as f64,
+                        world_y as f64 / self.settings.cheese_squash,
+                        (origin.z + z as i32) as f64,
+                    );
+                    if value > self.settings.cheese_threshold {
+                        chunk.set(local, BlockState::AIR);
+                    }
+                }
+            }
+        }
+    }
+
+    fn carve_worms(&self, chunk: &mut Chunk, context: &PassContext) {
+        let origin = chunk.pos.origin();
+        let size = CHUNK_SIZE as i32;
+        let reach = self.worm_reach();
+        for dx in -reach..=reach {
+            for dy in -reach..=reach {
+                for dz in -reach..=reach {
+                    for step in self.worm(chunk.pos.offset(dx, dy, dz)) {
+                        // Blocks of the sphere inside of this chunk
+                        let range = |axis: usize, origin: i32| {
+                            let min = ((step.center[axis] - step.radius).floor() as i32).max(origin);
+                            let max = ((step.center[axis] + step.radius).ceil() as i32).min(origin + size - 1);
+                            min..=max
+                        };
+                        for x in range(0, origin.x) {
+                            for y in range(1, origin.y) {
+                                for z in range(2, origin.z) {
+                                    let offset = [x as f64 + 0.5 - step.center[0], y as f64 + 0.5 - step.center[1], z as f64 + 0.5 - step.center[2]];
+                                    if offset.iter().map(|d| d * d).sum::<f64>() > step.radius * step.radius {
+                                        continue;
+                                    }
+
+                                    let (lx, lz) = ((x - origin.x) as usize, (z - origin.z) as usize);
+                                    let local = LocalPos::new(lx, (y - origin.y) as usize, lz);
+                                    if self.carvable(chunk.get(local), y, context.height(lx, lz), 0) {
+                                        chunk.set(local, BlockState::AIR);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl GenerationPass for CavePass {
+    fn name(&self) -> &str {
+        "caves"
+    }
+
+    fn apply(&self, chunk: &mut Chunk, context: &mut PassContext) {
+        self.carve_cheese(chunk, context);
+        self.carve_worms(chunk, context);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::pos::BlockPos;
+
+    use super::*;
+
+    const STONE: BlockState = BlockState(1);
+    const WATER: BlockState = BlockState(2);
+
+    /// Solid stone everywhere, with the surface far above
+    fn carve(pass: &CavePass, pos: ChunkPos) -> Vec<BlockState> {
+        let mut chunk = Chunk::filled(pos, STONE);
+        pass.apply(&mut chunk, &mut PassContext::new(pos));
+        chunk.iter().map(|(_, state)| state).collect()
+    }
+
+    #[test]
+    fn caves_are_deterministic() {
+        let positions = [ChunkPos::new(0, -2, 0), ChunkPos::new(5, -4, -3), ChunkPos::new(-9, -1, 12)];
+        let pass = CavePass::new(77, WATER);
+        let first: Vec<_> = positions.iter().map(|pos| carve(&pass, *pos)).collect();
+        let again = CavePass::new(77, WATER);
+        for (pos, expected) in positions.iter().zip(first).rev() {
+            assert_eq!(carve(&again, *pos), expected, "{:?}", pos);
+        }
+        assert!(positions.iter().any(|pos| carve(&CavePass::new(78, WATER), *pos) != carve(&pass, *pos)));
+    }
+
+    #[test]
+    fn caves_carve_some_of_the_underground() {
+        let pass = CavePass::new(5, WATER);
+        let (mut carved, mut total) = (0, 0);
+        for i in 0..24 {
+            let blocks = carve(&pass, ChunkPos::new(i * 3 - 30, -(i % 4) - 1, i * -2));
+            carved += blocks.iter().filter(|state| state.is_air()).count();
+            total += blocks.len();
+        }
+        let share = carved as f64 / total as f64;
+        assert!((0.005..0.3).contains(&share), "{} carved", share);
+    }
+
+    #[test]
+    fn tunnels_cross_chunk_borders() {
+        let settings = CaveSettings { cheese_threshold: 2.0, worm_chance: 1.0, ..CaveSettings::default() };
+        let pass = CavePass::with_settings(3, WATER, settings);
+        let (start, path) = (0..16)
+            .map(|i| ChunkPos::new(i, -1, 0))
+            .map(|start| (start, pass.worm(start)))
+            .find(|(start, path)| path.iter().any(|step| {
+                let center = step.center.map(|v| v.floor() as i32);
+                BlockPos::new(center[0], center[1], center[2]).chunk() != *start
+            }))
+            .expect("a tunnel leaving its chunk");
+        assert!(path.len() >= pass.settings().worm_length.0 as usize);
+
+        // Every step is carved by whichever chunk it ends up in
+        for step in [path[0], path[path.len() / 2], path[path.len() - 1]] {
+            let center = step.center.map(|v| v.floor() as i32);
+            let block = BlockPos::new(center[0], center[1], center[2]);
+            assert!(carve(&pass, block.chunk())[block.local().index()].is_air(), "{:?} from {:?}", block, start);
+        }
+    }
+
+    #[test]
+    fn water_and_roofs_are_kept() {
+        let settings = CaveSettings { cheese_threshold: -2.0, cheese_roof: 2, worm_chance: 0.0, sea_level: 12, ..CaveSettings::default() };
+        let pass = CavePass::with_settings(8, WATER, settings);
+        let pos = ChunkPos::new(0, 0, 0);
+        let mut chunk = Chunk::new(pos);
+        let mut context = PassContext::new(pos);
+        // Land on one half, a sea floor below the sea level on the other
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let height = if x < 8 { 14 } else { 9 };
+                context.heights[z * CHUNK_SIZE + x] = height;
+                for y in 0..=12 {
+                    chunk.set(LocalPos::new(x, y, z), if y <= height as usize { STONE } else { WATER });
+                }
+                chunk.set(LocalPos::new(x, 13, z), if x < 8 { STONE } else { BlockState::AIR });
+                chunk.set(LocalPos::new(x, 14, z), if x < 8 { STONE } else { BlockState::AIR });
+            }
+        }
+        pass.apply(&mut chunk, &mut context);
+
+        for (local, state) in chunk.iter() {
+            let y = local.y as i32;
+            // Everything that may be carved is, the roof is 4 blocks under water
+            let expected = match (local.x < 8, y) {
+                (true, 0..=12) => BlockState::AIR,
+                (true, 13..=14) => STONE,
+                (false, 0..=5) => BlockState::AIR,
+                (false, 6..=9) => STONE,
+                (false, 10..=12) => WATER,
+                _ => BlockState::AIR,
+            };
+            assert_eq!(state, expected, "{:?}", local);
+        }
+    }
+}
@@ -0,0 +1,658 @@
+use std::path::PathBuf;
+
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{game::{biome::{BiomeId, BiomeRegistry}, block::BlockState, chunk::{Chunk, CHUNK_SIZE}, pos::{BlockPos, LocalPos}, registry::BlockRegistry}, resources::{loader::{FileError, LoadErrors, load_json_dir, parse_json}, manager::ResourceManager}};
+
+use super::{noise::{Rng, derive_seed}, pipeline::{GenerationPass, PassContext, Reach}};
+
+/// Directory inside of a resource pack holding one file per feature
+pub const FEATURES_DIR: &str = "features";
+
+/// Tallest trunk allowed
+pub const MAX_TREE_HEIGHT: i32 = 32;
+
+/// Largest boulder allowed
+pub const MAX_BOULDER_RADIUS: f64 = 4.0;
+
+/// A problem with one field of a feature file
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{}: `{field}`: {reason}", file.display())]
+pub struct FeatureError {
+    pub file: PathBuf,
+    pub field: String,
+    pub reason: String,
+}
+
+impl FileError for FeatureError {
+    fn new(file: PathBuf, field: &str, reason: String) -> Self {
+        Self { file, field: field.to_string(), reason }
+    }
+
+    fn set_file(&mut self, file: PathBuf) {
+        self.file = file;
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RangeJson<T> {
+    min: T,
+    max: T,
+}
+
+/// `features/<name>.json`, blocks are given as state strings and biomes by
+/// name. Features grow in every biome if `biomes` is empty.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum FeatureJson {
+    Tree {
+        #[serde(default)]
+        biomes: Vec<String>,
+        /// Blocks the feature can stand on
+        #[serde(default = "default_ground")]
+        ground: Vec<String>,
+        log: String,
+        leaves: String,
+        shape: TreeShape,
+        /// Length of the trunk
+        height: RangeJson<i32>,
+        /// How often the tree is picked over the other trees of a biome
+        #[serde(default = "default_weight")]
+        weight: u32,
+    },
+    Boulder {
+        #[serde(default)]
+        biomes: Vec<String>,
+        #[serde(default = "default_ground")]
+        ground: Vec<String>,
+        block: String,
+        radius: RangeJson<f64>,
+        /// Average number of boulders in a chunk
+        per_chunk: f64,
+    },
+}
+
+fn default_ground() -> Vec<String> {
+    vec!["grass_block".to_string()]
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// Part of a tree a template block is made of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreePart {
+    Log,
+    Leaves,
+}
+
+/// Shape of the leaves around a trunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TreeShape {
+    /// Round crown on top of the trunk
+    Blob,
+    /// Narrowing layers from low on the trunk up to a point
+    Cone,
+    /// Leaves around a short trunk on the ground
+    Bush,
+}
+
+impl TreeShape {
+    /// Blocks of a tree with a trunk of `height` logs, relative to the
+    /// bottom of the trunk. Leaves never take the place of a log.
+    pub fn template(self, height: i32) -> Vec<([i32; 3], TreePart)> {
+        let mut ret: Vec<_> = (0..height).map(|y| ([0, y, 0], TreePart::Log)).collect();
+        let mut layer = |y: i32, radius: i32, corners: bool| {
+            if y < 0 {
+                return;
+            }
+            for dz in -radius..=radius {
+                for dx in -radius..=radius {
+                    let corner = dx.abs() == radius && dz.abs() == radius;
+                    if (corner && !corners) || (dx == 0 && dz == 0 && y < height) {
+                        continue;
+                    }
+                    ret.push(([dx, y, dz], TreePart::Leaves));
+                }
+            }
+        };
+
+        match self {
+            TreeShape::Blob => {
+                layer(height - 3, 2, false);
+                layer(height - 2, 2, false);
+                layer(height - 1, 1, true);
+                layer(height, 1, false);
+            },
+            TreeShape::Cone => {
+                // Alternating wide and narrow layers below a single leaf on the top
+                for y in (height / 3).max(1)..height {
+                    let wide = (height - 1 - y) % 2 == 1;
+                    layer(y, if wide { 2 } else { 1 }, !wide);
+                }
+                layer(height, 0, true);
+            },
+            TreeShape::Bush => {
+                layer(height - 1, 2, false);
+                layer(height, 1, false);
+            },
+        }
+        ret
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeConfig {
+    pub log: BlockState,
+    pub leaves: BlockState,
+    pub shape: TreeShape,
+    /// Shortest and tallest trunk, both inclusive
+    pub min_height: i32,
+    pub max_height: i32,
+    pub weight: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoulderConfig {
+    pub block: BlockState,
+    pub min_radius: f64,
+    pub max_radius: f64,
+    pub per_chunk: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeatureKind {
+    /// Placed on the surface with the tree density of the biome
+    Tree(TreeConfig),
+    Boulder(BoulderConfig),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureConfig {
+    pub name: String,
+    /// Every biome if empty
+    pub biomes: Vec<BiomeId>,
+    pub ground: Vec<BlockState>,
+    pub kind: FeatureKind,
+}
+
+impl FeatureConfig {
+    pub fn grows_in(&self, biome: BiomeId) -> bool {
+        self.biomes.is_empty() || self.biomes.contains(&biome)
+    }
+}
+
+/// Parses and validates a feature file, `file` is left empty
+pub fn parse_feature(name: &str, json: &str, blocks: &BlockRegistry, biomes: &BiomeRegistry) -> Result<FeatureConfig, FeatureError> {
+    let error = |field: &str, reason: String| FeatureError {
+        file: PathBuf::new(),
+        field: field.to_string(),
+        reason,
+    };
+
+    let raw: FeatureJson = parse_json(json).map_err(|(field, reason)| error(&field, reason))?;
+
+    let state = |field: &str, input: &str| blocks.parse_state(input).map_err(|e| error(field, e.to_string()));
+    let states = |field: &str, inputs: &[String]| inputs.iter().enumerate()
+        .map(|(i, input)| state(&format!("{}[{}]", field, i), input))
+        .collect::<Result<Vec<_>, _>>();
+    let biome_ids = |names: &[String]| names.iter().enumerate()
+        .map(|(i, name)| biomes.get_by_name(name)
+            .map(|(id, _)| id)
+            .ok_or_else(|| error(&format!("biomes[{}]", i), format!("unknown biome `{}`", name))))
+        .collect::<Result<Vec<_>, _>>();
+
+    match raw {
+        FeatureJson::Tree { biomes, ground, log, leaves, shape, height, weight } => {
+            if height.min < 1 || height.max > MAX_TREE_HEIGHT || height.min > height.max {
+                return Err(error("height", format!("{}-{} is not a range inside of 1-{}", height.min, height.max, MAX_TREE_HEIGHT)));
+            }
+            if weight == 0 {
+                return Err(error("weight", "has to be at least 1".to_string()));
+            }
+            Ok(FeatureConfig {
+                name: name.to_string(),
+                biomes: biome_ids(&biomes)?,
+                ground: states("ground", &ground)?,
+                kind: FeatureKind::Tree(TreeConfig {
+                    log: state("log", &log)?,
+                    leaves: state("leaves", &leaves)?,
+                    shape,
+                    min_height: height.min,
+                    max_height: height.max,
+                    weight,
+                }),
+            })
+        },
+        FeatureJson::Boulder { biomes, ground, block, radius, per_chunk } => {
+            if !(radius.min >= 0.5 && radius.max <= MAX_BOULDER_RADIUS && radius.min <= radius.max) {
+                return Err(error("radius", format!("{}-{} is not a range inside of 0.5-{}", radius.min, radius.max, MAX_BOULDER_RADIUS)));
+            }
+            if !per_chunk.is_finite() || per_chunk < 0.0 {
+                return Err(error("per_chunk", format!("{} is not a finite, non negative number", per_chunk)));
+            }
+            Ok(FeatureConfig {
+                name: name.to_string(),
+                biomes: biome_ids(&biomes)?,
+                ground: states("ground", &ground)?,
+                kind: FeatureKind::Boulder(BoulderConfig {
+                    block: state("block", &block)?,
+                    min_radius: radius.min,
+                    max_radius: radius.max,
+                    per_chunk,
+                }),
+            })
+        },
+    }
+}
+
+/// Loads every `.json` file in `features` of every resource pack, sorted by name
+pub fn load_features(resources: &ResourceManager, blocks: &BlockRegistry, biomes: &BiomeRegistry) -> Result<Vec<FeatureConfig>, LoadErrors<FeatureError>> {
+    let (features, errors) = load_json_dir(resources, FEATURES_DIR, |name, json| parse_feature(name, json, blocks, biomes));
+    LoadErrors::check(errors, features.into_iter().map(|(_, feature)| feature).collect())
+}
+
+/// Places trees and boulders on the surface. Where they start is decided
+/// from the chunk alone, the blocks that stick out into other chunks are
+/// handed to the generator to place once those exist.
+pub struct FeaturePass {
+    seed: u64,
+    biomes: BiomeRegistry,
+    features: Vec<FeatureConfig>,
+    /// Which feature blocks overwrite which. Leaves give way to boulders and
+    /// boulders to logs, ties go to the higher state, so overlapping features
+    /// look the same whichever was placed first.
+    ranks: FxHashMap<BlockState, (u8, u32)>,
+}
+
+impl FeaturePass {
+    pub fn new(seed: u64, biomes: BiomeRegistry, features: Vec<FeatureConfig>) -> Self {
+        let mut ranks = FxHashMap::default();
+        let mut rank = |state: BlockState, rank: u8| {
+            let entry = ranks.entry(state).or_insert((rank, state.0));
+            entry.0 = entry.0.max(rank);
+        };
+        for feature in &features {
+            match &feature.kind {
+                FeatureKind::Tree(tree) => {
+                    rank(tree.leaves, 1);
+                    rank(tree.log, 3);
+                },
+                FeatureKind::Boulder(boulder) => rank(boulder.block, 2),
+            }
+        }
+
+        Self {
+            seed: derive_seed(seed, "features"),
+            biomes,
+            features,
+            ranks,
+        }
+    }
+
+    pub fn features(&self) -> &[FeatureConfig] {
+        &self.features
+    }
+
+    /// Air, feature blocks by precedence and `None` for anything else
+    fn rank(&self, state: BlockState) -> Option<(u8, u32)> {
+        if state.is_air() {
+            Some((0, 0))
+        } else {
+            self.ranks.get(&state).copied()
+        }
+    }
+
+    /// Sets a block in the chunk or hands it to the generator
+    fn place(&self, chunk: &mut Chunk, context: &mut PassContext, pos: BlockPos, state: BlockState) {
+        if pos.chunk() != chunk.pos {
+            context.outside.push((pos, state));
+        } else if self.replaces(chunk.get(pos.local()), state) {
+            chunk.set(pos.local(), state);
+        }
+    }
+
+    /// The surface block of a column if it is in this chunk and `feature` can stand on it
+    fn ground(&self, chunk: &Chunk, context: &PassContext, feature: &FeatureConfig, x: usize, z: usize) -> Option<BlockPos> {
+        let y = context.height(x, z) - chunk.pos.origin().y;
+        if !(0..CHUNK_SIZE as i32).contains(&y) || !feature.grows_in(chunk.biome(x, z)) {
+            return None;
+        }
+        let local = LocalPos::new(x, y as usize, z);
+        feature.ground.contains(&chunk.get(local)).then(|| BlockPos::from_parts(chunk.pos, local))
+    }
+
+    fn place_tree(&self, chunk: &mut Chunk, context: &mut PassContext, tree: &TreeConfig, ground: BlockPos, height: i32) {
+        for ([dx, dy, dz], part) in tree.shape.template(height) {
+            let state = match part {
+                TreePart::Log => tree.log,
+                TreePart::Leaves => tree.leaves,
+            };
+            self.place(chunk, context, ground.offset(dx, dy + 1, dz), state);
+        }
+    }
+
+    fn place_boulder(&self, chunk: &mut Chunk, context: &mut PassContext, boulder: &BoulderConfig, ground: BlockPos, radius: f64) {
+        // Half sunk into the ground, which only keeps the top
+        let reach = radius.ceil() as i32;
+        for dy in -reach..=reach {
+            for dz in -reach..=reach {
+                for dx in -reach..=reach {
+                    if ((dx * dx + dy * dy + dz * dz) as f64) <= radius * radius {
+                        self.place(chunk, context, ground.offset(dx, dy, dz), boulder.block);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl GenerationPass for FeaturePass {
+    fn name(&self) -> &str {
+        "features"
+    }
+
+    fn apply(&self, chunk: &mut Chunk, context: &mut PassContext) {
+        let mut rng = Rng::for_chunk(self.seed, chunk.pos);
+
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let biome = chunk.biome(x, z);
+                let density = self.biomes.get(biome).map_or(0.0, |biome| biome.tree_density);
+                // Roll for every column so a change in one column doesn't move the trees of the others
+                let (roll, pick, size) = (rng.next_f64(), rng.next_u64(), rng.next_u64());
+                if roll >= density {
+                    continue;
+                }
+
+                let trees: Vec<_> = self.features.iter()
+                    .filter_map(|feature| match &feature.kind {
+                        FeatureKind::Tree(tree) => Some((feature, tree)),
+                        _ => None,
+                    })
+                    .filter(|(feature, _)| feature.grows_in(biome))
+                    .collect();
+                let total: u64 = trees.iter().map(|(_, tree)| tree.weight as u64).sum();
+                if total == 0 {
+                    continue;
+                }
+                let mut pick = pick % total;
+                let (feature, tree) = trees.into_iter()
+                    .find(|(_, tree)| match pick.checked_sub(tree.weight as u64) {
+                        Some(rest) => {
+                            pick = rest;
+                            false
+                        },
+                        None => true,
+                    })
+                    .expect("the pick is below the total weight");
+                if let Some(ground) = self.ground(chunk, context, feature, x, z) {
+                    let height = tree.min_height + (size % (tree.max_height - tree.min_height + 1) as u64) as i32;
+                    self.place_tree(chunk, context, tree, ground, height);
+                }
+            }
+        }
+
+        for feature in &self.features {
+            let FeatureKind::Boulder(boulder) = &feature.kind else {
+                continue;
+            };
+            let count = boulder.per_chunk.floor() as u32 + rng.chance(boulder.per_chunk.fract()) as u32;
+            for _ in 0..count {
+                let (x, z) = (rng.range(0..CHUNK_SIZE as i32) as usize, rng.range(0..CHUNK_SIZE as i32) as usize);
+                let radius = rng.range_f64(boulder.min_radius, boulder.max_radius);
+                if let Some(ground) = self.ground(chunk, context, feature, x, z) {
+                    self.place_boulder(chunk, context, boulder, ground, radius);
+                }
+            }
+        }
+    }
+
+    fn replaces(&self, current: BlockState, new: BlockState) -> bool {
+        match (self.rank(current), self.rank(new)) {
+            (Some(current), Some(new)) => current < new,
+            _ => false,
+        }
+    }
+
+    /// Trees end up to `MAX_TREE_HEIGHT` blocks above their ground, leaves
+    /// and boulders stick out by at most `MAX_BOULDER_RADIUS` blocks
+    fn reach(&self) -> Reach {
+        let chunks = |blocks: i32| (blocks + CHUNK_SIZE as i32 - 1) / CHUNK_SIZE as i32;
+        let radius = MAX_BOULDER_RADIUS.ceil() as i32;
+        Reach {
+            sides: chunks(radius),
+            below: chunks(radius),
+            above: chunks(MAX_TREE_HEIGHT + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{biome::{Biome, Color}, registry::BlockProperties, world::World, pos::ChunkPos};
+
+    use super::{super::pipeline::WorldGenerator, *};
+
+    const NAMES: [&str; 6] = ["dirt", "grass_block", "oak_log", "oak_leaves", "spruce_leaves", "cobblestone"];
+
+    fn blocks() -> BlockRegistry {
+        let mut registry = BlockRegistry::new();
+        for name in NAMES {
+            registry.register(name, name, BlockProperties::default());
+        }
+        registry
+    }
+
+    fn biomes(tree_density: f64) -> BiomeRegistry {
+        BiomeRegistry::new(vec![Biome {
+            name: "woods".to_string(),
+            display_name: "Woods".to_string(),
+            temperature: 0.0,
+            humidity: 0.0,
+            surface: BlockState(2),
+            underwater: BlockState(1),
+            filler: BlockState(1),
+            filler_depth: 3,
+            height_offset: 0.0,
+            height_scale: 1.0,
+            tree_density,
+            grass_color: Color::WHITE,
+            foliage_color: Color::WHITE,
+        }])
+    }
+
+    fn features(blocks: &BlockRegistry, biomes: &BiomeRegistry) -> Vec<FeatureConfig> {
+        [
+            ("oak", r#"{ "type": "tree", "log": "oak_log", "leaves": "oak_leaves", "shape": "blob", "height": { "min": 4, "max": 6 }, "weight": 3 }"#),
+            ("spruce", r#"{ "type": "tree", "biomes": ["woods"], "log": "oak_log", "leaves": "spruce_leaves", "shape": "cone", "height": { "min": 6, "max": 9 } }"#),
+            ("boulder", r#"{ "type": "boulder", "block": "cobblestone", "radius": { "min": 1.0, "max": 2.5 }, "per_chunk": 1.5 }"#),
+        ].iter().map(|(name, json)| parse_feature(name, json, blocks, biomes).unwrap()).collect()
+    }
+
+    /// Flat grass with its surface at `y = 12`
+    struct Flat;
+
+    impl GenerationPass for Flat {
+        fn name(&self) -> &str {
+            "flat"
+        }
+
+        fn apply(&self, chunk: &mut Chunk, context: &mut PassContext) {
+            context.heights = [12; crate::game::chunk::CHUNK_AREA];
+            for (local, _) in chunk.clone().iter() {
+                let y = BlockPos::from_parts(chunk.pos, local).y;
+                if y < 12 {
+                    chunk.set(local, BlockState(1));
+                } else if y == 12 {
+                    chunk.set(local, BlockState(2));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_features() {
+        let (blocks, biomes) = (blocks(), biomes(0.0));
+        let field = |json: &str| parse_feature("test", json, &blocks, &biomes).unwrap_err().field;
+        let tree = |extra: &str| format!(r#"{{ "type": "tree", "log": "oak_log", "leaves": "oak_leaves", "shape": "blob", {} }}"#, extra);
+        assert_eq!(field(&tree(r#""height": { "min": 0, "max": 4 }"#)), "height");
+        assert_eq!(field(&tree(r#""height": { "min": 5, "max": 4 }"#)), "height");
+        assert_eq!(field(&tree(r#""height": { "min": 4, "max": 4 }, "weight": 0"#)), "weight");
+        assert_eq!(field(&tree(r#""height": { "min": 4, "max": 4 }, "biomes": ["woods", "ocean"]"#)), "biomes[1]");
+        assert_eq!(field(&tree(r#""height": { "min": 4, "max": 4 }, "ground": ["dirt", "mud"]"#)), "ground[1]");
+        assert_eq!(field(r#"{ "type": "tree", "log": "birch_log", "leaves": "oak_leaves", "shape": "blob", "height": { "min": 4, "max": 4 } }"#), "log");
+        assert_eq!(field(r#"{ "type": "boulder", "block": "cobblestone", "radius": { "min": 1.0, "max": 9.0 }, "per_chunk": 1.0 }"#), "radius");
+        assert_eq!(field(r#"{ "type": "boulder", "block": "cobblestone", "radius": { "min": 1.0, "max": 2.0 }, "per_chunk": -1.0 }"#), "per_chunk");
+        assert!(parse_feature("test", r#"{ "type": "well" }"#, &blocks, &biomes).is_err());
+        assert!(parse_feature("test", &tree(r#""height": { "min": 4, "max": 4 }, "fruit": "apple""#), &blocks, &biomes).is_err());
+    }
+
+    #[test]
+    fn templates_grow_around_the_trunk() {
+        for shape in [TreeShape::Blob, TreeShape::Cone, TreeShape::Bush] {
+            for height in 1..=9 {
+                let template = shape.template(height);
+                let logs: Vec<_> = template.iter().filter(|(_, part)| *part == TreePart::Log).map(|(pos, _)| *pos).collect();
+                assert_eq!(logs, (0..height).map(|y| [0, y, 0]).collect::<Vec<_>>());
+
+                let mut seen = rustc_hash::FxHashSet::default();
+                for ([x, y, z], _) in &template {
+                    assert!(seen.insert([*x, *y, *z]), "{:?} {} places {:?} twice", shape, height, [x, y, z]);
+                    assert!(x.abs() <= 2 && z.abs() <= 2 && (0..=height).contains(y));
+                }
+                assert!(template.len() > logs.len(), "{:?} {} has no leaves", shape, height);
+            }
+        }
+    }
+
+    #[test]
+    fn overlapping_features_keep_the_highest_block() {
+        let (blocks, biomes) = (blocks(), biomes(0.0));
+        let pass = FeaturePass::new(1, biomes.clone(), features(&blocks, &biomes));
+        let [dirt, log, oak_leaves, spruce_leaves, cobblestone] = ["dirt", "oak_log", "oak_leaves", "spruce_leaves", "cobblestone"]
+            .map(|name| blocks.get_by_name(name).unwrap().default_state);
+
+        let order = [BlockState::AIR, oak_leaves, spruce_leaves, cobblestone, log];
+        for (i, low) in order.iter().enumerate() {
+            for high in &order[i + 1..] {
+                assert!(pass.replaces(*low, *high) && !pass.replaces(*high, *low));
+            }
+            assert!(!pass.replaces(*low, *low));
+            assert!(!pass.replaces(dirt, *low) && !pass.replaces(*low, dirt));
+        }
+    }
+
+    #[test]
+    fn generation_order_does_not_change_the_result() {
+        let (blocks, biomes) = (blocks(), biomes(0.05));
+        let generator = || WorldGenerator::new()
+            .with_pass(Flat)
+            .with_pass(FeaturePass::new(7, biomes.clone(), features(&blocks, &biomes)));
+        let positions: Vec<_> = (-2..2)
+            .flat_map(|x| (0..2).flat_map(move |y| (-2..2).map(move |z| ChunkPos::new(x, y, z))))
+            .collect();
+
+        let generate = |order: &[ChunkPos]| {
+            let generator = generator();
+            let mut world = World::new();
+            for pos in order {
+                generator.generate_into(&mut world, *pos);
+            }
+            world
+        };
+        let forward = generate(&positions);
+        let backward = generate(&positions.iter().rev().copied().collect::<Vec<_>>());
+        let mut shuffled = positions.clone();
+        let mut rng = Rng::new(3);
+        for i in (1..shuffled.len()).rev() {
+            shuffled.swap(i, rng.range(0..i as i32 + 1) as usize);
+        }
+        let shuffled = generate(&shuffled);
+
+        for pos in &positions {
+            let chunk = forward.get_chunk(*pos).unwrap();
+            assert!(chunk.iter().eq(backward.get_chunk(*pos).unwrap().iter()), "{:?} differs backward", pos);
+            assert!(chunk.iter().eq(shuffled.get_chunk(*pos).unwrap().iter()), "{:?} differs shuffled", pos);
+        }
+
+        // Trees were placed, and some of them reach into the chunks above
+        let log = blocks.get_by_name("oak_log").unwrap().default_state;
+        let above: usize = positions.iter()
+            .filter(|pos| pos.y == 1)
+            .map(|pos| forward.get_chunk(*pos).unwrap().iter().filter(|(_, state)| *state == log).count())
+            .sum();
+        assert!(above > 0);
+        let single = generator();
+        single.generate(ChunkPos::new(0, 0, 0));
+        assert!(single.pending_chunks() > 1);
+    }
+
+    #[test]
+    fn saved_chunks_keep_the_features_of_their_neighbors() {
+        let (blocks, biomes) = (blocks(), biomes(0.05));
+        let generator = || WorldGenerator::new()
+            .with_pass(Flat)
+            .with_pass(FeaturePass::new(7, biomes.clone(), features(&blocks, &biomes)));
+        let positions: Vec<_> = (-2..2)
+            .flat_map(|x| (0..2).flat_map(move |y| (-2..2).map(move |z| ChunkPos::new(x, y, z))))
+            .collect();
+        let expected = generator();
+        let mut all = World::new();
+        for pos in &positions {
+            expected.generate_into(&mut all, *pos);
+        }
+
+        // Every other chunk was saved by an earlier run together with the
+        // blocks its neighbors placed into it, the rest are generated now
+        let (saved, missing): (Vec<ChunkPos>, Vec<ChunkPos>) = positions.iter().partition(|pos| (pos.x + pos.y + pos.z).rem_euclid(2) == 0);
+        let first = generator();
+        let mut world = World::new();
+        for pos in &positions {
+            first.generate_into(&mut world, *pos);
+        }
+        let generator = generator();
+        let mut loaded = World::new();
+        for pos in saved {
+            loaded.insert_chunk(world.get_chunk(pos).unwrap().clone());
+        }
+        for pos in &missing {
+            generator.generate_into(&mut loaded, *pos);
+        }
+        for pos in &positions {
+            assert!(loaded.get_chunk(*pos).unwrap().iter().eq(all.get_chunk(*pos).unwrap().iter()), "{:?} differs", pos);
+        }
+
+        // Nothing lands farther away than the pass says
+        let pass = FeaturePass::new(7, biomes.clone(), features(&blocks, &biomes));
+        let reach = pass.reach();
+        let mut outside = Vec::new();
+        for x in 0..8 {
+            let mut chunk = Chunk::new(ChunkPos::new(x, 0, 0));
+            let mut context = PassContext::new(chunk.pos);
+            Flat.apply(&mut chunk, &mut context);
+            pass.apply(&mut chunk, &mut context);
+            outside.extend(context.outside.iter().map(|(block, _)| (chunk.pos, block.chunk())));
+        }
+        assert!(!outside.is_empty());
+        for (from, to) in outside {
+            assert!((to.x - from.x).abs() <= reach.sides && (to.z - from.z).abs() <= reach.sides, "{:?} from {:?}", to, from);
+            assert!((-reach.below..=reach.above).contains(&(to.y - from.y)), "{:?} from {:?}", to, from);
+        }
+    }
+
+    #[test]
+    fn shipped_features() {
+        let resources = ResourceManager::open(&["assets"]).unwrap();
+        let blocks = BlockRegistry::from(crate::game::static_data::StaticBlockData::load(&resources).unwrap());
+        let biomes = BiomeRegistry::load(&resources, &blocks).unwrap();
+        let features = load_features(&resources, &blocks, &biomes).unwrap();
+        assert!(features.iter().any(|feature| matches!(feature.kind, FeatureKind::Tree(_))));
+        assert!(features.iter().any(|feature| matches!(feature.kind, FeatureKind::Boulder(_))));
+    }
+}
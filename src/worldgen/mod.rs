@@ -0,0 +1,7 @@
+pub mod biome;
+pub mod cave;
+pub mod feature;
+pub mod noise;
+pub mod ore;
+pub mod pipeline;
+pub mod terrain;
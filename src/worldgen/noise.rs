@@ -0,0 +1,252 @@
+use std::{f64::consts::FRAC_1_SQRT_2, ops::Range};
+
+use crate::game::pos::ChunkPos;
+
+/// Finalizer of SplitMix64, spreads every input bit over the whole output
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Random bits for a lattice point. Only depends on its arguments, which keeps
+/// generation independent of the order chunks are generated in.
+pub fn hash(seed: u64, x: i32, y: i32, z: i32) -> u64 {
+    let mut h = mix(seed ^ 0x9E37_79B9_7F4A_7C15);
+    for v in [x, y, z] {
+        h = mix(h ^ (v as u32 as u64));
+    }
+    h
+}
+
+/// Seed of an independent generator for one purpose, e.g. `"erosion"`
+pub fn derive_seed(seed: u64, purpose: &str) -> u64 {
+    // FNV-1a, stable across platforms and releases unlike `DefaultHasher`
+    let name = purpose.bytes().fold(0xCBF2_9CE4_8422_2325_u64, |h, b| (h ^ b as u64).wrapping_mul(0x0100_0000_01B3));
+    mix(seed ^ mix(name))
+}
+
+/// SplitMix64, for decisions that don't need to be smooth like where an ore
+/// vein starts. Seeded from a position, so results don't depend on the order
+/// chunks are generated in.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Generator for the decisions made in one chunk
+    pub fn for_chunk(seed: u64, pos: ChunkPos) -> Self {
+        Self::new(hash(seed, pos.x, pos.y, pos.z))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        mix(self.state)
+    }
+
+    /// Uniform in `0.0..1.0`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `range`, which can't be empty
+    pub fn range(&mut self, range: Range<i32>) -> i32 {
+        assert!(!range.is_empty(), "empty range");
+        let len = (range.end as i64 - range.start as i64) as u64;
+        (range.start as i64 + (self.next_u64() % len) as i64) as i32
+    }
+
+    /// Uniform in `min..max`
+    pub fn range_f64(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.next_f64()
+    }
+
+    /// True with probability `p`
+    pub fn chance(&mut self, p: f64) -> bool {
+        self.next_f64() < p
+    }
+}
+
+const D: f64 = FRAC_1_SQRT_2;
+
+const GRADIENTS_2D: [(f64, f64); 8] = [
+    (1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0),
+    (D, D), (-D, D), (D, -D), (-D, -D),
+];
+
+/// Directions to the edges of a cube, as in improved Perlin noise
+const GRADIENTS_3D: [(f64, f64, f64); 12] = [
+    (1.0, 1.0, 0.0), (-1.0, 1.0, 0.0), (1.0, -1.0, 0.0), (-1.0, -1.0, 0.0),
+    (1.0, 0.0, 1.0), (-1.0, 0.0, 1.0), (1.0, 0.0, -1.0), (-1.0, 0.0, -1.0),
+    (0.0, 1.0, 1.0), (0.0, -1.0, 1.0), (0.0, 1.0, -1.0), (0.0, -1.0, -1.0),
+];
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+/// Perlin gradient noise with gradients picked by hashing the lattice points
+/// instead of a permutation table, so it doesn't repeat
+#[derive(Debug, Clone, Copy)]
+pub struct GradientNoise {
+    seed: u64,
+}
+
+impl GradientNoise {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// Smooth noise in `-1.0..=1.0`, 0 at every integer point
+    pub fn sample2(&self, x: f64, z: f64) -> f64 {
+        let (x0, z0) = (x.floor(), z.floor());
+        let (fx, fz) = (x - x0, z - z0);
+        let (ix, iz) = (x0 as i32, z0 as i32);
+
+        let corner = |dx: i32, dz: i32| {
+            let (gx, gz) = GRADIENTS_2D[(hash(self.seed, ix + dx, 0, iz + dz) % 8) as usize];
+            gx * (fx - dx as f64) + gz * (fz - dz as f64)
+        };
+
+        let (u, v) = (fade(fx), fade(fz));
+        let value = lerp(
+            lerp(corner(0, 0), corner(1, 0), u),
+            lerp(corner(0, 1), corner(1, 1), u),
+            v,
+        );
+        (value * 1.4).clamp(-1.0, 1.0)
+    }
+
+    /// Smooth noise in `-1.0..=1.0`, 0 at every integer point
+    pub fn sample3(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+        let (fx, fy, fz) = (x - x0, y - y0, z - z0);
+        let (ix, iy, iz) = (x0 as i32, y0 as i32, z0 as i32);
+
+        let corner = |dx: i32, dy: i32, dz: i32| {
+            let (gx, gy, gz) = GRADIENTS_3D[(hash(self.seed, ix + dx, iy + dy, iz + dz) % 12) as usize];
+            gx * (fx - dx as f64) + gy * (fy - dy as f64) + gz * (fz - dz as f64)
+        };
+
+        let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+        let value = lerp(
+            lerp(
+                lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+                lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+                v,
+            ),
+            lerp(
+                lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+                lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+                v,
+            ),
+            w,
+        );
+        value.clamp(-1.0, 1.0)
+    }
+}
+
+/// Octaves of gradient noise at increasing frequency and decreasing amplitude
+#[derive(Debug, Clone)]
+pub struct Fbm {
+    octaves: Vec<GradientNoise>,
+    /// Frequency of the first octave, in cycles per block
+    frequency: f64,
+    /// Amplitude factor from one octave to the next
+    persistence: f64,
+}
+
+impl Fbm {
+    pub fn new(seed: u64, octaves: u32, frequency: f64, persistence: f64) -> Self {
+        Self {
+            octaves: (0..octaves).map(|i| GradientNoise::new(derive_seed(seed, &format!("octave{}", i)))).collect(),
+            frequency,
+            persistence,
+        }
+    }
+
+    fn sum(&self, sample: impl Fn(&GradientNoise, f64) -> f64) -> f64 {
+        let (mut sum, mut total, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, self.frequency);
+        for octave in &self.octaves {
+            sum += sample(octave, frequency) * amplitude;
+            total += amplitude;
+            amplitude *= self.persistence;
+            frequency *= 2.0;
+        }
+        if total > 0.0 { sum / total } else { 0.0 }
+    }
+
+    /// Noise in `-1.0..=1.0`
+    pub fn sample2(&self, x: f64, z: f64) -> f64 {
+        self.sum(|noise, frequency| noise.sample2(x * frequency, z * frequency))
+    }
+
+    /// Noise in `-1.0..=1.0`
+    pub fn sample3(&self, x: f64, y: f64, z: f64) -> f64 {
+        self.sum(|noise, frequency| noise.sample3(x * frequency, y * frequency, z * frequency))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_is_deterministic_and_bounded() {
+        let (a, b) = (GradientNoise::new(1), GradientNoise::new(2));
+        let mut differs = false;
+        for i in 0..2000 {
+            let (x, y, z) = (i as f64 * 0.37 - 300.0, i as f64 * 0.11, i as f64 * -0.53);
+            let value = a.sample3(x, y, z);
+            assert!((-1.0..=1.0).contains(&value));
+            assert!((-1.0..=1.0).contains(&a.sample2(x, z)));
+            assert_eq!(value, GradientNoise::new(1).sample3(x, y, z));
+            differs |= value != b.sample3(x, y, z);
+        }
+        assert!(differs);
+        assert_eq!(a.sample2(-4.0, 7.0), 0.0);
+    }
+
+    #[test]
+    fn noise_is_continuous() {
+        let noise = Fbm::new(7, 4, 1.0 / 32.0, 0.5);
+        for i in 0..1000 {
+            let x = i as f64 * 0.731 - 200.0;
+            let step = (noise.sample2(x, 3.5) - noise.sample2(x + 0.01, 3.5)).abs();
+            assert!(step < 0.02, "jump of {} at {}", step, x);
+        }
+    }
+
+    #[test]
+    fn seeds_differ() {
+        assert_ne!(derive_seed(5, "continentalness"), derive_seed(5, "erosion"));
+        assert_ne!(derive_seed(5, "erosion"), derive_seed(6, "erosion"));
+        assert_ne!(hash(1, 0, 0, 1), hash(1, 0, 1, 0));
+    }
+
+    #[test]
+    fn rng_is_seeded_and_uniform() {
+        let pos = ChunkPos::new(-2, 5, 9);
+        let sample = |mut rng: Rng| (0..8).map(|_| rng.next_u64()).collect::<Vec<_>>();
+        assert_eq!(sample(Rng::for_chunk(3, pos)), sample(Rng::for_chunk(3, pos)));
+        assert_ne!(sample(Rng::for_chunk(3, pos)), sample(Rng::for_chunk(3, pos.offset(1, 0, 0))));
+
+        let mut rng = Rng::new(1);
+        let mut counts = [0; 4];
+        for _ in 0..4000 {
+            let value = rng.range(-2..2);
+            counts[(value + 2) as usize] += 1;
+            assert!((0.0..1.0).contains(&rng.next_f64()));
+        }
+        assert!(counts.iter().all(|count| (800..1200).contains(count)), "{:?}", counts);
+    }
+}
@@ -0,0 +1,278 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{game::{block::BlockState, chunk::{Chunk, CHUNK_SIZE}, pos::{BlockPos, ChunkPos, Direction}, registry::BlockRegistry}, resources::{loader::{FileError, LoadErrors, load_json_dir, parse_json}, manager::ResourceManager}};
+
+use super::{noise::{Rng, derive_seed}, pipeline::{GenerationPass, PassContext}};
+
+/// Directory inside of a resource pack holding one file per kind of ore
+pub const ORES_DIR: &str = "ores";
+
+/// Longest vein allowed, so every chunk only has to look at its neighbors
+pub const MAX_VEIN_SIZE: u32 = CHUNK_SIZE as u32;
+
+/// A problem with one field of an ore file
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{}: `{field}`: {reason}", file.display())]
+pub struct OreError {
+    pub file: PathBuf,
+    pub field: String,
+    pub reason: String,
+}
+
+impl FileError for OreError {
+    fn new(file: PathBuf, field: &str, reason: String) -> Self {
+        Self { file, field: field.to_string(), reason }
+    }
+
+    fn set_file(&mut self, file: PathBuf) {
+        self.file = file;
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HeightRangeJson {
+    min: i32,
+    max: i32,
+}
+
+/// `ores/<name>.json`, blocks are given as state strings
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OreJson {
+    block: String,
+    /// Blocks the ore can replace
+    #[serde(default = "default_replace")]
+    replace: Vec<String>,
+    height: HeightRangeJson,
+    vein_size: u32,
+    /// Average number of veins started in a chunk inside of the height range
+    veins_per_chunk: f64,
+}
+
+fn default_replace() -> Vec<String> {
+    vec!["stone".to_string()]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OreConfig {
+    pub name: String,
+    pub block: BlockState,
+    pub replace: Vec<BlockState>,
+    /// Lowest and highest Y the ore is placed at, both inclusive
+    pub min_y: i32,
+    pub max_y: i32,
+    /// Blocks in a vein, fewer if some can't be replaced
+    pub vein_size: u32,
+    pub veins_per_chunk: f64,
+}
+
+/// Parses and validates an ore file, `file` is left empty
+pub fn parse_ore(name: &str, json: &str, blocks: &BlockRegistry) -> Result<OreConfig, OreError> {
+    let error = |field: &str, reason: String| OreError {
+        file: PathBuf::new(),
+        field: field.to_string(),
+        reason,
+    };
+
+    let raw: OreJson = parse_json(json).map_err(|(field, reason)| error(&field, reason))?;
+
+    if raw.height.min > raw.height.max {
+        return Err(error("height", format!("min {} is above max {}", raw.height.min, raw.height.max)));
+    }
+    if !(1..=MAX_VEIN_SIZE).contains(&raw.vein_size) {
+        return Err(error("vein_size", format!("{} is out of range 1-{}", raw.vein_size, MAX_VEIN_SIZE)));
+    }
+    if !raw.veins_per_chunk.is_finite() || raw.veins_per_chunk < 0.0 {
+        return Err(error("veins_per_chunk", format!("{} is not a finite, non negative number", raw.veins_per_chunk)));
+    }
+
+    let state = |field: &str, input: &str| blocks.parse_state(input).map_err(|e| error(field, e.to_string()));
+    Ok(OreConfig {
+        name: name.to_string(),
+        block: state("block", &raw.block)?,
+        replace: raw.replace.iter().enumerate()
+            .map(|(i, input)| state(&format!("replace[{}]", i), input))
+            .collect::<Result<_, _>>()?,
+        min_y: raw.height.min,
+        max_y: raw.height.max,
+        vein_size: raw.vein_size,
+        veins_per_chunk: raw.veins_per_chunk,
+    })
+}
+
+/// Loads every `.json` file in `ores` of every resource pack, sorted by name
+pub fn load_ores(resources: &ResourceManager, blocks: &BlockRegistry) -> Result<Vec<OreConfig>, LoadErrors<OreError>> {
+    let (ores, errors) = load_json_dir(resources, ORES_DIR, |name, json| parse_ore(name, json, blocks));
+    LoadErrors::check(errors, ores.into_iter().map(|(_, ore)| ore).collect())
+}
+
+/// Scatters veins of every ore. Veins start in a chunk and grow from block to
+/// block, so each chunk also places the parts of the veins of its neighbors
+/// that reach into it.
+pub struct OrePass {
+    /// Every ore with the seed of its veins
+    ores: Vec<(OreConfig, u64)>,
+}
+
+impl OrePass {
+    pub fn new(seed: u64, ores: Vec<OreConfig>) -> Self {
+        Self {
+            ores: ores.into_iter().map(|ore| {
+                let seed = derive_seed(seed, &format!("ore:{}", ore.name));
+                (ore, seed)
+            }).collect(),
+        }
+    }
+
+    /// Blocks of the veins of `ore` started in `start`, in world coordinates
+    fn veins(ore: &OreConfig, seed: u64, start: ChunkPos) -> Vec<BlockPos> {
+        let origin = start.origin();
+        let size = CHUNK_SIZE as i32;
+        // Only the part of the chunk inside of the height range
+        let (min_y, max_y) = (ore.min_y.max(origin.y), ore.max_y.min(origin.y + size - 1));
+        if min_y > max_y {
+            return Vec::new();
+        }
+
+        let mut rng = Rng::for_chunk(seed, start);
+        let share = (max_y - min_y + 1) as f64 / size as f64;
+        let expected = ore.veins_per_chunk * share;
+        let count = expected.floor() as u32 + rng.chance(expected.fract()) as u32;
+
+        let mut ret = Vec::new();
+        for _ in 0..count {
+            let mut pos = BlockPos::new(origin.x + rng.range(0..size), rng.range(min_y..max_y + 1), origin.z + rng.range(0..size));
+            for _ in 0..ore.vein_size {
+                ret.push(pos);
+                let (dx, dy, dz) = Direction::ALL[rng.range(0..6) as usize].offset();
+                pos = pos.offset(dx, dy, dz);
+            }
+        }
+        ret
+    }
+}
+
+impl GenerationPass for OrePass {
+    fn name(&self) -> &str {
+        "ores"
+    }
+
+    fn apply(&self, chunk: &mut Chunk, _: &mut PassContext) {
+        let center = chunk.pos;
+        for (ore, seed) in &self.ores {
+            // Veins are at most one chunk long, so they only reach direct neighbors
+            for start in std::iter::once(center).chain(center.surrounding()) {
+                for pos in Self::veins(ore, *seed, start) {
+                    if pos.chunk() != center || !(ore.min_y..=ore.max_y).contains(&pos.y) {
+                        continue;
+                    }
+                    if ore.replace.contains(&chunk.get(pos.local())) {
+                        chunk.set(pos.local(), ore.block);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::registry::BlockProperties;
+
+    use super::*;
+
+    fn blocks() -> BlockRegistry {
+        let mut registry = BlockRegistry::new();
+        for name in ["stone", "dirt", "coal_ore"] {
+            registry.register(name, name, BlockProperties::default());
+        }
+        registry
+    }
+
+    fn coal(blocks: &BlockRegistry) -> OreConfig {
+        parse_ore("coal", r#"{
+            "block": "coal_ore",
+            "height": { "min": -40, "max": 10 },
+            "vein_size": 12,
+            "veins_per_chunk": 6.5
+        }"#, blocks).unwrap()
+    }
+
+    #[test]
+    fn invalid_ores() {
+        let blocks = blocks();
+        let field = |json: &str| parse_ore("test", json, &blocks).unwrap_err().field;
+        assert_eq!(field(r#"{ "block": "gold_ore", "height": { "min": 0, "max": 1 }, "vein_size": 4, "veins_per_chunk": 1 }"#), "block");
+        assert_eq!(field(r#"{ "block": "coal_ore", "replace": ["stone", "lava"], "height": { "min": 0, "max": 1 }, "vein_size": 4, "veins_per_chunk": 1 }"#), "replace[1]");
+        assert_eq!(field(r#"{ "block": "coal_ore", "height": { "min": 5, "max": 1 }, "vein_size": 4, "veins_per_chunk": 1 }"#), "height");
+        assert_eq!(field(r#"{ "block": "coal_ore", "height": { "min": 0, "max": 1, "avg": 0 }, "vein_size": 4, "veins_per_chunk": 1 }"#), "height.avg");
+        assert_eq!(field(r#"{ "block": "coal_ore", "height": { "min": 0, "max": 1 }, "vein_size": 40, "veins_per_chunk": 1 }"#), "vein_size");
+        assert_eq!(field(r#"{ "block": "coal_ore", "height": { "min": 0, "max": 1 }, "vein_size": 4, "veins_per_chunk": -1 }"#), "veins_per_chunk");
+    }
+
+    #[test]
+    fn veins_stay_in_range_and_replace_only_stone() {
+        let blocks = blocks();
+        let [stone, dirt, coal_ore] = ["stone", "dirt", "coal_ore"].map(|name| blocks.get_by_name(name).unwrap().default_state);
+        let pass = OrePass::new(12, vec![coal(&blocks)]);
+
+        let mut total = 0;
+        for pos in [ChunkPos::new(0, -1, 0), ChunkPos::new(4, 0, -7), ChunkPos::new(-3, -3, 2), ChunkPos::new(1, 2, 1)] {
+            // Dirt on the top half
+            let mut chunk = Chunk::filled(pos, stone);
+            for (local, _) in chunk.clone().iter().filter(|(local, _)| local.y >= 8) {
+                chunk.set(local, dirt);
+            }
+            pass.apply(&mut chunk, &mut PassContext::new(pos));
+
+            for (local, state) in chunk.iter() {
+                if state == coal_ore {
+                    total += 1;
+                    assert!(local.y < 8);
+                    assert!((-40..=10).contains(&BlockPos::from_parts(pos, local).y));
+                }
+            }
+
+            let mut again = Chunk::filled(pos, stone);
+            pass.apply(&mut again, &mut PassContext::new(pos));
+            let mut other = Chunk::filled(pos, stone);
+            OrePass::new(12, vec![coal(&blocks)]).apply(&mut other, &mut PassContext::new(pos));
+            assert!(again.iter().eq(other.iter()));
+        }
+        assert!(total > 20, "{} ores", total);
+    }
+
+    #[test]
+    fn veins_cross_chunk_borders() {
+        let blocks = blocks();
+        let ore = coal(&blocks);
+        let seed = derive_seed(3, "ore:coal");
+        // Every block of every vein ends up in the chunk containing it
+        let start = ChunkPos::new(0, -1, 0);
+        let outside: Vec<_> = (0..40)
+            .flat_map(|i| OrePass::veins(&ore, seed, start.offset(i, 0, 0)).into_iter().map(move |pos| (i, pos)))
+            .filter(|(i, pos)| pos.chunk() != start.offset(*i, 0, 0) && (ore.min_y..=ore.max_y).contains(&pos.y))
+            .collect();
+        assert!(!outside.is_empty());
+
+        let pass = OrePass { ores: vec![(ore, seed)] };
+        let stone = blocks.get_by_name("stone").unwrap().default_state;
+        for (_, pos) in outside.iter().take(10) {
+            let mut chunk = Chunk::filled(pos.chunk(), stone);
+            pass.apply(&mut chunk, &mut PassContext::new(pos.chunk()));
+            assert_ne!(chunk.get(pos.local()), stone, "{:?}", pos);
+        }
+    }
+
+    #[test]
+    fn shipped_ores() {
+        let resources = ResourceManager::open(&["assets"]).unwrap();
+        let blocks = BlockRegistry::from(crate::game::static_data::StaticBlockData::load(&resources).unwrap());
+        let ores = load_ores(&resources, &blocks).unwrap();
+        assert!(!ores.is_empty());
+    }
+}
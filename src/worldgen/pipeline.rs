@@ -0,0 +1,325 @@
+use std::sync::Mutex;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::game::{block::BlockState, chunk::{Chunk, CHUNK_AREA, CHUNK_SIZE}, pos::{BlockPos, ChunkPos, LocalPos}, world::World};
+
+/// What the passes of one chunk know about it besides its blocks
+#[derive(Debug, Clone)]
+pub struct PassContext {
+    pub pos: ChunkPos,
+    /// Y of the topmost solid block of every column before carving, indexed
+    /// by `z * CHUNK_SIZE + x`. `i32::MAX` until a pass shapes the terrain.
+    pub heights: [i32; CHUNK_AREA],
+    /// Blocks the current pass places outside of the chunk, they are written
+    /// once the chunks containing them are generated
+    pub outside: Vec<(BlockPos, BlockState)>,
+}
+
+impl PassContext {
+    pub fn new(pos: ChunkPos) -> Self {
+        Self { pos, heights: [i32::MAX; CHUNK_AREA], outside: Vec::new() }
+    }
+
+    /// Surface height of the column at local `x` and `z`
+    pub fn height(&self, x: usize, z: usize) -> i32 {
+        self.heights[z * CHUNK_SIZE + x]
+    }
+}
+
+/// How many chunks away from its own chunk a pass places blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Reach {
+    /// Along x and z
+    pub sides: i32,
+    pub below: i32,
+    pub above: i32,
+}
+
+impl Reach {
+    /// The chunks whose blocks can land in `pos`
+    fn sources(self, pos: ChunkPos) -> impl Iterator<Item = ChunkPos> {
+        let sides = -self.sides..=self.sides;
+        sides.clone().flat_map(move |x| sides.clone().flat_map(move |z| (-self.below..=self.above).map(move |y| (x, y, z))))
+            .filter(|offset| *offset != (0, 0, 0))
+            .map(move |(x, y, z)| pos.offset(-x, -y, -z))
+    }
+}
+
+/// One step of world generation. A pass may only depend on the seed it was
+/// created with, the chunk position and what earlier passes did to the chunk.
+pub trait GenerationPass: Send + Sync {
+    /// Shown in logs, e.g. `"caves"`
+    fn name(&self) -> &str;
+
+    fn apply(&self, chunk: &mut Chunk, context: &mut PassContext);
+
+    /// Whether a block this pass placed outside of its chunk overwrites
+    /// `current`. Chunks are generated in any order, so passes that place
+    /// blocks outside must give the same result whatever order the blocks
+    /// of different chunks arrive in.
+    fn replaces(&self, _current: BlockState, _new: BlockState) -> bool {
+        true
+    }
+
+    /// How far the blocks this pass places outside of its chunk can land,
+    /// nowhere by default
+    fn reach(&self) -> Reach {
+        Reach::default()
+    }
+}
+
+/// A block waiting for its chunk to be generated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PendingWrite {
+    /// Index of the pass that placed it
+    pass: usize,
+    pos: LocalPos,
+    state: BlockState,
+}
+
+/// Generates chunks by running its passes in order. Blocks the passes place
+/// in other chunks are kept until those chunks are generated, or written
+/// into the world right away if they already were.
+#[derive(Default)]
+pub struct WorldGenerator {
+    passes: Vec<Box<dyn GenerationPass>>,
+    pending: Mutex<FxHashMap<ChunkPos, Vec<PendingWrite>>>,
+    /// Chunks whose outside blocks went through `pending`
+    generated: Mutex<FxHashSet<ChunkPos>>,
+}
+
+impl WorldGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a pass that runs after every pass added before it
+    pub fn with_pass(mut self, pass: impl GenerationPass + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn pass_names(&self) -> impl Iterator<Item = &str> {
+        self.passes.iter().map(|pass| pass.name())
+    }
+
+    /// Generates a chunk with the blocks placed into it by the chunks
+    /// generated before. Blocks it places into chunks that are already
+    /// generated are only written by `generate_into`.
+    pub fn generate(&self, pos: ChunkPos) -> Chunk {
+        self.generate_chunk(pos).0
+    }
+
+    /// Generates a chunk into the world and writes the blocks it places
+    /// outside of itself into the loaded chunks. Loaded neighbors this
+    /// generator didn't generate, e.g. ones loaded from a save, are generated
+    /// again on the side for the blocks they place into the chunk, so saved
+    /// chunks have to be loaded before their neighbors are generated.
+    pub fn generate_into(&self, world: &mut World, pos: ChunkPos) {
+        let sources: Vec<_> = {
+            let generated = self.generated.lock().unwrap();
+            self.reach().sources(pos).filter(|source| world.is_loaded(*source) && !generated.contains(source)).collect()
+        };
+        for source in sources {
+            let writes = self.run_passes(source).1.into_iter().filter(|(_, block, _)| block.chunk() == pos);
+            let writes: Vec<_> = writes.map(|(pass, block, state)| PendingWrite { pass, pos: block.local(), state }).collect();
+            self.pending.lock().unwrap().entry(pos).or_default().extend(writes);
+        }
+
+        let (chunk, targets) = self.generate_chunk(pos);
+        world.insert_chunk(chunk);
+        for target in targets {
+            self.apply_pending(world, target);
+        }
+    }
+
+    /// Writes the blocks waiting for a chunk into it if it is loaded, for
+    /// chunks that were loaded from somewhere else than this generator
+    pub fn apply_pending(&self, world: &mut World, pos: ChunkPos) {
+        if !world.is_loaded(pos) {
+            return;
+        }
+        let writes = self.pending.lock().unwrap().remove(&pos).unwrap_or_default();
+        for write in writes {
+            let pos = BlockPos::from_parts(pos, write.pos);
+            let current = world.get_block(pos).expect("the chunk is loaded");
+            if self.passes[write.pass].replaces(current, write.state) {
+                world.set_block(pos, write.state);
+            }
+        }
+    }
+
+    /// Number of chunks with blocks waiting for them
+    pub fn pending_chunks(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// The farthest any pass places blocks outside of its chunk
+    pub fn reach(&self) -> Reach {
+        self.passes.iter().map(|pass| pass.reach()).fold(Reach::default(), |a, b| Reach {
+            sides: a.sides.max(b.sides),
+            below: a.below.max(b.below),
+            above: a.above.max(b.above),
+        })
+    }
+
+    /// The chunk with only its own blocks, and the blocks every pass placed
+    /// outside of it by pass index
+    fn run_passes(&self, pos: ChunkPos) -> (Chunk, Vec<(usize, BlockPos, BlockState)>) {
+        let mut chunk = Chunk::new(pos);
+        let mut context = PassContext::new(pos);
+        let mut outside = Vec::new();
+        for (i, pass) in self.passes.iter().enumerate() {
+            pass.apply(&mut chunk, &mut context);
+            for (block, state) in context.outside.drain(..) {
+                debug_assert_ne!(block.chunk(), pos, "`{}` placed a block inside of its chunk as outside", pass.name());
+                outside.push((i, block, state));
+            }
+        }
+        (chunk, outside)
+    }
+
+    /// The chunk and the other chunks it placed blocks into
+    fn generate_chunk(&self, pos: ChunkPos) -> (Chunk, Vec<ChunkPos>) {
+        let (mut chunk, outside) = self.run_passes(pos);
+        let mut targets = Vec::new();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            for (pass, block, state) in outside {
+                let target = block.chunk();
+                pending.entry(target).or_default().push(PendingWrite { pass, pos: block.local(), state });
+                if !targets.contains(&target) {
+                    targets.push(target);
+                }
+            }
+        }
+        self.generated.lock().unwrap().insert(pos);
+
+        // Blocks from the neighbors go on top of everything the chunk generated itself
+        let writes = self.pending.lock().unwrap().remove(&pos).unwrap_or_default();
+        for write in writes {
+            if self.passes[write.pass].replaces(chunk.get(write.pos), write.state) {
+                chunk.set(write.pos, write.state);
+            }
+        }
+
+        chunk.compact();
+        (chunk, targets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{block::BlockState, pos::LocalPos};
+
+    use super::*;
+
+    /// Sets one block, or copies the block set by the pass before it
+    struct Stamp(&'static str, BlockState);
+
+    impl GenerationPass for Stamp {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn apply(&self, chunk: &mut Chunk, _: &mut PassContext) {
+            let below = chunk.get(LocalPos::new(0, 0, 0));
+            chunk.set(LocalPos::new(0, 0, 0), self.1);
+            chunk.set(LocalPos::new(1, 0, 0), below);
+        }
+    }
+
+    #[test]
+    fn passes_run_in_order() {
+        let generator = WorldGenerator::new()
+            .with_pass(Stamp("first", BlockState(1)))
+            .with_pass(Stamp("second", BlockState(2)));
+        assert_eq!(generator.pass_names().collect::<Vec<_>>(), ["first", "second"]);
+
+        let chunk = generator.generate(ChunkPos::new(0, 3, 0));
+        assert_eq!(chunk.get(LocalPos::new(0, 0, 0)), BlockState(2));
+        assert_eq!(chunk.get(LocalPos::new(1, 0, 0)), BlockState(1));
+        assert_eq!(chunk.blocks().palette().len(), 3);
+    }
+
+    /// Places a block into the chunk above, only over air
+    struct Roof(BlockState);
+
+    impl GenerationPass for Roof {
+        fn name(&self) -> &str {
+            "roof"
+        }
+
+        fn apply(&self, chunk: &mut Chunk, context: &mut PassContext) {
+            let above = BlockPos::from_parts(chunk.pos, LocalPos::new(3, 15, 3)).offset(0, 1, 0);
+            context.outside.push((above, self.0));
+        }
+
+        fn replaces(&self, current: BlockState, _: BlockState) -> bool {
+            current.is_air()
+        }
+
+        fn reach(&self) -> Reach {
+            Reach { above: 1, ..Reach::default() }
+        }
+    }
+
+    #[test]
+    fn outside_blocks_reach_their_chunk_in_any_order() {
+        let generator = || WorldGenerator::new()
+            .with_pass(Stamp("stamp", BlockState(2)))
+            .with_pass(Roof(BlockState(5)));
+        let (below, above) = (ChunkPos::new(0, 0, 0), ChunkPos::new(0, 1, 0));
+        let roof = LocalPos::new(3, 0, 3);
+
+        // The chunk above comes later and picks the block up from the buffer
+        let first = generator();
+        first.generate(below);
+        assert_eq!(first.pending_chunks(), 1);
+        let chunk = first.generate(above);
+        assert_eq!(chunk.get(roof), BlockState(5));
+        // Only the block for the chunk above that one is left
+        assert_eq!(first.pending_chunks(), 1);
+
+        // The chunk above is already in the world
+        let second = generator();
+        let mut world = World::new();
+        second.generate_into(&mut world, above);
+        second.generate_into(&mut world, below);
+        assert_eq!(world.get_chunk(above).unwrap().get(roof), BlockState(5));
+        assert!(world.get_chunk(above).unwrap().iter().eq(chunk.iter()));
+
+        // Blocks only replace what the pass allows
+        let third = generator();
+        let mut world = World::new();
+        third.generate_into(&mut world, above);
+        world.set_block(BlockPos::from_parts(above, roof), BlockState(7));
+        third.generate_into(&mut world, below);
+        assert_eq!(world.get_block(BlockPos::from_parts(above, roof)), Some(BlockState(7)));
+    }
+
+    #[test]
+    fn saved_neighbors_place_their_outside_blocks() {
+        let generator = || WorldGenerator::new()
+            .with_pass(Stamp("stamp", BlockState(2)))
+            .with_pass(Roof(BlockState(5)));
+        let (below, above) = (ChunkPos::new(0, 0, 0), ChunkPos::new(0, 1, 0));
+        let roof = LocalPos::new(3, 0, 3);
+        let expected = {
+            let generator = generator();
+            generator.generate(below);
+            generator.generate(above)
+        };
+
+        // The chunk below was generated and saved in an earlier run, the
+        // block it placed above went with that run
+        let saved = generator().generate(below);
+        let generator = generator();
+        let mut world = World::new();
+        world.insert_chunk(saved);
+        generator.generate_into(&mut world, above);
+        assert_eq!(world.get_chunk(above).unwrap().get(roof), BlockState(5));
+        assert!(world.get_chunk(above).unwrap().iter().eq(expected.iter()));
+    }
+}
@@ -0,0 +1,301 @@
+use crate::game::{biome::{Biome, BiomeId, BiomeRegistry}, block::BlockState, chunk::{Chunk, CHUNK_SIZE}, pos::{ChunkPos, LocalPos}, registry::BlockRegistry};
+
+use super::{biome::{BiomeGrid, BiomeSource}, noise::{Fbm, derive_seed}, pipeline::{GenerationPass, PassContext}};
+
+/// Blocks the terrain is built from, the surface comes from the biomes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerrainBlocks {
+    pub stone: BlockState,
+    pub water: BlockState,
+}
+
+impl TerrainBlocks {
+    /// Default states of `stone` and `water`
+    pub fn from_registry(registry: &BlockRegistry) -> Result<Self, String> {
+        let state = |name: &str| registry.get_by_name(name)
+            .map(|block| block.default_state)
+            .ok_or_else(|| format!("world generation needs the block `{}`", name));
+        Ok(Self {
+            stone: state("stone")?,
+            water: state("water")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TerrainSettings {
+    /// Water fills every column up to this height
+    pub sea_level: i32,
+    /// Surface offset from the sea level by continentalness, as points of a
+    /// piecewise linear curve sorted by continentalness
+    pub continents: Vec<(f64, f64)>,
+    /// Height of the hills where erosion is lowest and highest
+    pub hills: (f64, f64),
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self {
+            sea_level: 0,
+            continents: vec![(-1.0, -32.0), (-0.35, -14.0), (-0.1, -2.0), (0.05, 3.0), (0.4, 12.0), (1.0, 36.0)],
+            hills: (28.0, 3.0),
+        }
+    }
+}
+
+/// Value of a piecewise linear curve, constant past its ends
+fn spline(points: &[(f64, f64)], x: f64) -> f64 {
+    let Some(i) = points.iter().position(|(px, _)| x < *px) else {
+        return points.last().map_or(0.0, |(_, y)| *y);
+    };
+    if i == 0 {
+        return points[0].1;
+    }
+    let ((x0, y0), (x1, y1)) = (points[i - 1], points[i]);
+    y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+}
+
+/// Shapes the land from layered noise and covers it with the blocks of each
+/// column's biome. Every block only depends on the seed and its position, so
+/// chunks can be generated in any order.
+pub struct TerrainGenerator {
+    settings: TerrainSettings,
+    blocks: TerrainBlocks,
+    biomes: BiomeRegistry,
+    biome_source: BiomeSource,
+    /// Ocean to inland, large scale
+    continentalness: Fbm,
+    /// Flat to mountainous, scales the hills
+    erosion: Fbm,
+    hills: Fbm,
+}
+
+impl TerrainGenerator {
+    pub fn new(seed: u64, blocks: TerrainBlocks, biomes: BiomeRegistry) -> Self {
+        Self::with_settings(seed, blocks, biomes, TerrainSettings::default())
+    }
+
+    pub fn with_settings(seed: u64, blocks: TerrainBlocks, biomes: BiomeRegistry, settings: TerrainSettings) -> Self {
+        Self {
+            settings,
+            blocks,
+            biome_source: BiomeSource::new(seed, &biomes),
+            biomes,
+            continentalness: Fbm::new(derive_seed(seed, "continentalness"), 5, 1.0 / 768.0, 0.5),
+            erosion: Fbm::new(derive_seed(seed, "erosion"), 4, 1.0 / 384.0, 0.5),
+            hills: Fbm::new(derive_seed(seed, "hills"), 5, 1.0 / 96.0, 0.5),
+        }
+    }
+
+    pub fn settings(&self) -> &TerrainSettings {
+        &self.settings
+    }
+
+    pub fn blocks(&self) -> &TerrainBlocks {
+        &self.blocks
+    }
+
+    pub fn biomes(&self) -> &BiomeRegistry {
+        &self.biomes
+    }
+
+    pub fn biome_source(&self) -> &BiomeSource {
+        &self.biome_source
+    }
+
+    /// Y of the topmost solid block of a column
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        self.blended_height(x, z, &self.biome_source.grid((x, z), (x, z)))
+    }
+
+    /// Height of a column with the height offset and scale of the biomes
+    /// around it blended in
+    fn blended_height(&self, x: i32, z: i32, grid: &BiomeGrid) -> i32 {
+        let (offset, scale) = grid.weights(x, z).into_iter().fold((0.0, 0.0), |(offset, scale), (id, weight)| {
+            let biome = self.biome(id);
+            (offset + biome.height_offset * weight, scale + biome.height_scale * weight)
+        });
+
+        let (x, z) = (x as f64, z as f64);
+        let continent = spline(&self.settings.continents, self.continentalness.sample2(x, z));
+        let erosion = (self.erosion.sample2(x, z) * 0.5 + 0.5).clamp(0.0, 1.0);
+        let (rough, flat) = self.settings.hills;
+        let hills = self.hills.sample2(x, z) * (rough + (flat - rough) * erosion) * scale;
+        self.settings.sea_level + (continent + hills + offset).round() as i32
+    }
+
+    fn biome(&self, id: BiomeId) -> &Biome {
+        self.biomes.get(id).expect("the biome source only picks registered biomes")
+    }
+
+    /// Block at height `y` of a column in `biome` with its surface at `height`
+    fn block_at(&self, y: i32, height: i32, biome: &Biome) -> BlockState {
+        if y > height {
+            if y <= self.settings.sea_level { self.blocks.water } else { BlockState::AIR }
+        } else if y == height {
+            if height >= self.settings.sea_level { biome.surface } else { biome.underwater }
+        } else if y > height - 1 - biome.filler_depth as i32 {
+            biome.filler
+        } else {
+            self.blocks.stone
+        }
+    }
+
+    /// The chunk with only this pass applied
+    pub fn generate(&self, pos: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::new(pos);
+        self.apply(&mut chunk, &mut PassContext::new(pos));
+        chunk
+    }
+}
+
+impl GenerationPass for TerrainGenerator {
+    fn name(&self) -> &str {
+        "terrain"
+    }
+
+    /// Fills the chunk with the terrain and its biomes and records the height of every column
+    fn apply(&self, chunk: &mut Chunk, context: &mut PassContext) {
+        let origin = chunk.pos.origin();
+        let last = CHUNK_SIZE as i32 - 1;
+        let grid = self.biome_source.grid((origin.x, origin.z), (origin.x + last, origin.z + last));
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let (world_x, world_z) = (origin.x + x as i32, origin.z + z as i32);
+                let id = self.biome_source.biome_at(world_x, world_z);
+                chunk.set_biome(x, z, id);
+                let biome = self.biome(id);
+                let height = self.blended_height(world_x, world_z, &grid);
+                context.heights[z * CHUNK_SIZE + x] = height;
+                for y in 0..CHUNK_SIZE {
+                    let state = self.block_at(origin.y + y as i32, height, biome);
+                    if !state.is_air() {
+                        chunk.set(LocalPos::new(x, y, z), state);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::biome::Color;
+
+    use super::*;
+
+    const BLOCKS: TerrainBlocks = TerrainBlocks {
+        stone: BlockState(1),
+        water: BlockState(2),
+    };
+
+    /// Low grassland and raised desert, split by temperature
+    fn biomes() -> BiomeRegistry {
+        let biome = |name: &str, temperature: f64, surface: u32, filler: u32, height_offset: f64| Biome {
+            name: name.to_string(),
+            display_name: name.to_string(),
+            temperature,
+            humidity: 0.0,
+            surface: BlockState(surface),
+            underwater: BlockState(filler),
+            filler: BlockState(filler),
+            filler_depth: 3,
+            height_offset,
+            height_scale: 1.0,
+            tree_density: 0.0,
+            grass_color: Color::WHITE,
+            foliage_color: Color::WHITE,
+        };
+        BiomeRegistry::new(vec![biome("plains", -0.5, 3, 4, 0.0), biome("desert", 0.5, 5, 5, 16.0)])
+    }
+
+    fn generator(seed: u64) -> TerrainGenerator {
+        TerrainGenerator::new(seed, BLOCKS, biomes())
+    }
+
+    fn blocks(chunk: &Chunk) -> Vec<BlockState> {
+        chunk.iter().map(|(_, state)| state).collect()
+    }
+
+    #[test]
+    fn same_seed_same_chunks() {
+        let positions = [ChunkPos::new(0, 0, 0), ChunkPos::new(-3, -1, 7), ChunkPos::new(120, 1, -45)];
+        let generator = generator(42);
+        let first: Vec<_> = positions.iter().map(|pos| blocks(&generator.generate(*pos))).collect();
+
+        // Reversed, with a fresh generator and other chunks in between
+        let other = self::generator(42);
+        for (i, pos) in positions.iter().enumerate().rev() {
+            other.generate(pos.offset(1, 0, 0));
+            assert_eq!(blocks(&other.generate(*pos)), first[i], "{:?}", pos);
+        }
+
+        let different = self::generator(43);
+        assert!(positions.iter().zip(&first).any(|(pos, blocks_a)| blocks(&different.generate(*pos)) != *blocks_a));
+    }
+
+    #[test]
+    fn columns_are_layered() {
+        let generator = generator(7);
+        let sea_level = generator.settings().sea_level;
+        let (mut land, mut ocean) = (false, false);
+        for i in 0..400 {
+            let (x, z) = (i * 37 - 7000, i * -53 + 2000);
+            let height = generator.height_at(x, z);
+            let biome = generator.biome(generator.biome_source().biome_at(x, z));
+            land |= height > sea_level;
+            ocean |= height < sea_level;
+
+            let column: Vec<_> = (height - 6..=sea_level.max(height) + 2).map(|y| (y, generator.block_at(y, height, biome))).collect();
+            for (y, state) in column {
+                let expected = match y - height {
+                    1.. if y <= sea_level => BLOCKS.water,
+                    1.. => BlockState::AIR,
+                    0 if height >= sea_level => biome.surface,
+                    0 => biome.underwater,
+                    -3..=-1 => biome.filler,
+                    _ => BLOCKS.stone,
+                };
+                assert_eq!(state, expected, "y {} of a column at {}", y, height);
+            }
+        }
+        assert!(land && ocean);
+    }
+
+    #[test]
+    fn chunks_match_columns() {
+        let generator = generator(3);
+        let pos = ChunkPos::new(2, 0, -1);
+        let chunk = generator.generate(pos);
+        for (local, state) in chunk.iter() {
+            let block = pos.origin().offset(local.x as i32, local.y as i32, local.z as i32);
+            let biome = generator.biome_source().biome_at(block.x, block.z);
+            assert_eq!(chunk.biome(local.x as usize, local.z as usize), biome);
+            assert_eq!(state, generator.block_at(block.y, generator.height_at(block.x, block.z), generator.biome(biome)));
+        }
+    }
+
+    #[test]
+    fn heights_blend_across_biomes() {
+        let generator = generator(11);
+        let source = generator.biome_source();
+        let border = (-20_000..20_000)
+            .find(|&x| source.biome_at(x, 0) != source.biome_at(x + 1, 0))
+            .expect("a biome border");
+
+        // The desert is 16 blocks higher, blending spreads that over several
+        // columns instead of a cliff at the border
+        let heights: Vec<_> = (border - 16..=border + 16).map(|x| generator.height_at(x, 0)).collect();
+        let steepest = heights.windows(2).map(|pair| (pair[1] - pair[0]).abs()).max().unwrap();
+        assert!(steepest < 8, "step of {} in {:?}", steepest, heights);
+    }
+
+    #[test]
+    fn spline_interpolates() {
+        let points = [(-1.0, -10.0), (0.0, 0.0), (1.0, 20.0)];
+        assert_eq!(spline(&points, -2.0), -10.0);
+        assert_eq!(spline(&points, -0.5), -5.0);
+        assert_eq!(spline(&points, 0.25), 5.0);
+        assert_eq!(spline(&points, 3.0), 20.0);
+    }
+}